@@ -0,0 +1,383 @@
+/*
+
+    Reference-counted asset cache.
+
+    Textures are keyed by source file + glTF image index (+ role and sampler, which change the
+    uploaded format / sampler object), so materials referencing the same image share one GPU
+    texture. Whole models are keyed by their source file, loading a file twice hands out the
+    same meshes and materials again.
+
+    Assets are shared through `Arc`s: an entry whose only owner is the cache itself is unused
+    and gets dropped by `evict_unused`.
+
+    With texture streaming enabled, glTF images start as placeholders that `update_streaming`
+    replaces (in the cache and in every cached material) as their resident levels change.
+
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use nalgebra_glm as glm;
+
+use crate::camera::Camera;
+use crate::model::Model;
+use crate::model::load_model;
+use crate::shader_library::ShaderLibrary;
+use crate::streaming::StreamSource;
+use crate::streaming::TextureStreamer;
+use crate::streaming::create_placeholder_texture;
+use crate::texture::SamplerKey;
+use crate::texture::Texture;
+use crate::texture::TextureLoader;
+use crate::texture::TextureRole;
+use crate::texture::TextureSettings;
+use crate::texture::create_default_texture;
+
+
+///// TEXTURE ASSET KEY STRUCTURE //////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TextureAssetKey {
+    pub path       : PathBuf,
+    pub image_index: usize,
+    pub role       : TextureRole,
+    pub sampler    : SamplerKey,
+}
+///// TEXTURE ASSET KEY STRUCTURE //////////////////////////////////////////////////////////////////
+
+
+///// ASSET STATISTICS STRUCTURE ///////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AssetStats {
+    pub models       : usize,
+    pub meshes       : usize,
+    pub materials    : usize,
+    pub textures     : usize,
+    pub texture_bytes: u64,
+    pub buffer_bytes : u64,
+}
+
+impl fmt::Display for AssetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} models, {} meshes, {} materials, {} textures ({:.2} MiB textures, {:.2} MiB buffers)",
+               self.models, self.meshes, self.materials, self.textures,
+               self.texture_bytes as f64 / (1024.0 * 1024.0),
+               self.buffer_bytes  as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// GPU memory of a texture including its mip chain (estimate for block compressed formats).
+pub fn texture_memory(texture: &wgpu::Texture) -> u64 {
+    let format               = texture.format();
+    let (block_w, block_h)   = format.block_dimensions();
+    let block_size           = format.block_copy_size(None).unwrap_or(4) as u64;
+
+    (0..texture.mip_level_count()).map(|level| {
+        let size   = texture.size().mip_level_size(level, texture.dimension());
+        let blocks = size.width.div_ceil(block_w) as u64 * size.height.div_ceil(block_h) as u64;
+        blocks * block_size * size.depth_or_array_layers as u64
+    }).sum()
+}
+///// ASSET STATISTICS STRUCTURE ///////////////////////////////////////////////////////////////////
+
+
+///// ASSET CACHE STRUCTURE ////////////////////////////////////////////////////////////////////////
+pub struct AssetCache {
+    pub loader     : TextureLoader,
+    default_texture: Option<Arc<Texture>>,
+    textures       : HashMap<TextureAssetKey, Arc<Texture>>,
+    models         : HashMap<PathBuf, Model>,
+    streamer       : Option<TextureStreamer>,
+}
+
+impl AssetCache {
+    pub fn new(device: &wgpu::Device, shaders: &ShaderLibrary, settings: TextureSettings) -> Self {
+        Self {
+            loader         : TextureLoader::new(device, shaders, settings),
+            default_texture: None,
+            textures       : HashMap::new(),
+            models         : HashMap::new(),
+            streamer       : settings.streaming.map(|streaming| TextureStreamer::new(streaming, device.features())),
+        }
+    }
+
+    /// Cache keys use the canonical path, so "./a.glb" and "a.glb" are the same asset.
+    fn canonical_path(path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    /// Loads a glTF file once; later calls share its meshes, materials and textures.
+    pub fn load_model(&mut self,
+                      path                      : &str,
+                      device                    : &wgpu::Device,
+                      queue                     : &wgpu::Queue,
+                      material_bind_group_layout: &wgpu::BindGroupLayout) -> anyhow::Result<Model> {
+        let key = Self::canonical_path(Path::new(path));
+        if let Some(model) = self.models.get(&key) {
+            return Ok(model.clone());
+        }
+
+        let model = load_model(path, device, queue, material_bind_group_layout, self)?;
+        self.models.insert(key, model.clone());
+
+        Ok(model)
+    }
+
+    /// White 1x1 texture for empty material slots (shared by all materials).
+    pub fn default_texture(&mut self,
+                           device: &wgpu::Device,
+                           queue : &wgpu::Queue) -> anyhow::Result<Arc<Texture>> {
+        if let Some(texture) = &self.default_texture {
+            return Ok(texture.clone());
+        }
+
+        let texture = Arc::new(create_default_texture(device, queue)?);
+        self.default_texture = Some(texture.clone());
+
+        Ok(texture)
+    }
+
+    fn texture_key(path: &str, image_index: usize, role: TextureRole, sampler: &SamplerKey) -> TextureAssetKey {
+        TextureAssetKey {
+            path       : Self::canonical_path(Path::new(path)),
+            image_index,
+            role,
+            sampler    : *sampler,
+        }
+    }
+
+    /// Returns the cached texture for `image_index` of `path`, or calls `load` to create it.
+    pub fn texture(&mut self,
+                   path       : &str,
+                   image_index: usize,
+                   role       : TextureRole,
+                   sampler    : &SamplerKey,
+                   load       : impl FnOnce(&TextureLoader) -> anyhow::Result<Texture>) -> anyhow::Result<Arc<Texture>> {
+        let key = Self::texture_key(path, image_index, role, sampler);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let texture = Arc::new(load(&self.loader)?);
+        self.textures.insert(key, texture.clone());
+
+        Ok(texture)
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.streamer.is_some()
+    }
+
+    pub fn streamer(&self) -> Option<&TextureStreamer> {
+        self.streamer.as_ref()
+    }
+
+    /// Like `texture`, but hands out a placeholder and streams the image in (see `streaming.rs`).
+    /// `source` is only called for images that are not cached yet; its errors are returned
+    /// right away so callers can fall back to another image.
+    #[allow(clippy::too_many_arguments)]
+    pub fn streamed_texture(&mut self,
+                            path       : &str,
+                            image_index: usize,
+                            role       : TextureRole,
+                            sampler    : &SamplerKey,
+                            device     : &wgpu::Device,
+                            queue      : &wgpu::Queue,
+                            label      : &str,
+                            source     : impl FnOnce() -> anyhow::Result<StreamSource>) -> anyhow::Result<Arc<Texture>> {
+        let key = Self::texture_key(path, image_index, role, sampler);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+        let Some(streamer) = self.streamer.as_mut() else {
+            anyhow::bail!("Texture streaming is disabled");
+        };
+
+        let source  = source()?;
+        let texture = Arc::new(create_placeholder_texture(device, queue, &self.loader, role, sampler, label)?);
+        streamer.request(key.clone(), source, *sampler, label.to_string());
+        self.textures.insert(key, texture.clone());
+
+        Ok(texture)
+    }
+
+    /// Uploads finished decodes and moves streamed textures towards the detail their on-screen
+    /// size needs. `drawn` are the models of this frame with their model matrices.
+    pub fn update_streaming(&mut self,
+                            device         : &wgpu::Device,
+                            queue          : &wgpu::Queue,
+                            camera         : &Camera,
+                            viewport_height: u32,
+                            drawn          : &[(&Model, glm::Mat4)]) {
+        let Some(streamer) = self.streamer.as_mut() else {
+            return;
+        };
+
+        // ---> Largest on-screen size (pixels) of every drawn texture:
+        let keys = self.textures.iter()
+                                .map(|(key, texture)| (Arc::as_ptr(texture), key))
+                                .collect::<HashMap<_, _>>();
+        let pixels_per_unit = viewport_height as f32 / (camera.fovy * 0.5).tan();
+        let mut coverage    = HashMap::new();
+        for (model, matrix) in drawn {
+            for (index, mesh) in model.meshes.iter().enumerate() {
+                let Some(material) = model.materials.get(model.material_index(index)) else {
+                    continue;
+                };
+                let center   = (matrix * mesh.center.push(1.0)).xyz();
+                let scale    = glm::length(&matrix.column(0).xyz())
+                                   .max(glm::length(&matrix.column(1).xyz()))
+                                   .max(glm::length(&matrix.column(2).xyz()));
+                let radius   = mesh.radius * scale;
+                let distance = glm::distance(&camera.eye, &center);
+                let pixels   = if distance <= radius { f32::MAX } else { radius * pixels_per_unit / distance };
+
+                for texture in material.bindings.textures().iter() {
+                    if let Some(key) = keys.get(&Arc::as_ptr(texture)) {
+                        let entry = coverage.entry((*key).clone()).or_insert(0.0f32);
+                        *entry = entry.max(pixels);
+                    }
+                }
+            }
+        }
+
+        // ---> Swap the new textures in, everywhere the old ones are used:
+        for (key, texture) in streamer.update(device, queue, &self.loader, &coverage) {
+            let texture = Arc::new(texture);
+            let Some(old) = self.textures.insert(key.clone(), texture.clone()) else {
+                continue;
+            };
+            // ---> Drawn models too, their nodes may use material copies (Material::edited):
+            for model in self.models.values().chain(drawn.iter().map(|(model, _)| *model)) {
+                for material in &model.materials {
+                    material.bindings.replace_texture(device, &old, &texture, &material.name);
+                }
+            }
+        }
+    }
+
+    /// Drops every asset that is only referenced by the cache. Returns what was freed.
+    pub fn evict_unused(&mut self) -> AssetStats {
+        let before = self.stats();
+
+        // ---> Models first, this releases their materials and with that their textures:
+        self.models.retain(|_, model| {
+            model.meshes.iter().any(|mesh| Arc::strong_count(mesh) > 1) ||
+            model.materials.iter().any(|material| Arc::strong_count(material) > 1)
+        });
+        let streamer = &mut self.streamer;
+        self.textures.retain(|key, texture| {
+            let used = Arc::strong_count(texture) > 1;
+            if let (false, Some(streamer)) = (used, streamer.as_mut()) {
+                streamer.forget(key);
+            }
+            used
+        });
+
+        let after = self.stats();
+        AssetStats {
+            models       : before.models        - after.models,
+            meshes       : before.meshes        - after.meshes,
+            materials    : before.materials     - after.materials,
+            textures     : before.textures      - after.textures,
+            texture_bytes: before.texture_bytes - after.texture_bytes,
+            buffer_bytes : before.buffer_bytes  - after.buffer_bytes,
+        }
+    }
+
+    pub fn stats(&self) -> AssetStats {
+        let mut stats = AssetStats {
+            models  : self.models.len(),
+            textures: self.textures.len(),
+            ..Default::default()
+        };
+
+        for model in self.models.values() {
+            stats.meshes       += model.meshes.len();
+            stats.materials    += model.materials.len();
+            stats.buffer_bytes += model.meshes.iter()
+                                              .map(|mesh| mesh.vertex_buffer.size() + mesh.index_buffer.size())
+                                              .sum::<u64>();
+        }
+
+        stats.texture_bytes = self.textures.values()
+                                           .chain(self.default_texture.iter())
+                                           .map(|texture| texture_memory(&texture.texture))
+                                           .sum();
+
+        stats
+    }
+}
+///// ASSET CACHE STRUCTURE ////////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::GPU;
+    use crate::state::State;
+
+    const SCENE: &str = "tests/golden/scenes/cube.gltf";
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn models_and_textures_are_shared_and_evicted() {
+        let gpu = GPU::for_tests(1, 1);
+        let layout     = State::create_material_bind_group(&gpu);
+        let mut assets = AssetCache::new(&gpu.device, &gpu.shaders, TextureSettings::default());
+
+        let first  = assets.load_model(SCENE, &gpu.device, &gpu.queue, &layout).unwrap();
+        let second = assets.load_model(&format!("./{}", SCENE), &gpu.device, &gpu.queue, &layout).unwrap();
+
+        assert!(Arc::ptr_eq(&first.meshes[0],    &second.meshes[0]));
+        assert!(Arc::ptr_eq(&first.materials[0], &second.materials[0]));
+
+        let stats = assets.stats();
+        assert_eq!((stats.models, stats.meshes, stats.materials, stats.textures), (1, 1, 1, 1));
+        assert!(stats.texture_bytes > 0 && stats.buffer_bytes > 0);
+
+        // ---> Still in use, nothing may be evicted:
+        drop(first);
+        assert_eq!(assets.evict_unused(), AssetStats::default());
+
+        drop(second);
+        let freed = assets.evict_unused();
+        assert_eq!((freed.models, freed.textures), (1, 1));
+        assert_eq!(assets.stats().textures, 0);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn streamed_textures_start_as_placeholders() {
+        let gpu = GPU::for_tests(1, 1);
+        let settings   = TextureSettings { streaming: Some(Default::default()), ..Default::default() };
+        let mut assets = AssetCache::new(&gpu.device, &gpu.shaders, settings);
+        let sampler    = SamplerKey::default();
+
+        let mut png = Vec::new();
+        image::RgbaImage::new(256, 256).write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+
+        let placeholder = assets.streamed_texture("streamed.png", 0, TextureRole::BaseColor, &sampler,
+                                                  &gpu.device, &gpu.queue, "streamed.png",
+                                                  || Ok(StreamSource::Encoded(png))).unwrap();
+        assert_eq!(placeholder.texture.width(), 1);
+
+        // ---> Not drawn, so only the small placeholder levels get uploaded once decoding is done:
+        let camera = crate::camera::CameraState::new(&gpu).camera;
+        for _ in 0..500 {
+            assets.update_streaming(&gpu.device, &gpu.queue, &camera, 1, &[]);
+            if assets.streamer().unwrap().pending() == 0 && assets.textures.values().all(|t| t.texture.width() > 1) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let texture = assets.textures.values().next().unwrap();
+        assert_eq!((texture.texture.width(), texture.texture.mip_level_count()), (64, 7));
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,443 @@
+/*
+
+    Runtime texture atlas (UI, sprites, decals).
+
+    Many small images are packed into one texture with a skyline packer. Every image gets a
+    border of `padding` texels filled with copies of its edge texels (bleeding), so bilinear
+    filtering at region edges never picks up a neighbour.
+
+    Images are inserted incrementally. When the packer runs out of space, all images are packed
+    again from scratch (largest first), growing the atlas up to `max_size` if necessary. That
+    moves regions, so `generation` changes and region UVs have to be fetched again. The CPU
+    copy is uploaded by `upload`, which returns a plain `Texture`.
+
+*/
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::texture::SamplerKey;
+use crate::texture::Texture;
+use crate::texture::TextureLoader;
+use crate::texture::TextureOptions;
+use crate::texture::create_texture_with_mipmaps;
+
+
+///// SKYLINE PACKER STRUCTURE /////////////////////////////////////////////////////////////////////
+/// Bottom-left skyline rectangle packer. The skyline is a list of horizontal segments
+/// `(x, y, width)` covering the full width, everything below a segment is taken.
+#[derive(Debug, Clone)]
+pub struct SkylinePacker {
+    width  : u32,
+    height : u32,
+    skyline: Vec<(u32, u32, u32)>,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, skyline: vec![(0, 0, width)] }
+    }
+
+    /// Returns the top left corner of the placed rectangle, `None` if it does not fit.
+    pub fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        // ---> Lowest position first, then the narrowest segment (less wasted space):
+        let mut best: Option<(usize, u32, u32)> = None;
+        for index in 0..self.skyline.len() {
+            let Some(y) = self.fit(index, width, height) else {
+                continue;
+            };
+            let segment_width = self.skyline[index].2;
+            if best.is_none_or(|(best_index, best_y, _)| {
+                y < best_y || (y == best_y && segment_width < self.skyline[best_index].2)
+            }) {
+                best = Some((index, y, self.skyline[index].0));
+            }
+        }
+
+        let (index, y, x) = best?;
+        self.add_segment(index, x, y + height, width);
+        Some((x, y))
+    }
+
+    /// Height a rectangle starting at segment `index` would rest on.
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.skyline[index].0;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y         = 0;
+        let mut remaining = width as i64;
+        for &(_, segment_y, segment_width) in &self.skyline[index..] {
+            if remaining <= 0 {
+                break;
+            }
+            y          = y.max(segment_y);
+            remaining -= segment_width as i64;
+        }
+
+        (y + height <= self.height).then_some(y)
+    }
+
+    fn add_segment(&mut self, index: usize, x: u32, y: u32, width: u32) {
+        self.skyline.insert(index, (x, y, width));
+
+        // ---> Shrink (or remove) the segments now covered by the new one:
+        let right = x + width;
+        while index + 1 < self.skyline.len() {
+            let (next_x, next_y, next_width) = self.skyline[index + 1];
+            if next_x >= right {
+                break;
+            }
+            let cut = right - next_x;
+            if cut >= next_width {
+                self.skyline.remove(index + 1);
+            } else {
+                self.skyline[index + 1] = (right, next_y, next_width - cut);
+                break;
+            }
+        }
+
+        // ---> Merge neighbours at the same height:
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].1 == self.skyline[i + 1].1 {
+                self.skyline[i].2 += self.skyline[i + 1].2;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+///// SKYLINE PACKER STRUCTURE /////////////////////////////////////////////////////////////////////
+
+
+///// ATLAS REGION STRUCTURE ///////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AtlasId(u32);
+
+/// Where an image ended up: texel rectangle (without padding) and its UV range in the atlas.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    pub x     : u32,
+    pub y     : u32,
+    pub width : u32,
+    pub height: u32,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+impl AtlasRegion {
+    fn new(x: u32, y: u32, width: u32, height: u32, atlas_size: u32) -> Self {
+        let size = atlas_size as f32;
+        Self {
+            x, y, width, height,
+            uv_min: [x as f32 / size, y as f32 / size],
+            uv_max: [(x + width) as f32 / size, (y + height) as f32 / size],
+        }
+    }
+
+    /// Maps a UV of the original image (0..1) into the atlas.
+    pub fn remap_uv(&self, uv: [f32; 2]) -> [f32; 2] {
+        [
+            self.uv_min[0] + uv[0] * (self.uv_max[0] - self.uv_min[0]),
+            self.uv_min[1] + uv[1] * (self.uv_max[1] - self.uv_min[1]),
+        ]
+    }
+}
+///// ATLAS REGION STRUCTURE ///////////////////////////////////////////////////////////////////////
+
+
+///// TEXTURE ATLAS STRUCTURE //////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasSettings {
+    pub initial_size: u32,  // Square, grows by doubling
+    pub max_size    : u32,
+    pub padding     : u32,  // Bled texels around every image
+    pub options     : TextureOptions,
+}
+
+impl Default for AtlasSettings {
+    /// sRGB, clamped and without mipmaps (mip levels would mix regions once they are smaller
+    /// than the padding).
+    fn default() -> Self {
+        Self {
+            initial_size: 256,
+            max_size    : 4096,
+            padding     : 2,
+            options     : TextureOptions { srgb: true, generate_mipmaps: false, sampler: SamplerKey::clamped() },
+        }
+    }
+}
+
+pub struct TextureAtlas {
+    pub settings: AtlasSettings,
+    size        : u32,
+    packer      : SkylinePacker,
+    images      : BTreeMap<AtlasId, image::RgbaImage>,  // Kept for repacking
+    regions     : BTreeMap<AtlasId, AtlasRegion>,
+    pixels      : image::RgbaImage,
+    next_id     : u32,
+    generation  : u64,
+    texture     : Option<Arc<Texture>>,  // None = the CPU copy changed since the last upload
+}
+
+impl TextureAtlas {
+    pub fn new(settings: AtlasSettings) -> Self {
+        let size = settings.initial_size.min(settings.max_size);
+        Self {
+            settings,
+            size,
+            packer    : SkylinePacker::new(size, size),
+            images    : BTreeMap::new(),
+            regions   : BTreeMap::new(),
+            pixels    : image::RgbaImage::new(size, size),
+            next_id   : 0,
+            generation: 0,
+            texture   : None,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// Changes whenever regions move (repacking), cached UVs are stale after that.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn region(&self, id: AtlasId) -> Option<AtlasRegion> {
+        self.regions.get(&id).copied()
+    }
+
+    pub fn pixels(&self) -> &image::RgbaImage {
+        &self.pixels
+    }
+
+    /// Adds an image; repacks (and grows) the atlas if it does not fit anymore.
+    pub fn insert(&mut self, image: image::RgbaImage) -> anyhow::Result<AtlasId> {
+        if image.width() == 0 || image.height() == 0 {
+            anyhow::bail!("Cannot add an empty image ({}x{}) to the atlas", image.width(), image.height());
+        }
+
+        let padded = (image.width() + 2 * self.settings.padding, image.height() + 2 * self.settings.padding);
+        if padded.0 > self.settings.max_size || padded.1 > self.settings.max_size {
+            anyhow::bail!("Image of {}x{} is larger than the atlas ({}x{} at most)",
+                          image.width(), image.height(), self.settings.max_size, self.settings.max_size);
+        }
+
+        let id = AtlasId(self.next_id);
+        self.next_id += 1;
+
+        // ---> Incremental, the other regions stay where they are:
+        if let Some((x, y)) = self.packer.insert(padded.0, padded.1) {
+            self.place(id, &image, x, y);
+            self.images.insert(id, image);
+            self.texture = None;
+            return Ok(id);
+        }
+
+        self.images.insert(id, image);
+        if let Err(e) = self.repack() {
+            self.images.remove(&id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// Removes an image. Its space is reclaimed by the next repack.
+    pub fn remove(&mut self, id: AtlasId) -> bool {
+        self.regions.remove(&id);
+        self.images.remove(&id).is_some()
+    }
+
+    /// Packs every image again (largest first), doubling the atlas size until they fit.
+    pub fn repack(&mut self) -> anyhow::Result<()> {
+        let padding = self.settings.padding;
+        let mut order = self.images.keys().copied().collect::<Vec<_>>();
+        order.sort_by_key(|id| {
+            let image = &self.images[id];
+            std::cmp::Reverse((image.height(), image.width()))
+        });
+
+        let mut size = self.settings.initial_size.min(self.settings.max_size);
+        let positions = loop {
+            let mut packer = SkylinePacker::new(size, size);
+            let positions  = order.iter()
+                                  .map(|id| {
+                                      let image = &self.images[id];
+                                      packer.insert(image.width() + 2 * padding, image.height() + 2 * padding)
+                                  })
+                                  .collect::<Option<Vec<_>>>();
+            match positions {
+                Some(positions)                         => break (packer, positions),
+                None if size < self.settings.max_size   => size = (size * 2).min(self.settings.max_size),
+                None => anyhow::bail!("{} images do not fit into a {}x{} atlas", order.len(), size, size),
+            }
+        };
+
+        let (packer, positions) = positions;
+        self.size       = size;
+        self.packer     = packer;
+        self.pixels     = image::RgbaImage::new(size, size);
+        self.regions.clear();
+        for (id, (x, y)) in order.into_iter().zip(positions) {
+            let image = self.images.remove(&id).unwrap();
+            self.place(id, &image, x, y);
+            self.images.insert(id, image);
+        }
+        self.generation += 1;
+        self.texture     = None;
+
+        Ok(())
+    }
+
+    /// Copies `image` to its padded rectangle at `(x, y)`; the padding repeats the edge texels.
+    fn place(&mut self, id: AtlasId, image: &image::RgbaImage, x: u32, y: u32) {
+        let padding = self.settings.padding;
+        for dy in 0..image.height() + 2 * padding {
+            for dx in 0..image.width() + 2 * padding {
+                let source_x = dx.saturating_sub(padding).min(image.width()  - 1);
+                let source_y = dy.saturating_sub(padding).min(image.height() - 1);
+                self.pixels.put_pixel(x + dx, y + dy, *image.get_pixel(source_x, source_y));
+            }
+        }
+
+        let region = AtlasRegion::new(x + padding, y + padding, image.width(), image.height(), self.size);
+        self.regions.insert(id, region);
+    }
+
+    /// Texture holding the current atlas. A new texture is created when the atlas changed since
+    /// the last call, otherwise the previous one is returned.
+    pub fn upload(&mut self,
+                  device: &wgpu::Device,
+                  queue : &wgpu::Queue,
+                  loader: &TextureLoader) -> Arc<Texture> {
+        if let Some(texture) = &self.texture {
+            return texture.clone();
+        }
+
+        let options = &self.settings.options;
+        let format  = if options.srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+        let sampler = SamplerKey { mipmapped: options.generate_mipmaps && options.sampler.mipmapped, ..options.sampler };
+        let texture = Arc::new(create_texture_with_mipmaps(device, queue, loader, &sampler, &self.pixels,
+                                                           self.size, self.size, format, Some("Texture Atlas")));
+        self.texture = Some(texture.clone());
+
+        texture
+    }
+}
+///// TEXTURE ATLAS STRUCTURE //////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> image::RgbaImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba([value, value, value, 255]))
+    }
+
+    fn overlaps(a: &AtlasRegion, b: &AtlasRegion, padding: u32) -> bool {
+        a.x < b.x + b.width + 2 * padding && b.x < a.x + a.width + 2 * padding &&
+        a.y < b.y + b.height + 2 * padding && b.y < a.y + a.height + 2 * padding
+    }
+
+    #[test]
+    fn packer_places_rectangles_without_overlap() {
+        let mut packer = SkylinePacker::new(64, 64);
+        let mut placed = Vec::new();
+        for (width, height) in [(32, 16), (16, 32), (32, 32), (16, 16), (8, 8), (24, 8)] {
+            let (x, y) = packer.insert(width, height).unwrap();
+            assert!(x + width <= 64 && y + height <= 64);
+            for &(other_x, other_y, other_width, other_height) in &placed {
+                assert!(x >= other_x + other_width  || other_x >= x + width ||
+                        y >= other_y + other_height || other_y >= y + height);
+            }
+            placed.push((x, y, width, height));
+        }
+        assert_eq!(packer.insert(64, 64), None);
+    }
+
+    #[test]
+    fn padding_repeats_edge_texels() {
+        let mut atlas = TextureAtlas::new(AtlasSettings { initial_size: 16, ..Default::default() });
+        let mut image = solid(2, 2, 10);
+        image.put_pixel(1, 1, image::Rgba([200, 200, 200, 255]));
+        assert!(atlas.is_empty());
+
+        let id     = atlas.insert(image).unwrap();
+        let region = atlas.region(id).unwrap();
+        assert_eq!((region.x, region.y), (2, 2));
+        assert_eq!(atlas.pixels().get_pixel(0, 0)[0], 10);   // Corner of the padding
+        assert_eq!(atlas.pixels().get_pixel(5, 5)[0], 200);  // Bled from texel (1, 1)
+        assert_eq!(atlas.pixels().get_pixel(5, 0)[0], 10);   // Above texel (1, 0)
+
+        assert_eq!(region.uv_min, [2.0 / 16.0, 2.0 / 16.0]);
+        assert_eq!(region.remap_uv([1.0, 0.5]), [4.0 / 16.0, 3.0 / 16.0]);
+    }
+
+    #[test]
+    fn full_atlas_repacks_and_grows() {
+        let settings  = AtlasSettings { initial_size: 32, max_size: 64, padding: 1, ..Default::default() };
+        let mut atlas = TextureAtlas::new(settings);
+
+        let small = atlas.insert(solid(6, 6, 1)).unwrap();
+        let ids   = (0..3).map(|_| atlas.insert(solid(14, 14, 2)).unwrap()).collect::<Vec<_>>();
+        assert_eq!((atlas.size(), atlas.generation()), (32, 0));
+
+        // ---> Does not fit next to the others anymore:
+        let large = atlas.insert(solid(30, 30, 3)).unwrap();
+        assert_eq!((atlas.size(), atlas.generation()), (64, 1));
+
+        let regions = [small, large].iter().chain(&ids).map(|id| atlas.region(*id).unwrap()).collect::<Vec<_>>();
+        for (i, a) in regions.iter().enumerate() {
+            for b in &regions[i + 1..] {
+                assert!(!overlaps(a, b, 1), "{:?} overlaps {:?}", a, b);
+            }
+        }
+        let region = atlas.region(large).unwrap();
+        assert_eq!(atlas.pixels().get_pixel(region.x, region.y)[0], 3);
+
+        // ---> Too large for the maximum size, nothing changes:
+        assert!(atlas.insert(solid(64, 64, 4)).is_err());
+        assert!(atlas.insert(solid(40, 40, 4)).is_err());
+        assert_eq!((atlas.len(), atlas.generation()), (5, 1));
+
+        // ---> Empty images have nothing to bleed, removed ones are left out of the next repack:
+        assert!(atlas.insert(solid(0, 4, 4)).is_err());
+        assert!(atlas.insert(solid(4, 0, 4)).is_err());
+        assert!(atlas.remove(large) && !atlas.remove(large));
+        assert_eq!((atlas.len(), atlas.region(large)), (4, None));
+        atlas.repack().unwrap();
+        assert_eq!((atlas.size(), atlas.generation()), (32, 2));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn upload_is_redone_only_after_changes() {
+        let gpu = crate::gpu::GPU::for_tests(1, 1);
+        let loader    = TextureLoader::new(&gpu.device, &gpu.shaders, Default::default());
+        let mut atlas = TextureAtlas::new(AtlasSettings { initial_size: 32, ..Default::default() });
+        atlas.insert(solid(4, 4, 1)).unwrap();
+
+        let first = atlas.upload(&gpu.device, &gpu.queue, &loader);
+        assert_eq!((first.texture.width(), first.texture.format()), (32, wgpu::TextureFormat::Rgba8UnormSrgb));
+        assert!(Arc::ptr_eq(&first, &atlas.upload(&gpu.device, &gpu.queue, &loader)));
+
+        atlas.insert(solid(4, 4, 2)).unwrap();
+        assert!(!Arc::ptr_eq(&first, &atlas.upload(&gpu.device, &gpu.queue, &loader)));
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,1269 @@
+/*
+
+    Basis Universal ETC1S transcoder for KTX2 files with BasisLZ supercompression (the usual
+    payload of glTF KHR_texture_basisu colour textures). The codebooks and slices are decoded on
+    the CPU and every ETC1S block is rewritten into a format the adapter samples natively:
+
+        TEXTURE_COMPRESSION_ETC2 -> Etc2Rgb8 / Etc2Rgba8 (ETC1S is a subset of ETC1, lossless)
+        TEXTURE_COMPRESSION_BC   -> Bc1 / Bc7
+        TEXTURE_COMPRESSION_ASTC -> Astc 4x4
+        none of them             -> Rgba8 (decoded)
+
+    The endpoint colours of an ETC1S block lie on one line, so the BC1, BC7 and ASTC endpoints
+    (and the EAC alpha of Etc2Rgba8) are fitted once per endpoint and blocks only remap their
+    selectors. Colour and alpha have their own selectors, which BC7 mode 5 and dual plane ASTC
+    blocks both keep.
+
+    UASTC payloads are out of scope (they need the UASTC mode tables of the reference
+    transcoder): `Ktx2Image::parse` rejects them, glTF files then fall back to their PNG/JPEG.
+
+*/
+
+use std::collections::HashMap;
+
+
+/// ETC1 intensity modifiers, indexed by the linear ETC1S selector (darkest first).
+const ETC1_INTENSITIES: [[i32; 4]; 8] = [
+    [ -8,  -2,  2,   8], [ -17,  -5,  5,  17], [ -29,  -9,  9,  29], [ -42, -13, 13,  42],
+    [-60, -18, 18,  60], [ -80, -24, 24,  80], [-106, -33, 33, 106], [-183, -47, 47, 183],
+];
+
+/// Linear ETC1S selector -> ETC1 pixel index.
+const SELECTOR_TO_ETC1: [u8; 4] = [3, 2, 0, 1];
+
+/// Interpolation weights (of 64) of 2 bit BC7 and ASTC indices.
+const WEIGHTS_2BIT: [i32; 4] = [0, 21, 43, 64];
+
+// ---> ASTC 4x4 block modes with 2 bit weights, single and dual plane:
+const ASTC_BLOCK_MODE           : u32 = 66;
+const ASTC_BLOCK_MODE_DUAL_PLANE: u32 = 66 | 1 << 10;
+const ASTC_CEM_LDR_RGB_DIRECT   : u32 = 8;
+const ASTC_CEM_LDR_RGBA_DIRECT  : u32 = 12;
+
+/// ETC2 EAC alpha modifier tables.
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6,  -9, -15, 2, 5, 8, 14], [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5,  -8, -13, 1, 4, 7, 12], [-2, -4,  -6, -13, 1, 3, 5, 12],
+    [-3, -6,  -8, -12, 2, 5, 7, 11], [-3, -7,  -9, -11, 2, 6, 8, 10],
+    [-4, -7,  -8, -11, 3, 6, 7, 10], [-3, -5,  -8, -11, 2, 4, 7, 10],
+    [-2, -6,  -8, -10, 1, 5, 7,  9], [-2, -5,  -8, -10, 1, 4, 7,  9],
+    [-2, -4,  -8, -10, 1, 3, 7,  9], [-2, -5,  -7, -10, 1, 4, 6,  9],
+    [-3, -4,  -7, -10, 2, 3, 6,  9], [-1, -2,  -3, -10, 0, 1, 2,  9],
+    [-4, -6,  -8,  -9, 3, 5, 7,  8], [-3, -5,  -7,  -9, 2, 4, 6,  8],
+];
+
+// ---> Bitstream constants of the BasisLZ slice coder:
+const COLOR5_PAL0_PREV_HI              : u8  = 9;
+const COLOR5_PAL1_PREV_HI              : u8  = 21;
+const ENDPOINT_PRED_REPEAT_LAST_SYMBOL : u32 = 256;
+const ENDPOINT_PRED_MIN_REPEAT_COUNT   : u32 = 3;
+const ENDPOINT_PRED_COUNT_VLC_BITS     : u32 = 4;
+const SELECTOR_HISTORY_RLE_COUNT_THRESH: u32 = 3;
+const SELECTOR_HISTORY_RLE_COUNT_TOTAL : u32 = 64;
+const IMAGE_FLAG_P_FRAME               : u32 = 0x2;
+
+/// Order in which the code lengths of the code length alphabet are sent.
+const CODE_LENGTH_ORDER: [usize; 21] = [17, 18, 19, 20, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15, 16];
+
+
+///// BIT READER STRUCTURE /////////////////////////////////////////////////////////////////////////
+/// LSB-first bit reader; like the reference decoder, reading past the end yields zeros.
+struct BitReader<'a> {
+    data    : &'a [u8],
+    position: usize,  // In bits
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for bit in 0..count {
+            let byte = self.data.get(self.position / 8).copied().unwrap_or(0);
+            value   |= (((byte >> (self.position % 8)) & 1) as u32) << bit;
+            self.position += 1;
+        }
+        value
+    }
+
+    /// Variable length integer in chunks of `chunk_bits`, each followed by a "more" bit.
+    fn vlc(&mut self, chunk_bits: u32) -> anyhow::Result<u32> {
+        let (mut value, mut shift) = (0u32, 0u32);
+        loop {
+            let chunk = self.bits(chunk_bits + 1);
+            value    |= (chunk & ((1 << chunk_bits) - 1)) << shift;
+            shift    += chunk_bits;
+
+            if chunk & (1 << chunk_bits) == 0 {
+                return Ok(value);
+            }
+            if shift >= 32 {
+                anyhow::bail!("Invalid BasisLZ variable length integer");
+            }
+        }
+    }
+}
+///// BIT READER STRUCTURE /////////////////////////////////////////////////////////////////////////
+
+
+///// HUFFMAN TABLE STRUCTURE //////////////////////////////////////////////////////////////////////
+/// Canonical Huffman code (codes are sent most significant bit first).
+#[derive(Debug, Default)]
+struct HuffmanTable {
+    counts : [u16; 17],  // Codes per length
+    symbols: Vec<u16>,   // Ordered by (length, symbol)
+}
+
+impl HuffmanTable {
+    fn new(code_sizes: &[u8]) -> anyhow::Result<Self> {
+        let mut table = Self::default();
+        for &size in code_sizes {
+            if size > 16 {
+                anyhow::bail!("Invalid BasisLZ Huffman code size {}", size);
+            }
+            table.counts[size as usize] += 1;
+        }
+        table.counts[0] = 0;
+
+        for size in 1..=16 {
+            table.symbols.extend(code_sizes.iter()
+                                           .enumerate()
+                                           .filter(|(_, &code_size)| code_size == size)
+                                           .map(|(symbol, _)| symbol as u16));
+        }
+        Ok(table)
+    }
+
+    /// Reads a table as stored by the Basis Universal encoder (code lengths are themselves
+    /// Huffman coded, with run-length codes for zeros and repeats).
+    fn read(reader: &mut BitReader) -> anyhow::Result<Self> {
+        let symbol_count = reader.bits(14) as usize;
+        if symbol_count == 0 {
+            return Ok(Self::default());
+        }
+
+        let code_length_count = reader.bits(5) as usize;
+        if code_length_count == 0 || code_length_count > CODE_LENGTH_ORDER.len() {
+            anyhow::bail!("Invalid BasisLZ Huffman table header");
+        }
+        let mut code_length_sizes = [0u8; 21];
+        for &code in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_length_sizes[code] = reader.bits(3) as u8;
+        }
+        let code_lengths = Self::new(&code_length_sizes)?;
+
+        let mut code_sizes = vec![0u8; symbol_count];
+        let mut symbol     = 0;
+        while symbol < symbol_count {
+            let (size, count) = match code_lengths.decode(reader)? {
+                size @ 0..=16 => (size as u8, 1),
+                17            => (0, reader.bits(3) as usize + 3),    // Short run of zeros
+                18            => (0, reader.bits(7) as usize + 11),   // Long run of zeros
+                code          => {
+                    let previous = *code_sizes.get(symbol.wrapping_sub(1))
+                                              .ok_or_else(|| anyhow::anyhow!("Invalid BasisLZ Huffman table"))?;
+                    let count    = if code == 19 { reader.bits(2) + 3 } else { reader.bits(7) + 7 };
+                    (previous, count as usize)
+                },
+            };
+
+            if symbol + count > symbol_count {
+                anyhow::bail!("Invalid BasisLZ Huffman table (code lengths overrun)");
+            }
+            code_sizes[symbol..symbol + count].fill(size);
+            symbol += count;
+        }
+
+        Self::new(&code_sizes)
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> anyhow::Result<u32> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for length in 1..=16 {
+            code |= reader.bits(1) as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize] as u32);
+            }
+            index += count;
+            first  = (first + count) << 1;
+            code <<= 1;
+        }
+        anyhow::bail!("Invalid BasisLZ Huffman code")
+    }
+}
+///// HUFFMAN TABLE STRUCTURE //////////////////////////////////////////////////////////////////////
+
+
+///// ETC1S CODEBOOK STRUCTURES ////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Endpoint {
+    color5: [u8; 3],
+    inten : u8,
+}
+
+impl Endpoint {
+    /// RGB of the four selectors, darkest first.
+    fn colors(&self) -> [[u8; 3]; 4] {
+        let base = self.color5.map(|c| (c << 3) | (c >> 2));
+        ETC1_INTENSITIES[self.inten as usize].map(|delta| base.map(|c| (c as i32 + delta).clamp(0, 255) as u8))
+    }
+}
+
+/// Linear selectors (0..=3) of a 4x4 block, row by row.
+type Selector = [u8; 16];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Block {
+    endpoint: u16,
+    selector: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ImageDesc {
+    flags       : u32,
+    rgb_slice   : (usize, usize),  // (offset in the level, length)
+    alpha_slice : (usize, usize),
+}
+///// ETC1S CODEBOOK STRUCTURES ////////////////////////////////////////////////////////////////////
+
+
+///// ETC1S TRANSCODER STRUCTURE ///////////////////////////////////////////////////////////////////
+/// Codebooks and Huffman tables of a BasisLZ file (KTX2 supercompression global data).
+pub struct Etc1sTranscoder {
+    endpoints          : Vec<Endpoint>,
+    selectors          : Vec<Selector>,
+    images             : Vec<ImageDesc>,
+    endpoint_pred_model: HuffmanTable,
+    delta_endpoint_model: HuffmanTable,
+    selector_model     : HuffmanTable,
+    selector_rle_model : HuffmanTable,
+    selector_history   : usize,
+}
+
+impl Etc1sTranscoder {
+    /// `image_count` is the number of images in the file (one per level for 2D textures).
+    pub fn new(global_data: &[u8], image_count: usize) -> anyhow::Result<Self> {
+        let u16_at = |offset: usize| global_data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+        let u32_at = |offset: usize| global_data.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+        let truncated = || anyhow::anyhow!("BasisLZ global data is truncated");
+
+        let endpoint_count   = u16_at(0).ok_or_else(truncated)?;
+        let selector_count   = u16_at(2).ok_or_else(truncated)?;
+        let endpoints_length = u32_at(4).ok_or_else(truncated)?;
+        let selectors_length = u32_at(8).ok_or_else(truncated)?;
+        let tables_length    = u32_at(12).ok_or_else(truncated)?;
+        if endpoint_count == 0 || selector_count == 0 {
+            anyhow::bail!("BasisLZ file without endpoint or selector codebook");
+        }
+
+        let images = (0..image_count).map(|image| {
+            let offset = 20 + 20 * image;
+            Ok(ImageDesc {
+                flags      : u32_at(offset).ok_or_else(truncated)? as u32,
+                rgb_slice  : (u32_at(offset + 4).ok_or_else(truncated)?,  u32_at(offset + 8).ok_or_else(truncated)?),
+                alpha_slice: (u32_at(offset + 12).ok_or_else(truncated)?, u32_at(offset + 16).ok_or_else(truncated)?),
+            })
+        }).collect::<anyhow::Result<Vec<_>>>()?;
+
+        let endpoints_start = 20 + 20 * image_count;
+        let selectors_start = endpoints_start + endpoints_length;
+        let tables_start    = selectors_start + selectors_length;
+        let section = |start: usize, length: usize| global_data.get(start..start + length).ok_or_else(truncated);
+
+        let endpoints = Self::read_endpoints(section(endpoints_start, endpoints_length)?, endpoint_count)?;
+        let selectors = Self::read_selectors(section(selectors_start, selectors_length)?, selector_count)?;
+
+        let mut reader = BitReader::new(section(tables_start, tables_length)?);
+        let endpoint_pred_model  = HuffmanTable::read(&mut reader)?;
+        let delta_endpoint_model = HuffmanTable::read(&mut reader)?;
+        let selector_model       = HuffmanTable::read(&mut reader)?;
+        let selector_rle_model   = HuffmanTable::read(&mut reader)?;
+        let selector_history     = reader.bits(13) as usize;
+
+        Ok(Self { endpoints, selectors, images, endpoint_pred_model, delta_endpoint_model,
+                  selector_model, selector_rle_model, selector_history })
+    }
+
+    /// Colours and intensities are delta coded against the previous endpoint.
+    fn read_endpoints(data: &[u8], count: usize) -> anyhow::Result<Vec<Endpoint>> {
+        let mut reader = BitReader::new(data);
+        let color_models = [HuffmanTable::read(&mut reader)?,
+                            HuffmanTable::read(&mut reader)?,
+                            HuffmanTable::read(&mut reader)?];
+        let inten_model  = HuffmanTable::read(&mut reader)?;
+        let grayscale    = reader.bits(1) == 1;
+
+        let mut previous  = Endpoint { color5: [16, 16, 16], inten: 0 };
+        let mut endpoints = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut endpoint = Endpoint {
+                inten: ((previous.inten as u32 + inten_model.decode(&mut reader)?) & 7) as u8,
+                ..previous
+            };
+
+            for channel in 0..if grayscale { 1 } else { 3 } {
+                let model = match previous.color5[channel] {
+                    value if value <= COLOR5_PAL0_PREV_HI => &color_models[0],
+                    value if value <= COLOR5_PAL1_PREV_HI => &color_models[1],
+                    _                                     => &color_models[2],
+                };
+                endpoint.color5[channel] = ((previous.color5[channel] as u32 + model.decode(&mut reader)?) & 31) as u8;
+            }
+            if grayscale {
+                endpoint.color5 = [endpoint.color5[0]; 3];
+            }
+
+            endpoints.push(endpoint);
+            previous = endpoint;
+        }
+
+        Ok(endpoints)
+    }
+
+    /// Selector rows are sent raw or XOR coded against the previous selector.
+    fn read_selectors(data: &[u8], count: usize) -> anyhow::Result<Vec<Selector>> {
+        let mut reader = BitReader::new(data);
+        if reader.bits(1) == 1 || reader.bits(1) == 1 {
+            anyhow::bail!("BasisLZ global/hybrid selector codebooks are not supported");
+        }
+        let raw        = reader.bits(1) == 1;
+        let delta_model = if raw { None } else { Some(HuffmanTable::read(&mut reader)?) };
+
+        let mut previous  = [0u8; 4];
+        let mut selectors = Vec::with_capacity(count);
+        for index in 0..count {
+            let mut selector = [0u8; 16];
+            for (row, previous_row) in previous.iter_mut().enumerate() {
+                let bits = match &delta_model {
+                    Some(model) if index > 0 => model.decode(&mut reader)? as u8 ^ *previous_row,
+                    _                        => reader.bits(8) as u8,
+                };
+                *previous_row = bits;
+
+                for column in 0..4 {
+                    selector[row * 4 + column] = (bits >> (column * 2)) & 3;
+                }
+            }
+            selectors.push(selector);
+        }
+
+        Ok(selectors)
+    }
+
+    /// Does the file carry an alpha slice next to every colour slice?
+    pub fn has_alpha(&self) -> bool {
+        self.images.first().is_some_and(|image| image.alpha_slice.1 > 0)
+    }
+
+    /// Transcodes image `image` (whose slices live in `level_data`) of `width`x`height` texels
+    /// into `format` (one of the `target_format` results).
+    pub fn transcode(&self,
+                     image     : usize,
+                     level_data: &[u8],
+                     width     : u32,
+                     height    : u32,
+                     format    : wgpu::TextureFormat) -> anyhow::Result<Vec<u8>> {
+        let desc = self.images.get(image).ok_or_else(|| anyhow::anyhow!("BasisLZ image {} is missing", image))?;
+        if desc.flags & IMAGE_FLAG_P_FRAME != 0 {
+            anyhow::bail!("BasisLZ video frames are not supported");
+        }
+
+        let (blocks_x, blocks_y) = (width.div_ceil(4), height.div_ceil(4));
+        let slice = |(offset, length): (usize, usize)| {
+            let data = level_data.get(offset..offset + length)
+                                 .ok_or_else(|| anyhow::anyhow!("BasisLZ slice is out of bounds"))?;
+            self.decode_slice(data, blocks_x, blocks_y)
+        };
+
+        let colors = slice(desc.rgb_slice)?;
+        let alphas = if self.has_alpha() { Some(slice(desc.alpha_slice)?) } else { None };
+
+        Ok(match format {
+            wgpu::TextureFormat::Etc2Rgb8Unorm => {
+                colors.iter().flat_map(|block| self.etc1_block(block)).collect()
+            },
+            wgpu::TextureFormat::Etc2Rgba8Unorm => {
+                let alphas   = alphas.as_deref().unwrap_or(&colors);
+                let mut fits = HashMap::new();
+                colors.iter().zip(alphas).flat_map(|(color, alpha)| {
+                    let fit = *fits.entry(alpha.endpoint).or_insert_with(|| fit_eac(self.alpha_values(alpha)));
+                    let mut block = [0u8; 16];
+                    block[..8].copy_from_slice(&fit.block(&self.selectors[alpha.selector as usize]));
+                    block[8..].copy_from_slice(&self.etc1_block(color));
+                    block
+                }).collect()
+            },
+            wgpu::TextureFormat::Bc1RgbaUnorm => {
+                let mut fits = HashMap::new();
+                colors.iter().flat_map(|block| {
+                    let endpoint = &self.endpoints[block.endpoint as usize];
+                    let fit      = *fits.entry(block.endpoint).or_insert_with(|| fit_bc1(endpoint.colors()));
+                    fit.block(&self.selectors[block.selector as usize])
+                }).collect()
+            },
+            wgpu::TextureFormat::Bc7RgbaUnorm => {
+                let alphas       = alphas.as_deref();
+                let color_levels = (0..128u8).map(|code| (code << 1) | (code >> 6)).collect::<Vec<_>>();
+                let alpha_levels = (0..=255u8).collect::<Vec<_>>();
+                let mut color_fits = HashMap::new();
+                let mut alpha_fits = HashMap::new();
+                colors.iter().enumerate().flat_map(|(index, color)| {
+                    let endpoint = &self.endpoints[color.endpoint as usize];
+                    let rgb      = *color_fits.entry(color.endpoint).or_insert_with(|| fit_line(endpoint.colors(), &color_levels));
+                    let alpha    = alphas.map(|alphas| &alphas[index]);
+                    let a        = *alpha_fits.entry(alpha.map(|alpha| alpha.endpoint)).or_insert_with(|| {
+                        fit_line(alpha.map_or([255; 4], |alpha| self.alpha_values(alpha)).map(|value| [value]), &alpha_levels)
+                    });
+                    bc7_mode5_block(&rgb, &self.selectors[color.selector as usize],
+                                    &a,   &self.selectors[alpha.unwrap_or(color).selector as usize])
+                }).collect()
+            },
+            wgpu::TextureFormat::Astc { block: wgpu::AstcBlock::B4x4, channel: wgpu::AstcChannel::Unorm } => {
+                // ---> Opaque: 8 bit endpoints, alpha: dual plane, which leaves endpoint range 48:
+                let levels = if alphas.is_some() { astc_levels_48() } else { (0..=255u8).collect() };
+                let mut color_fits = HashMap::new();
+                let mut alpha_fits = HashMap::new();
+                colors.iter().enumerate().flat_map(|(index, color)| {
+                    let endpoint = &self.endpoints[color.endpoint as usize];
+                    let rgb      = *color_fits.entry(color.endpoint).or_insert_with(|| {
+                        fit_line(endpoint.colors(), &levels).ascending(&levels)
+                    });
+                    let selector = &self.selectors[color.selector as usize];
+                    match alphas.as_ref().map(|alphas| &alphas[index]) {
+                        None        => astc_block(&rgb, selector, None),
+                        Some(alpha) => {
+                            let values = self.alpha_values(alpha).map(|value| [value]);
+                            let a      = *alpha_fits.entry(alpha.endpoint).or_insert_with(|| fit_line(values, &levels));
+                            astc_block(&rgb, selector, Some((&a, &self.selectors[alpha.selector as usize])))
+                        }
+                    }
+                }).collect()
+            },
+            wgpu::TextureFormat::Rgba8Unorm => {
+                let mut pixels = vec![255u8; (width * height * 4) as usize];
+                for (index, block) in colors.iter().enumerate() {
+                    let (block_x, block_y) = (index as u32 % blocks_x, index as u32 / blocks_x);
+                    let palette  = self.endpoints[block.endpoint as usize].colors();
+                    let selector = &self.selectors[block.selector as usize];
+                    let alpha    = alphas.as_ref().map(|alphas| {
+                        (self.alpha_values(&alphas[index]), &self.selectors[alphas[index].selector as usize])
+                    });
+
+                    for texel in 0..16 {
+                        let (x, y) = (block_x * 4 + texel as u32 % 4, block_y * 4 + texel as u32 / 4);
+                        if x >= width || y >= height {
+                            continue;
+                        }
+                        let offset = ((y * width + x) * 4) as usize;
+                        pixels[offset..offset + 3].copy_from_slice(&palette[selector[texel] as usize]);
+                        if let Some((values, selector)) = alpha {
+                            pixels[offset + 3] = values[selector[texel] as usize];
+                        }
+                    }
+                }
+                pixels
+            },
+            other => anyhow::bail!("ETC1S can not be transcoded to {:?}", other),
+        })
+    }
+
+    /// Endpoint and selector indices of every block of a slice, row by row.
+    fn decode_slice(&self, data: &[u8], blocks_x: u32, blocks_y: u32) -> anyhow::Result<Vec<Block>> {
+        let invalid = |what: &str| anyhow::anyhow!("Invalid BasisLZ slice ({})", what);
+        let mut reader = BitReader::new(data);
+
+        let selector_count   = self.selectors.len() as u32;
+        let history_first    = selector_count;
+        let history_rle      = selector_count + self.selector_history as u32;
+        let mut history      = SelectorHistory::new(self.selector_history);
+        let mut rle_count    = 0u32;
+        let total_blocks     = blocks_x * blocks_y;
+
+        // ---> Prediction bits cover 2x2 blocks, the lower row is kept for the next row:
+        let mut row_endpoints = [vec![0u16; blocks_x as usize], vec![0u16; blocks_x as usize]];
+        let mut row_preds     = vec![0u32; blocks_x as usize];
+        let mut pred_bits     = 0u32;
+        let mut previous_pred = 0u32;
+        let mut pred_repeat   = 0u32;
+        let mut previous      = 0u32;  // Endpoint index of the previous block
+
+        let mut blocks = Vec::with_capacity(total_blocks as usize);
+        for block_y in 0..blocks_y {
+            let (current_row, upper_row) = ((block_y & 1) as usize, (block_y & 1 ^ 1) as usize);
+
+            for block_x in 0..blocks_x {
+                if block_x & 1 == 0 {
+                    if block_y & 1 == 0 {
+                        if pred_repeat > 0 {
+                            pred_repeat -= 1;
+                            pred_bits    = previous_pred;
+                        } else {
+                            pred_bits = self.endpoint_pred_model.decode(&mut reader)?;
+                            if pred_bits == ENDPOINT_PRED_REPEAT_LAST_SYMBOL {
+                                pred_repeat = reader.vlc(ENDPOINT_PRED_COUNT_VLC_BITS)? + ENDPOINT_PRED_MIN_REPEAT_COUNT - 1;
+                                pred_bits   = previous_pred;
+                            } else {
+                                previous_pred = pred_bits;
+                            }
+                        }
+                        row_preds[block_x as usize] = pred_bits >> 4;
+                    } else {
+                        pred_bits = row_preds[block_x as usize];
+                    }
+                }
+
+                // ---> Endpoint: left, upper, upper left or a delta to the previous one:
+                let endpoint = match pred_bits & 3 {
+                    0 if block_x > 0                 => previous,
+                    1 if block_y > 0                 => row_endpoints[upper_row][block_x as usize] as u32,
+                    2 if block_x > 0 && block_y > 0  => row_endpoints[upper_row][block_x as usize - 1] as u32,
+                    3 => {
+                        let endpoint = previous + self.delta_endpoint_model.decode(&mut reader)?;
+                        if endpoint >= self.endpoints.len() as u32 { endpoint - self.endpoints.len() as u32 } else { endpoint }
+                    },
+                    _ => return Err(invalid("endpoint prediction outside the image")),
+                };
+                pred_bits >>= 2;
+                row_endpoints[current_row][block_x as usize] = endpoint as u16;
+                previous = endpoint;
+
+                // ---> Selector: direct, from the history buffer or a run of the last history entry:
+                let symbol = if rle_count > 0 {
+                    rle_count -= 1;
+                    history_first
+                } else {
+                    let symbol = self.selector_model.decode(&mut reader)?;
+                    if symbol == history_rle {
+                        let run = self.selector_rle_model.decode(&mut reader)?;
+                        rle_count = if run == SELECTOR_HISTORY_RLE_COUNT_TOTAL - 1 {
+                            reader.vlc(7)? + SELECTOR_HISTORY_RLE_COUNT_THRESH
+                        } else {
+                            run + SELECTOR_HISTORY_RLE_COUNT_THRESH
+                        };
+                        if rle_count > total_blocks {
+                            return Err(invalid("selector run too long"));
+                        }
+                        rle_count -= 1;
+                        history_first
+                    } else {
+                        symbol
+                    }
+                };
+
+                let selector = if symbol >= history_first {
+                    history.take((symbol - history_first) as usize).ok_or_else(|| invalid("selector history"))?
+                } else {
+                    history.add(symbol);
+                    symbol
+                };
+
+                if endpoint >= self.endpoints.len() as u32 || selector >= selector_count {
+                    return Err(invalid("codebook index out of range"));
+                }
+                blocks.push(Block { endpoint: endpoint as u16, selector: selector as u16 });
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    fn etc1_block(&self, block: &Block) -> [u8; 8] {
+        etc1_block(&self.endpoints[block.endpoint as usize], &self.selectors[block.selector as usize])
+    }
+
+    /// Alpha slices are grayscale ETC1S, the alpha of the four selectors is their green.
+    fn alpha_values(&self, block: &Block) -> [u8; 4] {
+        self.endpoints[block.endpoint as usize].colors().map(|color| color[1])
+    }
+}
+
+/// Transcode target for an ETC1S image: lossless ETC2 where available, then BC and ASTC, decoded
+/// RGBA as the last resort (also for sizes that are not a multiple of the 4x4 blocks).
+pub fn target_format(features: wgpu::Features, has_alpha: bool, width: u32, height: u32) -> wgpu::TextureFormat {
+    let block_aligned = width.is_multiple_of(4) && height.is_multiple_of(4);
+
+    if block_aligned && features.contains(wgpu::Features::TEXTURE_COMPRESSION_ETC2) {
+        if has_alpha { wgpu::TextureFormat::Etc2Rgba8Unorm } else { wgpu::TextureFormat::Etc2Rgb8Unorm }
+    } else if block_aligned && features.contains(wgpu::Features::TEXTURE_COMPRESSION_BC) {
+        if has_alpha { wgpu::TextureFormat::Bc7RgbaUnorm } else { wgpu::TextureFormat::Bc1RgbaUnorm }
+    } else if block_aligned && features.contains(wgpu::Features::TEXTURE_COMPRESSION_ASTC) {
+        wgpu::TextureFormat::Astc { block: wgpu::AstcBlock::B4x4, channel: wgpu::AstcChannel::Unorm }
+    } else {
+        wgpu::TextureFormat::Rgba8Unorm
+    }
+}
+///// ETC1S TRANSCODER STRUCTURE ///////////////////////////////////////////////////////////////////
+
+
+///// SELECTOR HISTORY STRUCTURE ///////////////////////////////////////////////////////////////////
+/// Approximate move-to-front list of recently used selectors.
+struct SelectorHistory {
+    values: Vec<u32>,
+    rover : usize,
+}
+
+impl SelectorHistory {
+    fn new(size: usize) -> Self {
+        Self { values: vec![0; size], rover: 0 }
+    }
+
+    fn add(&mut self, value: u32) {
+        if self.values.is_empty() {
+            return;
+        }
+        self.values[self.rover] = value;
+        self.rover += 1;
+        if self.rover == self.values.len() {
+            self.rover = self.values.len() / 2;
+        }
+    }
+
+    /// Value at `index`, which moves halfway to the front.
+    fn take(&mut self, index: usize) -> Option<u32> {
+        let value = *self.values.get(index)?;
+        if index > 0 {
+            self.values.swap(index / 2, index);
+        }
+        Some(value)
+    }
+}
+///// SELECTOR HISTORY STRUCTURE ///////////////////////////////////////////////////////////////////
+
+
+///// BLOCK ENCODING PROCEDURES ////////////////////////////////////////////////////////////////////
+/// ETC1 differential block with both halves using the endpoint (no delta, no flip).
+fn etc1_block(endpoint: &Endpoint, selector: &Selector) -> [u8; 8] {
+    let [r, g, b] = endpoint.color5;
+    let (mut msb, mut lsb) = (0u16, 0u16);
+
+    // ---> ETC pixel indices are stored column by column:
+    for (texel, &value) in selector.iter().enumerate() {
+        let bit   = (texel % 4) * 4 + texel / 4;
+        let index = SELECTOR_TO_ETC1[value as usize] as u16;
+        msb |= (index >> 1) << bit;
+        lsb |= (index & 1) << bit;
+    }
+
+    let [msb0, msb1] = msb.to_be_bytes();
+    let [lsb0, lsb1] = lsb.to_be_bytes();
+    [r << 3, g << 3, b << 3, (endpoint.inten << 5) | (endpoint.inten << 2) | 0b10, msb0, msb1, lsb0, lsb1]
+}
+
+/// Palette entry closest to every selector value.
+fn nearest_indices<const N: usize>(values: [[i32; 3]; 4], palette: &[[i32; 3]; N]) -> [u8; 4] {
+    values.map(|value| {
+        (0..N).min_by_key(|&index| (0..3).map(|c| (value[c] - palette[index][c]).pow(2)).sum::<i32>())
+              .unwrap() as u8
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bc1Fit {
+    color0 : u16,
+    color1 : u16,
+    indices: [u8; 4],  // BC1 index per linear selector
+}
+
+impl Bc1Fit {
+    fn block(&self, selector: &Selector) -> [u8; 8] {
+        let bits = selector.iter().enumerate()
+                           .fold(0u32, |bits, (texel, &value)| bits | (self.indices[value as usize] as u32) << (2 * texel));
+        let mut block = [0u8; 8];
+        block[0..2].copy_from_slice(&self.color0.to_le_bytes());
+        block[2..4].copy_from_slice(&self.color1.to_le_bytes());
+        block[4..8].copy_from_slice(&bits.to_le_bytes());
+        block
+    }
+}
+
+/// BC1 (four colour mode) between the darkest and brightest colour of an endpoint.
+fn fit_bc1(colors: [[u8; 3]; 4]) -> Bc1Fit {
+    let pack   = |[r, g, b]: [u8; 3]| {
+        let quantize = |value: u8, max: u32| (value as u32 * max + 127) / 255;
+        ((quantize(r, 31) << 11) | (quantize(g, 63) << 5) | quantize(b, 31)) as u16
+    };
+    let unpack = |color: u16| {
+        let expand = |value: u16, bits: u32| ((value << (8 - bits)) | (value >> (2 * bits - 8))) as i32;
+        [expand(color >> 11, 5), expand((color >> 5) & 63, 6), expand(color & 31, 5)]
+    };
+
+    let (mut color0, mut color1) = (pack(colors[3]), pack(colors[0]));
+    if color0 == color1 {
+        return Bc1Fit { color0, color1, indices: [0; 4] };
+    }
+    if color0 < color1 {
+        std::mem::swap(&mut color0, &mut color1);
+    }
+
+    let (c0, c1) = (unpack(color0), unpack(color1));
+    let palette  = [c0, c1, [0, 1, 2].map(|c| (2 * c0[c] + c1[c]) / 3), [0, 1, 2].map(|c| (c0[c] + 2 * c1[c]) / 3)];
+    Bc1Fit { color0, color1, indices: nearest_indices(colors.map(|color| color.map(|c| c as i32)), &palette) }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EacFit {
+    base      : u8,
+    multiplier: u8,
+    table     : u8,
+    indices   : [u8; 4],
+}
+
+impl EacFit {
+    fn block(&self, selector: &Selector) -> [u8; 8] {
+        let mut bits = (self.base as u64) << 56 | (self.multiplier as u64) << 52 | (self.table as u64) << 48;
+        for (texel, &value) in selector.iter().enumerate() {
+            let pixel = (texel % 4) * 4 + texel / 4;  // Column by column, like ETC
+            bits |= (self.indices[value as usize] as u64) << (45 - 3 * pixel);
+        }
+        bits.to_be_bytes()
+    }
+}
+
+/// Best EAC base/table/multiplier for the (at most four) alpha values of an endpoint.
+fn fit_eac(values: [u8; 4]) -> EacFit {
+    let (low, high) = (*values.iter().min().unwrap() as i32, *values.iter().max().unwrap() as i32);
+    let mut best = (i32::MAX, EacFit { base: low as u8, multiplier: 1, table: 13, indices: [4; 4] });
+
+    for (table, modifiers) in EAC_MODIFIERS.iter().enumerate() {
+        for multiplier in 1..16 {
+            let centre = (low + high) / 2 - (modifiers[3] + modifiers[7]) * multiplier / 2;
+            for base in (centre - 2..=centre + 2).map(|base| base.clamp(0, 255)) {
+                let palette = modifiers.map(|modifier| [(base + modifier * multiplier).clamp(0, 255); 3]);
+                let indices = nearest_indices(values.map(|value| [value as i32; 3]), &palette);
+                let error   = (0..4).map(|i| (values[i] as i32 - palette[indices[i] as usize][0]).pow(2)).sum::<i32>();
+
+                if error < best.0 {
+                    best = (error, EacFit { base: base as u8, multiplier: multiplier as u8, table: table as u8, indices });
+                }
+            }
+        }
+    }
+
+    best.1
+}
+
+/// LSB-first writer for 128 bit blocks.
+#[derive(Default)]
+struct BlockWriter {
+    bits    : u128,
+    position: u32,
+}
+
+impl BlockWriter {
+    fn put(&mut self, value: u32, count: u32) {
+        self.bits     |= ((value as u128) & ((1 << count) - 1)) << self.position;
+        self.position += count;
+    }
+}
+
+/// Two endpoints (codes into a level table) and the 2 bit index of every linear selector.
+#[derive(Debug, Clone, Copy)]
+struct LineFit<const C: usize> {
+    endpoints: [[u8; C]; 2],
+    indices  : [u8; 4],
+}
+
+impl<const C: usize> LineFit<C> {
+    fn swapped(&self) -> Self {
+        Self { endpoints: [self.endpoints[1], self.endpoints[0]], indices: self.indices.map(|index| 3 - index) }
+    }
+
+    /// ASTC blue-contracts endpoints whose second colour is darker, keep it the brighter one.
+    fn ascending(self, levels: &[u8]) -> Self {
+        let sum = |endpoint: [u8; C]| endpoint.iter().map(|&code| levels[code as usize] as u32).sum::<u32>();
+        if sum(self.endpoints[1]) < sum(self.endpoints[0]) { self.swapped() } else { self }
+    }
+
+    /// Index of every texel, swapping the endpoints when texel 0 needs the BC7 anchor bit.
+    fn anchored(&self, selector: &Selector) -> (Self, [u8; 16]) {
+        let fit = if self.indices[selector[0] as usize] >= 2 { self.swapped() } else { *self };
+        (fit, selector.map(|value| fit.indices[value as usize]))
+    }
+}
+
+/// Line between the darkest and brightest selector value, quantized to `levels` (the 8 bit value
+/// of every endpoint code) and interpolated with 2 bit weights.
+fn fit_line<const C: usize>(values: [[u8; C]; 4], levels: &[u8]) -> LineFit<C> {
+    let nearest   = |value: u8| (0..levels.len()).min_by_key(|&code| (levels[code] as i32 - value as i32).abs()).unwrap() as u8;
+    let endpoints = [values[0].map(nearest), values[3].map(nearest)];
+
+    let palette = WEIGHTS_2BIT.map(|weight| {
+        let mut color = [0i32; C];
+        for (c, channel) in color.iter_mut().enumerate() {
+            let (e0, e1) = (levels[endpoints[0][c] as usize] as i32, levels[endpoints[1][c] as usize] as i32);
+            *channel = ((64 - weight) * e0 + weight * e1 + 32) >> 6;
+        }
+        color
+    });
+    let indices = values.map(|value| {
+        (0..4).min_by_key(|&index| (0..C).map(|c| (value[c] as i32 - palette[index][c]).pow(2)).sum::<i32>())
+              .unwrap() as u8
+    });
+    LineFit { endpoints, indices }
+}
+
+/// BC7 mode 5: 7 bit colour and 8 bit alpha endpoints with their own 2 bit indices.
+fn bc7_mode5_block(color: &LineFit<3>, color_selector: &Selector, alpha: &LineFit<1>, alpha_selector: &Selector) -> [u8; 16] {
+    let (color, color_indices) = color.anchored(color_selector);
+    let (alpha, alpha_indices) = alpha.anchored(alpha_selector);
+
+    let mut block = BlockWriter::default();
+    block.put(1 << 5, 6);  // Mode 5
+    block.put(0, 2);       // No channel rotation
+    for channel in 0..3 {
+        block.put(color.endpoints[0][channel] as u32, 7);
+        block.put(color.endpoints[1][channel] as u32, 7);
+    }
+    block.put(alpha.endpoints[0][0] as u32, 8);
+    block.put(alpha.endpoints[1][0] as u32, 8);
+
+    // ---> The first index of each set drops its (zero) high bit:
+    for indices in [color_indices, alpha_indices] {
+        for (texel, &index) in indices.iter().enumerate() {
+            block.put(index as u32, if texel == 0 { 1 } else { 2 });
+        }
+    }
+    block.bits.to_le_bytes()
+}
+
+/// 8 bit value of every ASTC colour endpoint code of range 48 (a trit and four bits).
+fn astc_levels_48() -> Vec<u8> {
+    (0..48u32).map(|code| {
+        let (trit, bits) = (code >> 4, code & 15);
+        let a = if bits & 1 == 1 { 0x1FF } else { 0 };
+        let b = ((bits >> 1) << 6) | (bits >> 1);
+        let t = (trit * 22 + b) ^ a;
+        ((a & 0x80) | (t >> 2)) as u8
+    }).collect()
+}
+
+/// Trits packed into 8 bits by ASTC integer sequence encoding.
+fn astc_decode_trits(packed: u8) -> [u8; 5] {
+    let (c, t3, t4) = if (packed >> 2) & 7 == 7 {
+        ((packed >> 5) << 2 | (packed & 3), 2, 2)
+    } else if (packed >> 5) & 3 == 3 {
+        (packed & 31, packed >> 7, 2)
+    } else {
+        (packed & 31, (packed >> 5) & 3, packed >> 7)
+    };
+    let (t0, t1, t2) = if c & 3 == 3 {
+        ((c >> 3 & 1) << 1 | (c >> 2 & 1 & !(c >> 3) & 1), c >> 4 & 1, 2)
+    } else if (c >> 2) & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        ((c >> 1 & 1) << 1 | (c & 1 & !(c >> 1) & 1), (c >> 2) & 3, c >> 4)
+    };
+    [t0, t1, t2, t3, t4]
+}
+
+/// Integer sequence encoding of range 48 values (bits of every value, trits packed in between).
+fn astc_encode_range_48(block: &mut BlockWriter, values: &[u8]) {
+    const TRIT_BITS: [(u32, u32); 5] = [(0, 2), (2, 2), (4, 1), (5, 2), (7, 1)];  // (first bit, count)
+
+    for chunk in values.chunks(5) {
+        let mut trits = [0u8; 5];
+        chunk.iter().enumerate().for_each(|(index, &value)| trits[index] = value >> 4);
+        let packed = (0..=255u8).find(|&packed| astc_decode_trits(packed) == trits).unwrap() as u32;
+
+        for (&value, (first, count)) in chunk.iter().zip(TRIT_BITS) {
+            block.put(value as u32 & 15, 4);
+            block.put(packed >> first, count);
+        }
+    }
+}
+
+/// ASTC 4x4 block, RGB direct with 8 bit endpoints, or RGBA direct with a second weight plane for
+/// the alpha (range 48 endpoints).
+fn astc_block(color: &LineFit<3>, color_selector: &Selector, alpha: Option<(&LineFit<1>, &Selector)>) -> [u8; 16] {
+    let mut block = BlockWriter::default();
+    block.put(if alpha.is_some() { ASTC_BLOCK_MODE_DUAL_PLANE } else { ASTC_BLOCK_MODE }, 11);
+    block.put(0, 2);  // One partition
+    block.put(if alpha.is_some() { ASTC_CEM_LDR_RGBA_DIRECT } else { ASTC_CEM_LDR_RGB_DIRECT }, 4);
+
+    // ---> Endpoints (r0 r1 g0 g1 b0 b1 [a0 a1]), then weights from the top bit down:
+    let mut values: Vec<u8> = (0..3).flat_map(|c| [color.endpoints[0][c], color.endpoints[1][c]]).collect();
+    let mut weights: Vec<u8> = color_selector.iter().map(|&value| color.indices[value as usize]).collect();
+    match alpha {
+        None => values.iter().for_each(|&value| block.put(value as u32, 8)),
+        Some((alpha, alpha_selector)) => {
+            values.extend([alpha.endpoints[0][0], alpha.endpoints[1][0]]);
+            astc_encode_range_48(&mut block, &values);
+            block.put(3, 2);  // Second plane holds the alpha channel
+
+            // ---> Planes interleave per texel:
+            weights = weights.iter().zip(alpha_selector)
+                             .flat_map(|(&weight, &value)| [weight, alpha.indices[value as usize]])
+                             .collect();
+        }
+    }
+
+    let reversed = weights.iter().enumerate()
+                          .fold(0u128, |bits, (index, &weight)| bits | ((weight as u128) << (2 * index)))
+                          .reverse_bits();
+    (block.bits | reversed).to_le_bytes()
+}
+///// BLOCK ENCODING PROCEDURES ////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// LSB-first bit writer, the counterpart of `BitReader`.
+    #[derive(Default)]
+    struct BitWriter {
+        bytes   : Vec<u8>,
+        position: usize,
+    }
+
+    impl BitWriter {
+        fn bits(&mut self, value: u32, count: u32) {
+            for bit in 0..count {
+                if self.position.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                *self.bytes.last_mut().unwrap() |= (((value >> bit) & 1) as u8) << (self.position % 8);
+                self.position += 1;
+            }
+        }
+
+        /// Huffman table giving all `symbol_count` symbols a code of `size` bits, so symbol `s`
+        /// is sent as `s` (most significant bit first).
+        fn flat_table(&mut self, symbol_count: u32, size: u8) {
+            let order = CODE_LENGTH_ORDER.iter().position(|&code| code == size as usize).unwrap();
+            self.bits(symbol_count, 14);
+            self.bits(order as u32 + 1, 5);
+            for &code in &CODE_LENGTH_ORDER[..=order] {
+                self.bits(if code == size as usize { 1 } else { 0 }, 3);  // Single code length code "0"
+            }
+            for _ in 0..symbol_count {
+                self.bits(0, 1);
+            }
+        }
+
+        fn symbol(&mut self, symbol: u32, size: u8) {
+            for bit in (0..size).rev() {
+                self.bits((symbol >> bit) & 1, 1);
+            }
+        }
+    }
+
+    const ENDPOINTS: [Endpoint; 3] = [
+        Endpoint { color5: [ 4, 12, 28], inten: 1 },
+        Endpoint { color5: [20,  3, 31], inten: 5 },
+        Endpoint { color5: [10, 10, 10], inten: 0 },
+    ];
+    const SELECTOR_ROWS: [[u8; 4]; 2] = [[0b11_10_01_00, 0b00_01_10_11, 0b01_01_01_01, 0b11_00_11_00],
+                                         [0b10_10_00_00, 0b11_11_11_11, 0b00_11_10_01, 0b01_10_11_00]];
+    const HISTORY_SIZE: u32 = 4;
+
+    /// (offset, length) of a slice in its level.
+    type SliceRange = (u32, u32);
+
+    type BlockDecoder = fn(&[u8], usize, usize) -> [u8; 4];
+
+    const ASTC_4X4: wgpu::TextureFormat = wgpu::TextureFormat::Astc { block: wgpu::AstcBlock::B4x4, channel: wgpu::AstcChannel::Unorm };
+
+    /// Global data for `image_slices` (rgb and alpha slice of every image, relative to its level).
+    pub(crate) fn global_data(image_slices: &[(SliceRange, SliceRange)]) -> Vec<u8> {
+        // ---> Endpoints, each colour channel model chosen by the previous value:
+        let mut endpoints = BitWriter::default();
+        for size in [5, 6, 7, 3] {
+            endpoints.flat_table(if size == 3 { 8 } else { 32 }, size);
+        }
+        endpoints.bits(0, 1);  // Not grayscale
+        let mut previous = Endpoint { color5: [16, 16, 16], inten: 0 };
+        for endpoint in ENDPOINTS {
+            endpoints.symbol((endpoint.inten.wrapping_sub(previous.inten) & 7) as u32, 3);
+            for channel in 0..3 {
+                let size = match previous.color5[channel] { 0..=9 => 5, 10..=21 => 6, _ => 7 };
+                endpoints.symbol((endpoint.color5[channel].wrapping_sub(previous.color5[channel]) & 31) as u32, size);
+            }
+            previous = endpoint;
+        }
+
+        // ---> Selectors, XOR coded against the previous one:
+        let mut selectors = BitWriter::default();
+        selectors.bits(0, 3);  // Local codebook, not hybrid, not raw
+        selectors.flat_table(256, 8);
+        for (index, rows) in SELECTOR_ROWS.iter().enumerate() {
+            for row in 0..4 {
+                match index {
+                    0 => selectors.bits(rows[row] as u32, 8),
+                    _ => selectors.symbol((rows[row] ^ SELECTOR_ROWS[index - 1][row]) as u32, 8),
+                }
+            }
+        }
+
+        let mut tables = BitWriter::default();
+        tables.flat_table(257, 9);
+        tables.flat_table(ENDPOINTS.len() as u32, 2);
+        tables.flat_table(SELECTOR_ROWS.len() as u32 + HISTORY_SIZE + 1, 3);
+        tables.flat_table(SELECTOR_HISTORY_RLE_COUNT_TOTAL, 6);
+        tables.bits(HISTORY_SIZE, 13);
+
+        let mut data = Vec::new();
+        for value in [ENDPOINTS.len() as u16, SELECTOR_ROWS.len() as u16] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [endpoints.bytes.len(), selectors.bytes.len(), tables.bytes.len(), 0] {
+            data.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        for ((rgb_offset, rgb_length), (alpha_offset, alpha_length)) in image_slices {
+            for value in [0, *rgb_offset, *rgb_length, *alpha_offset, *alpha_length] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        data.extend(endpoints.bytes);
+        data.extend(selectors.bytes);
+        data.extend(tables.bytes);
+        data
+    }
+
+    /// 8x8 slice: every endpoint prediction and the selector history.
+    /// Blocks (endpoint, selector): (2, 0) (0, 1) / (2, 1) (0, 0).
+    pub(crate) fn slice_8x8() -> Vec<u8> {
+        let mut slice = BitWriter::default();
+        slice.symbol(3 | 3 << 2 | 1 << 4 | 1 << 6, 9);  // Delta, delta / upper, upper
+        slice.symbol(2, 2);                              // Endpoint 0 + 2
+        slice.symbol(0, 3);                              // Selector 0
+        slice.symbol(1, 2);                              // Endpoint (2 + 1) % 3
+        slice.symbol(1, 3);                              // Selector 1
+        slice.symbol(2 + 1, 3);                          // History entry 1 (selector 1)
+        slice.symbol(2 + 1, 3);                          // History entry 1 (selector 0 after the swap)
+        slice.bytes
+    }
+
+    /// 16x4 slice: a repeated prediction symbol and a selector run.
+    /// Blocks (endpoint, selector): (1, 0) (1, 0) (2, 0) (2, 0).
+    fn slice_16x4() -> Vec<u8> {
+        let mut slice = BitWriter::default();
+        slice.symbol(3, 9);                                        // Delta, left
+        slice.symbol(1, 2);                                        // Endpoint 0 + 1
+        slice.symbol(0, 3);                                        // Selector 0
+        slice.symbol(2 + HISTORY_SIZE, 3);                         // Run of history entry 0 ...
+        slice.symbol(0, 6);                                        // ... for three blocks
+        slice.symbol(ENDPOINT_PRED_REPEAT_LAST_SYMBOL, 9);         // Delta, left again
+        slice.bits(0, ENDPOINT_PRED_COUNT_VLC_BITS + 1);
+        slice.symbol(1, 2);                                        // Endpoint 1 + 1
+        slice.bytes
+    }
+
+    fn blocks(transcoder: &Etc1sTranscoder, slice: &[u8], blocks_x: u32, blocks_y: u32) -> Vec<(u16, u16)> {
+        transcoder.decode_slice(slice, blocks_x, blocks_y).unwrap()
+                  .into_iter().map(|block| (block.endpoint, block.selector)).collect()
+    }
+
+    /// Reference RGBA of a texel straight from the codebooks.
+    fn texel(block: (u16, u16), x: usize, y: usize) -> [u8; 3] {
+        let rows = SELECTOR_ROWS[block.1 as usize];
+        ENDPOINTS[block.0 as usize].colors()[((rows[y] >> (x * 2)) & 3) as usize]
+    }
+
+    #[test]
+    fn decodes_codebooks_and_slices() {
+        let slice      = slice_8x8();
+        let transcoder = Etc1sTranscoder::new(&global_data(&[((0, slice.len() as u32), (0, 0))]), 1).unwrap();
+
+        assert_eq!(transcoder.endpoints, ENDPOINTS);
+        assert_eq!(transcoder.selectors[1][4..8], [3, 3, 3, 3]);
+        assert!(!transcoder.has_alpha());
+
+        assert_eq!(blocks(&transcoder, &slice, 2, 2), vec![(2, 0), (0, 1), (2, 1), (0, 0)]);
+        assert_eq!(blocks(&transcoder, &slice_16x4(), 4, 1), vec![(1, 0), (1, 0), (2, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn picks_the_target_from_device_features() {
+        use wgpu::Features;
+        use wgpu::TextureFormat;
+
+        let both = Features::TEXTURE_COMPRESSION_ETC2 | Features::TEXTURE_COMPRESSION_BC;
+        assert_eq!(target_format(both, false, 8, 8), TextureFormat::Etc2Rgb8Unorm);
+        assert_eq!(target_format(both, true, 8, 8),  TextureFormat::Etc2Rgba8Unorm);
+        assert_eq!(target_format(Features::TEXTURE_COMPRESSION_BC, false, 8, 8), TextureFormat::Bc1RgbaUnorm);
+        assert_eq!(target_format(Features::TEXTURE_COMPRESSION_BC, true, 8, 8),  TextureFormat::Bc7RgbaUnorm);
+        assert_eq!(target_format(Features::TEXTURE_COMPRESSION_ASTC, true, 8, 8), ASTC_4X4);
+        assert_eq!(target_format(Features::empty(), true, 8, 8), TextureFormat::Rgba8Unorm);
+        assert_eq!(target_format(both, false, 6, 8), TextureFormat::Rgba8Unorm);
+    }
+
+    #[test]
+    fn rgba_output_matches_the_codebooks() {
+        let slice      = slice_8x8();
+        let length     = slice.len() as u32;
+        let transcoder = Etc1sTranscoder::new(&global_data(&[((0, length), (0, length))]), 1).unwrap();
+        let pixels     = transcoder.transcode(0, &slice, 7, 6, wgpu::TextureFormat::Rgba8Unorm).unwrap();
+
+        assert_eq!(pixels.len(), 7 * 6 * 4);
+        let blocks = [(2, 0), (0, 1), (2, 1), (0, 0)];
+        for y in 0..6 {
+            for x in 0..7 {
+                let block  = blocks[(y / 4) * 2 + x / 4];
+                let offset = (y * 7 + x) * 4;
+                let color  = texel(block, x % 4, y % 4);
+                assert_eq!(pixels[offset..offset + 4], [color[0], color[1], color[2], color[1]], "texel {}, {}", x, y);
+            }
+        }
+    }
+
+    /// ETC1 (differential, no flip) and EAC decoding of the fields the transcoder writes.
+    fn decode_etc(block: &[u8], alpha: Option<&[u8]>, x: usize, y: usize) -> [u8; 4] {
+        const ETC1_MODIFIERS: [[i32; 2]; 8] = [[2, 8], [5, 17], [9, 29], [13, 42], [18, 60], [24, 80], [33, 106], [47, 183]];
+        let pixel   = x * 4 + y;
+        let bits    = u32::from_be_bytes(block[4..8].try_into().unwrap());
+        let index   = ((bits >> (16 + pixel)) & 1) << 1 | ((bits >> pixel) & 1);
+        let modifier = ETC1_MODIFIERS[(block[3] >> 5) as usize][(index & 1) as usize] * if index & 2 != 0 { -1 } else { 1 };
+        let channel = |byte: u8| ((byte >> 3) << 3 | (byte >> 5)) as i32;
+        let color   = [0, 1, 2].map(|c| (channel(block[c]) + modifier).clamp(0, 255) as u8);
+
+        let alpha = alpha.map_or(255, |alpha| {
+            let bits  = u64::from_be_bytes(alpha.try_into().unwrap());
+            let index = ((bits >> (45 - 3 * pixel)) & 7) as usize;
+            let value = alpha[0] as i32 + EAC_MODIFIERS[(alpha[1] & 15) as usize][index] * (alpha[1] >> 4) as i32;
+            value.clamp(0, 255) as u8
+        });
+        [color[0], color[1], color[2], alpha]
+    }
+
+    /// BC1 (four colour mode) decoding.
+    fn decode_bc1(block: &[u8], x: usize, y: usize) -> [u8; 4] {
+        let texel  = y * 4 + x;
+        let unpack = |color: u16| [(color >> 11) as i32 * 255 / 31, ((color >> 5) & 63) as i32 * 255 / 63, (color & 31) as i32 * 255 / 31];
+        let (c0, c1) = (unpack(u16::from_le_bytes([block[0], block[1]])), unpack(u16::from_le_bytes([block[2], block[3]])));
+        let index  = (u32::from_le_bytes(block[4..8].try_into().unwrap()) >> (2 * texel)) & 3;
+        let color  = [0, 1, 2].map(|c| (match index {
+            0 => c0[c], 1 => c1[c], 2 => (2 * c0[c] + c1[c]) / 3, _ => (c0[c] + 2 * c1[c]) / 3,
+        }) as u8);
+        [color[0], color[1], color[2], 255]
+    }
+
+    /// BC7 mode 5 (without channel rotation) decoding.
+    fn decode_bc7(block: &[u8], x: usize, y: usize) -> [u8; 4] {
+        let bits  = u128::from_le_bytes(block.try_into().unwrap());
+        let field = |first: usize, count: usize| ((bits >> first) & ((1 << count) - 1)) as i32;
+        assert_eq!(field(0, 8), 1 << 5, "mode 5 without rotation");
+
+        // ---> Index of `texel` in the set starting at `first` (texel 0 has one bit):
+        let texel = y * 4 + x;
+        let index = |first: usize| if texel == 0 { field(first, 1) } else { field(first + 2 * texel - 1, 2) };
+        let lerp  = |e0: i32, e1: i32, index: i32| (((64 - WEIGHTS_2BIT[index as usize]) * e0 + WEIGHTS_2BIT[index as usize] * e1 + 32) >> 6) as u8;
+
+        let expand = |value: i32| (value << 1) | (value >> 6);
+        let color  = [0, 1, 2].map(|c| lerp(expand(field(8 + 14 * c, 7)), expand(field(15 + 14 * c, 7)), index(66)));
+        [color[0], color[1], color[2], lerp(field(50, 8), field(58, 8), index(97))]
+    }
+
+    #[test]
+    fn block_outputs_decode_close_to_the_codebooks() {
+        use wgpu::TextureFormat;
+
+        let slice      = slice_8x8();
+        let length     = slice.len() as u32;
+        let transcoder = Etc1sTranscoder::new(&global_data(&[((0, length), (0, length))]), 1).unwrap();
+        let blocks     = [(2, 0), (0, 1), (2, 1), (0, 0)];
+
+        // ---> (format, block size, decoder, colour and alpha tolerance): ETC1 colour is exact,
+        //      EAC alpha and BC1/BC7 colour are fitted:
+        let formats: [(TextureFormat, usize, BlockDecoder, i32, i32); 4] = [
+            (TextureFormat::Etc2Rgb8Unorm,  8,  |b, x, y| decode_etc(b, None, x, y),                0,  0),
+            (TextureFormat::Etc2Rgba8Unorm, 16, |b, x, y| decode_etc(&b[8..], Some(&b[..8]), x, y), 0,  2),
+            (TextureFormat::Bc1RgbaUnorm,   8,  decode_bc1,                                          12, 0),
+            (TextureFormat::Bc7RgbaUnorm,   16, decode_bc7,                                          4,  4),
+        ];
+
+        for (format, block_size, decode, color_tolerance, alpha_tolerance) in formats {
+            let data = transcoder.transcode(0, &slice, 8, 8, format).unwrap();
+            assert_eq!(data.len(), 4 * block_size, "{:?}", format);
+
+            for (index, &block) in blocks.iter().enumerate() {
+                for texel in 0..16 {
+                    let (x, y)  = (texel % 4, texel / 4);
+                    let decoded = decode(&data[index * block_size..(index + 1) * block_size], x, y);
+                    let color   = texel_rgba(block, x, y, block_size == 16);
+                    let error   = |c: usize| (decoded[c] as i32 - color[c] as i32).abs();
+                    assert!((0..3).all(|c| error(c) <= color_tolerance) && error(3) <= alpha_tolerance,
+                            "{:?} block {} texel {}: {:?} vs {:?}", format, index, texel, decoded, color);
+                }
+            }
+        }
+    }
+
+    fn texel_rgba(block: (u16, u16), x: usize, y: usize, alpha: bool) -> [u8; 4] {
+        let [r, g, b] = texel(block, x, y);
+        [r, g, b, if alpha { g } else { 255 }]
+    }
+
+    #[test]
+    fn astc_trits_have_one_encoding_each() {
+        let mut decoded = (0..=255u8).map(astc_decode_trits).collect::<Vec<_>>();
+        decoded.sort_unstable();
+        decoded.dedup();
+        assert_eq!(decoded.len(), 3usize.pow(5));
+
+        // ---> Range 48 codes cover the whole 8 bit range:
+        let levels = astc_levels_48();
+        assert_eq!((levels[0], levels[1]), (0, 255));
+        assert_eq!(levels.iter().collect::<std::collections::HashSet<_>>().len(), 48);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn gpu_decodes_every_target_close_to_the_codebooks() {
+        use crate::gpu::GPU;
+        use crate::texture::SamplerKey;
+        use crate::texture::TextureLoader;
+        use crate::texture::TextureSettings;
+        use crate::texture::create_render_target;
+        use crate::texture::create_texture_from_levels;
+        use wgpu::TextureFormat;
+
+        let gpu    = GPU::for_tests(8, 8);
+        let loader = TextureLoader::new(&gpu.device, &gpu.shaders, TextureSettings { generate_mipmaps: false, anisotropy: 1, streaming: None });
+        let target = create_render_target(&gpu.device, 8, 8, TextureFormat::Rgba8Unorm, Some("Test Target"));
+
+        let slice  = slice_8x8();
+        let length = slice.len() as u32;
+        let opaque = Etc1sTranscoder::new(&global_data(&[((0, length), (0, 0))]), 1).unwrap();
+        let alpha  = Etc1sTranscoder::new(&global_data(&[((0, length), (0, length))]), 1).unwrap();
+        let blocks = [(2, 0), (0, 1), (2, 1), (0, 0)];
+
+        // ---> (transcoder, format, colour and alpha tolerance), the driver decodes the blocks:
+        let formats = [
+            (&opaque, TextureFormat::Etc2Rgb8Unorm,  1,  0),
+            (&alpha,  TextureFormat::Etc2Rgba8Unorm, 1,  2),
+            (&opaque, TextureFormat::Bc1RgbaUnorm,   12, 0),
+            (&alpha,  TextureFormat::Bc7RgbaUnorm,   4,  4),
+            (&opaque, ASTC_4X4,                      4,  0),
+            (&alpha,  ASTC_4X4,                      6,  6),
+        ];
+
+        for (transcoder, format, color_tolerance, alpha_tolerance) in formats {
+            if !gpu.device.features().contains(format.required_features()) {
+                println!("Adapter can not sample {:?}, skipped", format);
+                continue;
+            }
+            let data    = transcoder.transcode(0, &slice, 8, 8, format).unwrap();
+            let texture = create_texture_from_levels(&gpu.device, &gpu.queue, &loader, &SamplerKey::clamped(),
+                                                     &[data], 8, 8, format, Some("Test")).unwrap();
+
+            let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            loader.mipmaps.blit(&gpu.device, &mut encoder, &texture.view, &target.view, target.texture.format());
+            gpu.queue.submit(Some(encoder.finish()));
+            let image = gpu.read_texture(&target.texture).unwrap();
+
+            for (x, y, pixel) in image.enumerate_pixels() {
+                let (x, y)   = (x as usize, y as usize);
+                let expected = texel_rgba(blocks[(y / 4) * 2 + x / 4], x % 4, y % 4, transcoder.has_alpha());
+                let error    = |c: usize| (pixel.0[c] as i32 - expected[c] as i32).abs();
+                assert!((0..3).all(|c| error(c) <= color_tolerance) && error(3) <= alpha_tolerance,
+                        "{:?} texel {}, {}: {:?} vs {:?}", format, x, y, pixel.0, expected);
+            }
+        }
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
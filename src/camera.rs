@@ -31,23 +31,27 @@ impl Camera {
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
-    view_proj: [[f32; 4]; 4],
-    position : [f32; 3],
-    _padding : f32,
+    view_proj    : [[f32; 4]; 4],
+    position     : [f32; 3],
+    _padding     : f32,
+    inv_view_proj: [[f32; 4]; 4],  // For world position reconstruction from depth...
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
-            view_proj: glm::Mat4::identity().into(),
-            position : [0.0, 0.0, 0.0],
-            _padding : 0.0,
+            view_proj    : glm::Mat4::identity().into(),
+            position     : [0.0, 0.0, 0.0],
+            _padding     : 0.0,
+            inv_view_proj: glm::Mat4::identity().into(),
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
-        self.position  = camera.eye.into();
+        let view_proj      = camera.build_view_projection_matrix();
+        self.view_proj     = view_proj.into();
+        self.position      = camera.eye.into();
+        self.inv_view_proj = glm::inverse(&view_proj).into();
     }
 }
 ///// CAMERA UNIFORM STRUCTURE /////////////////////////////////////////////////////////////////////
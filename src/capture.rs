@@ -0,0 +1,291 @@
+/*
+
+    Screenshot and frame sequence capture.
+
+    F12 -> screenshots/screenshot_<timestamp>.png
+    F11 -> start/stop recording every frame to recordings/<timestamp>/frame_000000.png
+           (the simulation runs at a fixed timestep while recording, so the frames form a video).
+
+*/
+
+use std::path::PathBuf;
+use std::sync::mpsc::SyncSender;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use std::time::SystemTime;
+
+
+/// Frames that may wait for the writer thread; more block the render loop until one is written
+/// (a recording must not lose frames, and the read-back images must not pile up in memory).
+pub const MAX_PENDING_FRAMES: usize = 4;
+
+
+///// RECORDING STRUCTURE //////////////////////////////////////////////////////////////////////////
+pub struct Recording {
+    pub directory  : PathBuf,
+    pub frame_index: u32,
+    pub frame_rate : f32,
+}
+///// RECORDING STRUCTURE //////////////////////////////////////////////////////////////////////////
+
+
+///// FRAME WRITER STRUCTURE ///////////////////////////////////////////////////////////////////////
+struct FrameJob {
+    image: image::RgbaImage,
+    paths: Vec<(PathBuf, bool)>,  // (path, announce)
+}
+
+/// One background thread that encodes and writes the captured frames (PNG encoding is slow).
+struct FrameWriter {
+    sender: SyncSender<FrameJob>,
+    worker: JoinHandle<()>,
+}
+
+impl FrameWriter {
+    fn spawn() -> Self {
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<FrameJob>(MAX_PENDING_FRAMES);
+        let worker = std::thread::Builder::new()
+            .name("Frame Writer".to_string())
+            .spawn(move || {
+                for job in receiver {
+                    for (path, announce) in job.paths {
+                        match job.image.save(&path) {
+                            Ok(_) if announce => println!("Saved {}", path.display()),
+                            Ok(_)             => {}
+                            Err(e)            => eprintln!("Failed to save {}: {}", path.display(), e),
+                        }
+                    }
+                }
+            })
+            .expect("Failed to spawn frame writer thread!");
+
+        Self { sender, worker }
+    }
+
+    /// Waits until every queued frame is on disk.
+    fn finish(self) {
+        drop(self.sender);
+        if self.worker.join().is_err() {
+            eprintln!("Frame writer thread panicked!");
+        }
+    }
+}
+///// FRAME WRITER STRUCTURE ///////////////////////////////////////////////////////////////////////
+
+
+///// FRAME CAPTURE STRUCTURE //////////////////////////////////////////////////////////////////////
+pub struct FrameCapture {
+    pub screenshot_requested: bool,
+    pub recording           : Option<Recording>,
+    pub recording_frame_rate: f32,
+    pub screenshot_directory: PathBuf,
+    pub recording_directory : PathBuf,
+    writer                  : Option<FrameWriter>,  // Started with the first captured frame
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        Self {
+            screenshot_requested: false,
+            recording           : None,
+            recording_frame_rate: 60.0,
+            screenshot_directory: PathBuf::from("screenshots"),
+            recording_directory : PathBuf::from("recordings"),
+            writer              : None,
+        }
+    }
+
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    pub fn toggle_recording(&mut self) {
+        match self.recording.take() {
+            Some(recording) => {
+                println!("Recording stopped: {} frames in {}",
+                         recording.frame_index, recording.directory.display());
+            }
+            None => {
+                let directory = self.recording_directory.join(timestamp());
+                println!("Recording started: {} ({} fps)",
+                         directory.display(), self.recording_frame_rate);
+                self.recording = Some(Recording {
+                    directory,
+                    frame_index: 0,
+                    frame_rate : self.recording_frame_rate,
+                });
+            }
+        }
+    }
+
+    /// Does the current frame have to be read back?
+    pub fn wants_frame(&self) -> bool {
+        self.screenshot_requested || self.recording.is_some()
+    }
+
+    /// Simulated frame time while recording (None = real time).
+    pub fn fixed_timestep(&self) -> Option<Duration> {
+        self.recording.as_ref()
+                      .map(|recording| Duration::from_secs_f32(1.0 / recording.frame_rate))
+    }
+
+    /// Queues the frame for the writer thread, blocks while `MAX_PENDING_FRAMES` are waiting.
+    pub fn save_frame(&mut self, image: image::RgbaImage) -> anyhow::Result<()> {
+        let paths = self.frame_paths()?;
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let writer = self.writer.get_or_insert_with(FrameWriter::spawn);
+        writer.sender.send(FrameJob { image, paths })
+                     .map_err(|_| anyhow::anyhow!("Frame writer thread is gone!"))
+    }
+
+    /// Blocks until every queued frame has been written.
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            writer.finish();
+        }
+    }
+
+    // ---> Output files of the current frame, advances the recording:
+    fn frame_paths(&mut self) -> anyhow::Result<Vec<(PathBuf, bool)>> {
+        let mut paths = Vec::new();  // (path, announce)
+
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            std::fs::create_dir_all(&self.screenshot_directory)?;
+            let path = self.screenshot_directory.join(format!("screenshot_{}.png", timestamp()));
+            paths.push((path, true));
+        }
+
+        if let Some(recording) = &mut self.recording {
+            std::fs::create_dir_all(&recording.directory)?;
+            let path = recording.directory.join(format!("frame_{:06}.png", recording.frame_index));
+            paths.push((path, false));
+            recording.frame_index += 1;
+        }
+
+        Ok(paths)
+    }
+}
+
+impl Drop for FrameCapture {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+///// FRAME CAPTURE STRUCTURE //////////////////////////////////////////////////////////////////////
+
+
+///// TIMESTAMP PROCEDURE //////////////////////////////////////////////////////////////////////////
+/// UTC timestamp like `20260118_142501_123` (no chrono dependency needed).
+pub fn timestamp() -> String {
+    format_timestamp(SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default())
+}
+
+fn format_timestamp(since_epoch: Duration) -> String {
+    let seconds     = since_epoch.as_secs();
+    let millis      = since_epoch.subsec_millis();
+
+    // ---> Civil date from days since 1970-01-01 (Howard Hinnant's algorithm):
+    let days  = (seconds / 86400) as i64 + 719468;
+    let era   = days.div_euclid(146097);
+    let doe   = days.rem_euclid(146097);
+    let yoe   = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy   = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp    = (5 * doy + 2) / 153;
+    let day   = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year  = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let time = seconds % 86400;
+    format!("{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}",
+            year, month, day, time / 3600, (time % 3600) / 60, time % 60, millis)
+}
+///// TIMESTAMP PROCEDURE //////////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("seal_capture_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn gradient(width: u32, height: u32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, height, |x, y| image::Rgba([x as u8 * 16, y as u8 * 16, 128, 255]))
+    }
+
+    #[test]
+    fn timestamps_are_utc_civil_dates() {
+        assert_eq!(format_timestamp(Duration::ZERO), "19700101_000000_000");
+        // ---> 2024-02-29 23:59:58.250 (leap day):
+        assert_eq!(format_timestamp(Duration::from_millis(1_709_251_198_250)), "20240229_235958_250");
+    }
+
+    #[test]
+    fn recordings_number_frames_in_sequence() {
+        let directory   = temp_dir("naming");
+        let mut capture = FrameCapture::new();
+        capture.screenshot_directory = directory.join("screenshots");
+        capture.recording_directory  = directory.join("recordings");
+
+        assert!(!capture.wants_frame());
+        assert!(capture.frame_paths().unwrap().is_empty());
+
+        capture.toggle_recording();
+        capture.request_screenshot();
+        assert_eq!(capture.fixed_timestep(), Some(Duration::from_secs_f32(1.0 / 60.0)));
+
+        // ---> Screenshot (announced) and the first recorded frame:
+        let paths = capture.frame_paths().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].0.starts_with(&capture.screenshot_directory));
+        assert!(paths[0].0.file_name().unwrap().to_string_lossy().starts_with("screenshot_"));
+        assert!(paths[0].1);
+        assert_eq!(paths[1].0.file_name().unwrap(), "frame_000000.png");
+        assert!(!paths[1].1);
+
+        // ---> Screenshots are one-shot, the recording continues:
+        let paths = capture.frame_paths().unwrap();
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].0.file_name().unwrap(), "frame_000001.png");
+
+        let recording = capture.recording.as_ref().unwrap();
+        assert!(recording.directory.starts_with(&capture.recording_directory));
+        assert_eq!(recording.frame_index, 2);
+
+        capture.toggle_recording();
+        assert!(!capture.wants_frame());
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn queued_frames_are_encoded_to_png() {
+        let directory   = temp_dir("encode");
+        let mut capture = FrameCapture::new();
+        capture.recording_directory = directory.clone();
+        capture.toggle_recording();
+
+        // ---> More frames than the queue holds, none may be lost:
+        let frame_count = MAX_PENDING_FRAMES as u32 * 2 + 1;
+        for _ in 0..frame_count {
+            capture.save_frame(gradient(16, 8)).unwrap();
+        }
+        let recording = capture.recording.as_ref().unwrap().directory.clone();
+        capture.flush();
+
+        for index in 0..frame_count {
+            let path  = recording.join(format!("frame_{:06}.png", index));
+            let image = image::open(&path).unwrap().to_rgba8();
+            assert_eq!(image, gradient(16, 8), "{}", path.display());
+        }
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
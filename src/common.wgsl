@@ -0,0 +1,97 @@
+// Shared by shader.wgsl and custom_material.wgsl (#include, see shader_library.rs):
+// uniforms of the camera and model, the vertex layout and the vertex shader.
+//
+// INSTANCING: the model transform comes from the per-instance buffer (instance.rs) instead of
+// the model uniform.
+
+///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
+struct CameraUniform {
+    view_proj    : mat4x4<f32>,
+    position     : vec3<f32>,    // Camera position for specular...
+    _pad         : f32,
+    inv_view_proj: mat4x4<f32>,  // Used by the deferred lighting pass...
+};
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+
+struct ModelUniform {
+    model        : mat4x4<f32>,
+    normal_matrix: mat3x3<f32>,  // Inverse transpose for normals...
+};
+@group(1) @binding(0) var<uniform> model: ModelUniform;
+///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
+
+///// INPUT / OUTPUT STRUCTURES ////////////////////////////////////////////////////////////////////
+// ---> Input Vertex Structure (vertex.rs):
+struct VertexInput {
+    @location(0) position    : vec3<f32>,
+    @location(1) normal      : vec3<f32>,
+    @location(2) tex_coords  : vec2<f32>,
+    @location(3) tangent     : vec3<f32>,
+    @location(4) bitangent   : vec3<f32>,
+    @location(5) tex_coords_1: vec2<f32>,
+    @location(6) color       : vec4<f32>,
+};
+
+#ifdef INSTANCING
+// ---> Input Instance Structure (instance.rs):
+struct InstanceInput {
+    @location(7)  model_0 : vec4<f32>,
+    @location(8)  model_1 : vec4<f32>,
+    @location(9)  model_2 : vec4<f32>,
+    @location(10) model_3 : vec4<f32>,
+    @location(11) normal_0: vec4<f32>,
+    @location(12) normal_1: vec4<f32>,
+    @location(13) normal_2: vec4<f32>,
+};
+#endif
+
+// ---> Output from vertex shader:
+struct VertexOutput {
+    @builtin(position)                             clip_position: vec4<f32>,
+    @location(0) @interpolate(perspective, center) frag_pos     : vec3<f32>,
+    @location(1) @interpolate(perspective, center) tex_coords   : vec2<f32>,
+    @location(2) @interpolate(perspective, center) tangent      : vec3<f32>,
+    @location(3) @interpolate(perspective, center) bitangent    : vec3<f32>,
+    @location(4) @interpolate(perspective, center) normal       : vec3<f32>,
+    @location(5) @interpolate(perspective, center) tex_coords_1 : vec2<f32>,
+    @location(6) @interpolate(perspective, center) color        : vec4<f32>,
+}
+
+// ---> Output into the G-buffer (deferred path):
+struct GBufferOutput {
+    @location(0) albedo  : vec4<f32>,  // rgb = base color, a = alpha
+    @location(1) normal  : vec4<f32>,  // xyz = world normal
+    @location(2) material: vec4<f32>,  // r = metallic, g = roughness, b = occlusion
+    @location(3) emissive: vec4<f32>,  // rgb = emitted radiance
+}
+///// INPUT / OUTPUT STRUCTURES ////////////////////////////////////////////////////////////////////
+
+///// VERTEX SHADER ////////////////////////////////////////////////////////////////////////////////
+@vertex
+#ifdef INSTANCING
+fn vs_main(vertex: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model_matrix  = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let normal_matrix = mat3x3<f32>(instance.normal_0.xyz, instance.normal_1.xyz, instance.normal_2.xyz);
+#else
+fn vs_main(vertex: VertexInput) -> VertexOutput {
+    let model_matrix  = model.model;
+    let normal_matrix = model.normal_matrix;
+#endif
+    var out: VertexOutput;
+
+    // ---> World space transformation:
+    let world_position = model_matrix * vec4<f32>(vertex.position, 1.0);
+    out.clip_position  = camera.view_proj * world_position;
+    out.frag_pos       = world_position.xyz;
+    out.tex_coords     = vertex.tex_coords;
+    out.tex_coords_1   = vertex.tex_coords_1;
+    out.color          = vertex.color;
+
+    // ---> Construction of TBN Matrix:
+    out.tangent   = normalize(normal_matrix * vertex.tangent);
+    out.bitangent = normalize(normal_matrix * vertex.bitangent);
+    out.normal    = normalize(normal_matrix * vertex.normal);
+
+    return out;
+}
+///// VERTEX SHADER ////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,464 @@
+/*
+
+    User defined materials with their own WGSL fragment code.
+
+    A `MaterialDefinition` declares the parameters and textures of a material next to its
+    fragment code. `CustomShader` turns it into a shader module of its own (the prelude in
+    `custom_material.wgsl`, the generated declarations and the user code) and a matching bind
+    group layout; the pipelines are created on first use by the `PipelineCache`. A
+    `CustomMaterial` is one set of parameter values and textures for a shader, and replaces the
+    glTF material of a mesh through `SceneGraph::set_material_override`.
+
+    The user code sees `camera`, `model`, `VertexOutput`, its parameters as `params.<name>` and
+    every texture as `<name>_texture` + `<name>_sampler`. It has to provide
+
+        fn material_color(in: VertexOutput) -> vec4<f32>
+
+    which returns the final colour: custom materials are not lit by the engine, in the deferred
+    path they are stored as emission (like KHR_materials_unlit materials).
+
+    Bind group layout (@group(2)):
+        binding 0              -> parameter uniform
+        binding 1 + 2 * i      -> texture i
+        binding 2 + 2 * i      -> sampler i
+
+*/
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use anyhow::bail;
+use anyhow::Context;
+use wgpu::util::DeviceExt;
+
+use crate::pipeline::AlphaMode;
+use crate::pipeline::PassKind;
+use crate::pipeline::PipelineKey;
+use crate::shader_library;
+use crate::shader_library::ShaderDefines;
+use crate::shader_library::ShaderSource;
+use crate::shader_library::SHADER_ROOT;
+use crate::texture::Texture;
+
+
+///// PARAMETER TYPE ENUM //////////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+}
+
+impl ParamType {
+    pub fn components(self) -> usize {
+        match self {
+            ParamType::Float => 1,
+            ParamType::Vec2  => 2,
+            ParamType::Vec3  => 3,
+            ParamType::Vec4  => 4,
+        }
+    }
+
+    fn wgsl(self) -> &'static str {
+        match self {
+            ParamType::Float => "f32",
+            ParamType::Vec2  => "vec2<f32>",
+            ParamType::Vec3  => "vec3<f32>",
+            ParamType::Vec4  => "vec4<f32>",
+        }
+    }
+
+    /// Alignment in a uniform buffer (WGSL: vec3 aligns like vec4).
+    fn align(self) -> usize {
+        match self {
+            ParamType::Float                  => 4,
+            ParamType::Vec2                   => 8,
+            ParamType::Vec3 | ParamType::Vec4 => 16,
+        }
+    }
+}
+///// PARAMETER TYPE ENUM //////////////////////////////////////////////////////////////////////////
+
+
+///// MATERIAL DEFINITION STRUCTURE ////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialParam {
+    pub name   : String,
+    pub ty     : ParamType,
+    pub default: [f32; 4],  // Only the first `ty.components()` values are used
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialDefinition {
+    pub name        : String,
+    pub source      : String,               // WGSL, defines `material_color`
+    pub params      : Vec<MaterialParam>,   // Uniform members, in declaration order
+    pub textures    : Vec<String>,
+    pub alpha_mode  : AlphaMode,
+    pub alpha_cutoff: f32,                  // Only used with AlphaMode::Mask
+    pub double_sided: bool,
+}
+
+impl MaterialDefinition {
+    pub fn new(name: &str, source: &str) -> Self {
+        Self {
+            name        : name.to_string(),
+            source      : source.to_string(),
+            params      : Vec::new(),
+            textures    : Vec::new(),
+            alpha_mode  : AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+
+    pub fn with_param(mut self, name: &str, ty: ParamType, default: &[f32]) -> Self {
+        let mut values = [0.0; 4];
+        for (value, default) in values.iter_mut().zip(default) {
+            *value = *default;
+        }
+        self.params.push(MaterialParam { name: name.to_string(), ty, default: values });
+        self
+    }
+
+    pub fn with_texture(mut self, name: &str) -> Self {
+        self.textures.push(name.to_string());
+        self
+    }
+
+    pub fn with_alpha_mode(mut self, alpha_mode: AlphaMode, alpha_cutoff: f32) -> Self {
+        self.alpha_mode   = alpha_mode;
+        self.alpha_cutoff = alpha_cutoff;
+        self
+    }
+
+    pub fn with_double_sided(mut self, double_sided: bool) -> Self {
+        self.double_sided = double_sided;
+        self
+    }
+
+    /// Byte offset of every parameter in the uniform buffer and the buffer size.
+    pub fn param_layout(&self) -> (Vec<usize>, usize) {
+        let mut offsets = Vec::with_capacity(self.params.len());
+        let mut size    = 0usize;
+        for param in &self.params {
+            let offset = size.next_multiple_of(param.ty.align());
+            offsets.push(offset);
+            size = offset + 4 * param.ty.components();
+        }
+
+        // ---> Uniform buffers can't be empty, an empty struct gets a padding member:
+        (offsets, size.max(16).next_multiple_of(16))
+    }
+
+    /// Parameter, texture and name errors are reported here, WGSL errors by `CustomShader::new`.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let names = self.params.iter().map(|param| &param.name).chain(&self.textures).collect::<Vec<_>>();
+        for (i, name) in names.iter().enumerate() {
+            let mut chars = name.chars();
+            if !chars.next().is_some_and(|c| c.is_ascii_alphabetic()) ||
+               !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!("Material '{}': '{}' is not a valid identifier", self.name, name);
+            }
+            if names[..i].contains(name) {
+                bail!("Material '{}': '{}' is declared twice", self.name, name);
+            }
+        }
+        if !self.source.contains("fn material_color") {
+            bail!("Material '{}': the source does not define material_color", self.name);
+        }
+        Ok(())
+    }
+
+    /// Generated declarations of the parameters, textures and alpha mode (appended to the prelude).
+    pub fn declarations(&self) -> String {
+        let mut wgsl = String::from("struct MaterialParams {\n");
+        for param in &self.params {
+            wgsl += &format!("    {}: {},\n", param.name, param.ty.wgsl());
+        }
+        if self.params.is_empty() {
+            wgsl += "    _padding: vec4<f32>,\n";
+        }
+        wgsl += "};\n@group(2) @binding(0) var<uniform> params: MaterialParams;\n";
+
+        for (i, texture) in self.textures.iter().enumerate() {
+            wgsl += &format!("@group(2) @binding({}) var {}_texture: texture_2d<f32>;\n", 1 + 2 * i, texture);
+            wgsl += &format!("@group(2) @binding({}) var {}_sampler: sampler;\n",         2 + 2 * i, texture);
+        }
+
+        wgsl += &format!("const ALPHA_MASK  : bool = {};\n", self.alpha_mode == AlphaMode::Mask);
+        wgsl += &format!("const ALPHA_CUTOFF: f32  = {:?};\n", self.alpha_cutoff);
+        wgsl
+    }
+}
+///// MATERIAL DEFINITION STRUCTURE ////////////////////////////////////////////////////////////////
+
+
+///// CUSTOM SHADER STRUCTURE //////////////////////////////////////////////////////////////////////
+static NEXT_SHADER_ID: AtomicU64 = AtomicU64::new(1);  // 0 = shader.wgsl (see PipelineKey)
+
+#[derive(Debug)]
+pub struct CustomShader {
+    pub id               : u64,
+    pub definition       : MaterialDefinition,
+    pub module           : wgpu::ShaderModule,
+    pub instanced_module : wgpu::ShaderModule,  // INSTANCING permutation (see pipeline.rs)
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    offsets              : Vec<usize>,
+    uniform_size         : usize,
+}
+
+impl CustomShader {
+    pub fn new(device: &wgpu::Device, definition: MaterialDefinition) -> anyhow::Result<Arc<Self>> {
+        definition.validate()?;
+
+        // ---> Prelude + declarations + user code, compile errors are returned instead of panicking:
+        let compile = |defines: &ShaderDefines| -> anyhow::Result<wgpu::ShaderModule> {
+            let mut source = ShaderSource::load(Path::new(SHADER_ROOT), "custom_material.wgsl", defines)?;
+            source.append(&format!("{} (declarations)", definition.name), &definition.declarations());
+            source.append(&definition.name, &definition.source);
+            shader_library::compile(device, &format!("Custom Material Shader: {}", definition.name), &source)
+                           .with_context(|| format!("Material '{}'", definition.name))
+        };
+        let module           = compile(&ShaderDefines::new())?;
+        let instanced_module = compile(&ShaderDefines::new().with("INSTANCING"))?;
+
+        // ---> Uniform, then texture + sampler per declared texture:
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding   : 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty        : wgpu::BindingType::Buffer {
+                ty                : wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size  : None,
+            },
+            count     : None,
+        }];
+        for i in 0..definition.textures.len() as u32 {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding   : 1 + 2 * i,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty        : wgpu::BindingType::Texture {
+                    sample_type   : wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled  : false,
+                },
+                count     : None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding   : 2 + 2 * i,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count     : None,
+            });
+        }
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label  : Some(&format!("Custom Material Bind Group Layout: {}", definition.name)),
+                entries: &entries,
+            },
+        );
+
+        let (offsets, uniform_size) = definition.param_layout();
+        Ok(Arc::new(Self {
+            id: NEXT_SHADER_ID.fetch_add(1, Ordering::Relaxed),
+            definition,
+            module,
+            instanced_module,
+            bind_group_layout,
+            offsets,
+            uniform_size,
+        }))
+    }
+
+    /// Key of the pipeline variant for `pass` (see `PipelineCache::get_custom`).
+    pub fn pipeline_key(&self, pass: PassKind) -> PipelineKey {
+        PipelineKey {
+            shader: self.id,
+            ..PipelineKey::new(pass, self.definition.double_sided, self.definition.alpha_mode, true)
+        }
+    }
+
+    /// Uniform buffer contents with every parameter at its default.
+    fn default_uniform(&self) -> Vec<u8> {
+        let mut data = vec![0u8; self.uniform_size];
+        for (param, &offset) in self.definition.params.iter().zip(&self.offsets) {
+            let values = &param.default[..param.ty.components()];
+            data[offset..offset + 4 * values.len()].copy_from_slice(bytemuck::cast_slice(values));
+        }
+        data
+    }
+}
+///// CUSTOM SHADER STRUCTURE //////////////////////////////////////////////////////////////////////
+
+
+///// CUSTOM MATERIAL STRUCTURE ////////////////////////////////////////////////////////////////////
+#[derive(Debug)]
+pub struct CustomMaterial {
+    pub name      : String,
+    pub shader    : Arc<CustomShader>,
+    _textures     : Vec<Arc<Texture>>,  // Bound in declaration order, kept alive with the bind group
+    uniform_buffer: wgpu::Buffer,
+    bind_group    : wgpu::BindGroup,
+}
+
+impl CustomMaterial {
+    pub fn new(device  : &wgpu::Device,
+               shader  : &Arc<CustomShader>,
+               textures: Vec<Arc<Texture>>,
+               name    : &str) -> anyhow::Result<Self> {
+        let definition = &shader.definition;
+        if textures.len() != definition.textures.len() {
+            bail!("Material '{}': '{}' declares {} textures, got {}",
+                  name, definition.name, definition.textures.len(), textures.len());
+        }
+
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label   : Some(&format!("Custom Material Uniform Buffer: {}", name)),
+                contents: &shader.default_uniform(),
+                usage   : wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding : 0,
+            resource: uniform_buffer.as_entire_binding(),
+        }];
+        for (i, texture) in textures.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding : 1 + 2 * i as u32,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding : 2 + 2 * i as u32,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label  : Some(&format!("Custom Material Bind Group: {}", name)),
+                layout : &shader.bind_group_layout,
+                entries: &entries,
+            },
+        );
+
+        Ok(Self {
+            name          : name.to_string(),
+            shader        : shader.clone(),
+            _textures     : textures,
+            uniform_buffer,
+            bind_group,
+        })
+    }
+
+    /// Writes a parameter, `value` needs as many components as its declared type.
+    pub fn set_param(&self, queue: &wgpu::Queue, name: &str, value: &[f32]) -> anyhow::Result<()> {
+        let definition = &self.shader.definition;
+        let Some(index) = definition.params.iter().position(|param| param.name == name) else {
+            bail!("Material '{}': unknown parameter '{}'", self.name, name);
+        };
+        let param = &definition.params[index];
+        if value.len() != param.ty.components() {
+            bail!("Material '{}': '{}' is a {:?}, got {} values", self.name, name, param.ty, value.len());
+        }
+
+        queue.write_buffer(&self.uniform_buffer, self.shader.offsets[index] as u64, bytemuck::cast_slice(value));
+        Ok(())
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+///// CUSTOM MATERIAL STRUCTURE ////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::BlendMode;
+    use crate::state::RenderPath;
+    use crate::state::State;
+
+    const SOLID: &str = "fn material_color(in: VertexOutput) -> vec4<f32> { return params.tint; }";
+
+    #[test]
+    fn params_are_laid_out_like_wgsl_uniforms() {
+        let definition = MaterialDefinition::new("Layout", SOLID)
+                                            .with_param("a", ParamType::Float, &[1.0])
+                                            .with_param("b", ParamType::Vec3,  &[1.0, 2.0, 3.0])
+                                            .with_param("c", ParamType::Vec2,  &[0.0, 0.0])
+                                            .with_param("d", ParamType::Float, &[0.0])
+                                            .with_param("tint", ParamType::Vec4, &[0.0; 4]);
+        assert_eq!(definition.param_layout(), (vec![0, 16, 32, 40, 48], 64));
+        assert_eq!(MaterialDefinition::new("Empty", SOLID).param_layout(), (vec![], 16));
+        assert!(definition.declarations().contains("    b: vec3<f32>,\n"));
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        let base = || MaterialDefinition::new("Invalid", SOLID).with_param("tint", ParamType::Vec4, &[1.0; 4]);
+        assert!(base().validate().is_ok());
+        assert!(base().with_texture("tint").validate().is_err());
+        assert!(base().with_texture("2d").validate().is_err());
+        assert!(base().with_param("tint color", ParamType::Float, &[0.0]).validate().is_err());
+        assert!(MaterialDefinition::new("Invalid", "fn main() {}").validate().is_err());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn alpha_mode_and_double_sided_pick_the_pipeline_variant() {
+        let gpu    = crate::gpu::GPU::for_tests(1, 1);
+        let opaque = CustomShader::new(&gpu.device, MaterialDefinition::new("Opaque", SOLID)
+                                                        .with_param("tint", ParamType::Vec4, &[1.0; 4])).unwrap();
+        let glass  = CustomShader::new(&gpu.device, MaterialDefinition::new("Glass", SOLID)
+                                                        .with_param("tint", ParamType::Vec4, &[1.0; 4])
+                                                        .with_alpha_mode(AlphaMode::Blend, 0.5)
+                                                        .with_double_sided(true)).unwrap();
+
+        let key = opaque.pipeline_key(PassKind::Forward);
+        assert_eq!((key.alpha_mode, key.blend, key.cull_mode), (AlphaMode::Opaque, BlendMode::Replace, Some(wgpu::Face::Back)));
+        let key = glass.pipeline_key(PassKind::Forward);
+        assert_eq!((key.alpha_mode, key.blend, key.cull_mode), (AlphaMode::Blend, BlendMode::Alpha, None));
+        assert_ne!(key.shader, opaque.pipeline_key(PassKind::Forward).shader);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn custom_material_overrides_mesh_in_both_render_paths() {
+        let mut state = State::for_tests(32, 32, "tests/golden/scenes/cube.gltf");
+        let device     = state.gpu.device.clone();
+        let definition = MaterialDefinition::new("Solid", SOLID).with_param("tint", ParamType::Vec4, &[0.0, 1.0, 0.0, 1.0]);
+
+        // ---> Broken WGSL is an error, not a panic:
+        let broken = MaterialDefinition::new("Broken", "fn material_color(in: VertexOutput) -> vec4<f32> { return 1; }");
+        let error  = CustomShader::new(&device, broken).unwrap_err();
+        assert!(format!("{:#}", error).contains("Broken:1:"), "{:#}", error);
+
+        let shader   = CustomShader::new(&device, definition).unwrap();
+        let material = Arc::new(CustomMaterial::new(&device, &shader, Vec::new(), "Magenta").unwrap());
+        material.set_param(&state.gpu.queue, "tint", &[1.0, 0.0, 1.0, 1.0]).unwrap();
+        assert!(material.set_param(&state.gpu.queue, "tint", &[1.0]).is_err());
+
+        let node = state.model_node.unwrap();
+        assert!(state.set_material_override(node, 99, Some(material.clone())).is_err());
+        state.set_material_override(node, 0, Some(material)).unwrap();
+        state.set_camera(nalgebra_glm::vec3(3.0, 2.5, 5.0), nalgebra_glm::vec3(0.0, 0.0, 0.0));
+
+        for path in [RenderPath::Forward, RenderPath::Deferred] {
+            state.render_path = path;
+            let image = state.render_to_image().unwrap();
+            assert_eq!(image.get_pixel(16, 16).0, [255, 0, 255, 255], "{:?}", path);
+        }
+
+        // ---> Back to the glTF material:
+        state.set_material_override(node, 0, None).unwrap();
+        let image = state.render_to_image().unwrap();
+        assert_ne!(image.get_pixel(16, 16).0, [255, 0, 255, 255]);
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,38 @@
+// Prelude of custom material shaders (see custom_material.rs). The engine appends the generated
+// parameter / texture declarations (@group(2)) and the user code, which provides
+//
+//     fn material_color(in: VertexOutput) -> vec4<f32>
+
+#include "common.wgsl"
+
+///// FRAGMENT SHADERS /////////////////////////////////////////////////////////////////////////////
+// ---> ALPHA_MASK and ALPHA_CUTOFF are generated from the definition:
+fn alpha_test(alpha: f32) {
+    if ALPHA_MASK && alpha < ALPHA_CUTOFF {
+        discard;
+    }
+}
+
+@fragment
+fn fs_custom(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = material_color(in);
+    alpha_test(color.a);
+    return color;
+}
+
+// ---> Deferred: stored as emission over a black albedo, like unlit materials (shader.wgsl):
+@fragment
+fn fs_gbuffer_custom(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> GBufferOutput {
+    var out: GBufferOutput;
+
+    let color = material_color(in);
+    alpha_test(color.a);
+
+    out.albedo   = vec4<f32>(0.0, 0.0, 0.0, color.a);
+    out.normal   = vec4<f32>(select(-in.normal, in.normal, front_facing), 0.0);
+    out.material = vec4<f32>(0.0, 1.0, 1.0, 0.0);
+    out.emissive = vec4<f32>(color.rgb, 0.0);
+
+    return out;
+}
+///// FRAGMENT SHADERS /////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,331 @@
+/*
+
+    Deferred rendering path: geometry pass into a G-buffer + fullscreen lighting pass.
+    Alpha blended and transmissive meshes can't be stored in the G-buffer, they are shaded forward
+    on top. So are materials with clearcoat, sheen or specular parameters (Material::is_forward_only).
+
+*/
+
+use crate::gpu::GPU;
+use nalgebra_glm as glm;
+
+use crate::model::Model;
+use crate::pipeline::PassKind;
+use crate::pipeline::PipelineCache;
+use crate::render_graph::RenderGraph;
+use crate::render_graph::ResourceHandle;
+use crate::render_graph::TextureDesc;
+use crate::shader_library::ShaderDefines;
+use crate::sky::Sky;
+use crate::texture::MipmapGenerator;
+use crate::transmission::SceneColor;
+
+
+///// G-BUFFER FORMATS /////////////////////////////////////////////////////////////////////////////
+pub const GBUFFER_ALBEDO_FORMAT  : wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+pub const GBUFFER_NORMAL_FORMAT  : wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+pub const GBUFFER_MATERIAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+pub const GBUFFER_EMISSIVE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+///// G-BUFFER FORMATS /////////////////////////////////////////////////////////////////////////////
+
+
+///// G-BUFFER STRUCTURE ///////////////////////////////////////////////////////////////////////////
+/// G-buffer targets are transient render graph resources, re-used (and aliased) across frames.
+#[derive(Debug, Clone, Copy)]
+pub struct GBuffer {
+    pub albedo  : ResourceHandle,  // Base color + alpha
+    pub normal  : ResourceHandle,  // World space normal
+    pub material: ResourceHandle,  // Metallic, roughness, occlusion
+    pub emissive: ResourceHandle,  // Emitted radiance
+}
+
+impl GBuffer {
+    pub fn declare(graph: &mut RenderGraph, width: u32, height: u32) -> Self {
+        Self {
+            albedo  : graph.create_texture("G-Buffer Albedo",
+                          TextureDesc::render_target(width, height, GBUFFER_ALBEDO_FORMAT)),
+            normal  : graph.create_texture("G-Buffer Normal",
+                          TextureDesc::render_target(width, height, GBUFFER_NORMAL_FORMAT)),
+            material: graph.create_texture("G-Buffer Material",
+                          TextureDesc::render_target(width, height, GBUFFER_MATERIAL_FORMAT)),
+            emissive: graph.create_texture("G-Buffer Emissive",
+                          TextureDesc::render_target(width, height, GBUFFER_EMISSIVE_FORMAT)),
+        }
+    }
+
+    pub fn handles(&self) -> [ResourceHandle; 4] {
+        [self.albedo, self.normal, self.material, self.emissive]
+    }
+
+    pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let color_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty        : wgpu::BindingType::Texture {
+                sample_type   : wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled  : false,
+            },
+            count     : None,
+        };
+
+        device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label  : Some("G-Buffer Bind Group Layout"),
+                entries: &[
+                    color_entry(0),  // Albedo
+                    color_entry(1),  // Normal
+                    color_entry(2),  // Metallic / roughness / occlusion
+                    color_entry(3),  // Emissive
+                    color_entry(4),  // Depth (as unfilterable float, works on GL too)
+                ],
+            },
+        )
+    }
+}
+///// G-BUFFER STRUCTURE ///////////////////////////////////////////////////////////////////////////
+
+
+///// DEFERRED RENDERER STRUCTURE //////////////////////////////////////////////////////////////////
+pub struct DeferredRenderer {
+    pub gbuffer_layout    : wgpu::BindGroupLayout,
+    pub lighting_pipeline : wgpu::RenderPipeline,
+}
+
+impl DeferredRenderer {
+    /// Geometry pipelines come from the `PipelineCache` (shared vertex stage and material bind
+    /// groups with the forward path).
+    pub fn new(gpu         : &GPU,
+               camera_bgl  : &wgpu::BindGroupLayout,
+               lighting_bgl: &wgpu::BindGroupLayout) -> Self {
+        let device = &gpu.device;
+
+        let gbuffer_layout = GBuffer::create_bind_group_layout(device);
+
+        // ---> Lighting pass reads the G-buffer in a fullscreen triangle:
+        let lighting_shader = gpu.load_shader("deferred.wgsl", &ShaderDefines::new());
+        let lighting_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label               : Some("Deferred Lighting Pipeline Layout"),
+                bind_group_layouts  : &[
+                    camera_bgl,       // @group(0)
+                    lighting_bgl,     // @group(1)
+                    &gbuffer_layout,  // @group(2)
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let lighting_pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label        : Some("Deferred Lighting Pipeline"),
+                layout       : Some(&lighting_layout),
+                vertex       : wgpu::VertexState {
+                    module             : &lighting_shader,
+                    entry_point        : Some("vs_fullscreen"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers            : &[],
+                },
+                primitive    : wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample  : wgpu::MultisampleState::default(),
+                fragment     : Some(wgpu::FragmentState {
+                    module             : &lighting_shader,
+                    entry_point        : Some("fs_lighting"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    targets            : &[Some(wgpu::ColorTargetState {
+                        format    : gpu.config.format,
+                        blend     : Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview    : None,
+                cache        : None,
+            },
+        );
+
+        Self { gbuffer_layout, lighting_pipeline }
+    }
+
+    /// Declares the geometry and lighting passes; the G-buffer is allocated by the graph.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_passes<'a>(&'a self,
+                          graph            : &mut RenderGraph<'a>,
+                          device           : &'a wgpu::Device,
+                          frame            : ResourceHandle,
+                          depth            : ResourceHandle,
+                          size             : (u32, u32),
+                          camera_bind_group: &'a wgpu::BindGroup,
+                          model_bind_group : &'a wgpu::BindGroup,
+                          light_bind_group : &'a wgpu::BindGroup,
+                          scene_color      : &'a SceneColor,
+                          mipmaps          : &'a MipmapGenerator,
+                          pipelines        : &'a PipelineCache,
+                          eye              : glm::Vec3,
+                          model            : Option<&'a Model>,
+                          sky              : Option<&'a Sky>) {
+        let gbuffer = GBuffer::declare(graph, size.0, size.1);
+
+        // ---> Transmission needs the opaque result in a readable target:
+        let transmission = model.is_some_and(|model| model.has_transmission());
+        let lit          = if transmission { scene_color.declare(graph, size.0, size.1) } else { frame };
+
+        // ---> Geometry pass (fill the G-buffer):
+        graph.add_pass("G-Buffer Pass", |builder| {
+            for handle in gbuffer.handles() {
+                builder.write(handle);
+            }
+            builder.write(depth);
+        }, move |encoder, resources| {
+            let color_attachments = gbuffer.handles().map(|handle| {
+                Some(wgpu::RenderPassColorAttachment {
+                    view          : resources.texture_view(handle),
+                    resolve_target: None,
+                    ops           : wgpu::Operations {
+                        load : wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label                   : Some("G-Buffer Pass"),
+                color_attachments       : &color_attachments,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view       : resources.texture_view(depth),
+                    depth_ops  : Some(wgpu::Operations {
+                        load : wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes        : None,
+                occlusion_query_set     : None,
+            });
+
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            render_pass.set_bind_group(1, model_bind_group, &[]);
+
+            if let Some(model) = model {
+                model.draw_opaque(&mut render_pass, pipelines, PassKind::GBuffer);
+            }
+        });
+
+        // ---> Lighting pass (G-buffer -> frame):
+        graph.add_pass("Deferred Lighting Pass", |builder| {
+            for handle in gbuffer.handles() {
+                builder.read(handle);
+            }
+            builder.read(depth);
+            builder.write(lit);
+        }, move |encoder, resources| {
+            let gbuffer_bind_group = device.create_bind_group(
+                &wgpu::BindGroupDescriptor {
+                    label  : Some("G-Buffer Bind Group"),
+                    layout : &self.gbuffer_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding : 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                resources.texture_view(gbuffer.albedo),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding : 1,
+                            resource: wgpu::BindingResource::TextureView(
+                                resources.texture_view(gbuffer.normal),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding : 2,
+                            resource: wgpu::BindingResource::TextureView(
+                                resources.texture_view(gbuffer.material),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding : 3,
+                            resource: wgpu::BindingResource::TextureView(
+                                resources.texture_view(gbuffer.emissive),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding : 4,
+                            resource: wgpu::BindingResource::TextureView(
+                                resources.texture_view(depth),
+                            ),
+                        },
+                    ],
+                },
+            );
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label                   : Some("Deferred Lighting Pass"),
+                color_attachments       : &[Some(wgpu::RenderPassColorAttachment {
+                    view          : resources.texture_view(lit),
+                    resolve_target: None,
+                    ops           : wgpu::Operations {
+                        load : wgpu::LoadOp::Clear(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes        : None,
+                occlusion_query_set     : None,
+            });
+
+            render_pass.set_pipeline(&self.lighting_pipeline);
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            render_pass.set_bind_group(1, light_bind_group, &[]);
+            render_pass.set_bind_group(2, &gbuffer_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        });
+
+        // ---> Forward only materials (opaque, shaded like the forward path):
+        if model.is_some_and(|model| model.has_forward_only()) {
+            graph.add_pass("Forward Opaque Pass", |builder| {
+                builder.write(lit);
+                builder.write(depth);
+            }, move |encoder, resources| {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label                   : Some("Forward Opaque Pass"),
+                    color_attachments       : &[Some(wgpu::RenderPassColorAttachment {
+                        view          : resources.texture_view(lit),
+                        resolve_target: None,
+                        ops           : wgpu::Operations {
+                            load : wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view       : resources.texture_view(depth),
+                        depth_ops  : Some(wgpu::Operations {
+                            load : wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    timestamp_writes        : None,
+                    occlusion_query_set     : None,
+                });
+
+                render_pass.set_bind_group(0, camera_bind_group, &[]);
+                render_pass.set_bind_group(1, model_bind_group, &[]);
+                render_pass.set_bind_group(3, &scene_color.default_bind_group, &[]);
+
+                if let Some(model) = model {
+                    model.draw_forward_only(&mut render_pass, pipelines);
+                }
+            });
+        }
+
+        // ---> Background where the G-buffer stayed empty:
+        if let Some(sky) = sky {
+            sky.add_pass(graph, lit, depth, camera_bind_group);
+        }
+
+        // ---> Blended and transmissive meshes over the lit frame:
+        scene_color.add_transparent_passes(graph, device, mipmaps, transmission.then_some(lit), frame, depth,
+                                           camera_bind_group, model_bind_group, pipelines, eye, model);
+    }
+}
+///// DEFERRED RENDERER STRUCTURE //////////////////////////////////////////////////////////////////
@@ -0,0 +1,86 @@
+///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
+struct CameraUniform {
+    view_proj    : mat4x4<f32>,
+    position     : vec3<f32>,
+    _pad         : f32,
+    inv_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+
+struct Light {
+    position : vec3<f32>,
+    range    : f32,
+    color    : vec3<f32>, // RGB-color
+    intensity: f32,
+};
+@group(1) @binding(0) var<uniform> light: Light;
+///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
+
+///// G-BUFFER TEXTURES ////////////////////////////////////////////////////////////////////////////
+@group(2) @binding(0) var gbuffer_albedo  : texture_2d<f32>;
+@group(2) @binding(1) var gbuffer_normal  : texture_2d<f32>;
+@group(2) @binding(2) var gbuffer_material: texture_2d<f32>;
+@group(2) @binding(3) var gbuffer_emissive: texture_2d<f32>;
+@group(2) @binding(4) var gbuffer_depth   : texture_2d<f32>;
+///// G-BUFFER TEXTURES ////////////////////////////////////////////////////////////////////////////
+
+///// VERTEX SHADER (FULLSCREEN TRIANGLE) //////////////////////////////////////////////////////////
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    // ---> One triangle covering the whole screen (no vertex buffer needed):
+    let x = f32((vertex_index << 1u) & 2u);
+    let y = f32(vertex_index & 2u);
+    out.clip_position = vec4<f32>(x * 2.0 - 1.0, y * 2.0 - 1.0, 0.0, 1.0);
+
+    return out;
+}
+///// VERTEX SHADER (FULLSCREEN TRIANGLE) //////////////////////////////////////////////////////////
+
+///// LIGHTING FRAGMENT SHADER /////////////////////////////////////////////////////////////////////
+@fragment
+fn fs_lighting(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let depth = textureLoad(gbuffer_depth, pixel, 0).r;
+
+    // ---> Nothing was drawn here, keep the background:
+    if depth >= 1.0 {
+        discard;
+    }
+
+    let albedo    = textureLoad(gbuffer_albedo,   pixel, 0);
+    let normal    = normalize(textureLoad(gbuffer_normal, pixel, 0).xyz);
+    let emissive  = textureLoad(gbuffer_emissive, pixel, 0).rgb;
+    let occlusion = textureLoad(gbuffer_material, pixel, 0).b;
+
+    // ---> Reconstruct world position from depth:
+    let size      = vec2<f32>(textureDimensions(gbuffer_depth));
+    let uv        = in.clip_position.xy / size;
+    let ndc       = vec4<f32>(uv.x * 2.0 - 1.0, (1.0 - uv.y) * 2.0 - 1.0, depth, 1.0);
+    let world     = camera.inv_view_proj * ndc;
+    let frag_pos  = world.xyz / world.w;
+
+    // ---> Light calculation (same model as the forward fs_main):
+    let light_dir   = normalize( light.position - frag_pos);
+    let view_dir    = normalize(camera.position - frag_pos);
+    let halfway_dir = normalize(light_dir + view_dir);
+
+    let diff = max(dot(normal, light_dir), 0.0);
+    let spec = pow(max(dot(normal, halfway_dir), 0.0), 32.0);
+
+    let distance    = length(light.position - frag_pos);
+    let attenuation = 1.0 / (distance * distance);
+
+    let ambient     = 0.1 * albedo.rgb * occlusion;
+    let diffuse     = diff * light.color * light.intensity * attenuation;
+    let specular    = spec * light.color * light.intensity * attenuation;
+    let final_color = (ambient + diffuse + specular) * albedo.rgb + emissive;
+
+    return vec4<f32>(final_color, albedo.a);
+}
+///// LIGHTING FRAGMENT SHADER /////////////////////////////////////////////////////////////////////
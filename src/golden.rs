@@ -0,0 +1,243 @@
+/*
+
+    Golden-image regression harness: renders reference scenes headless (fallback adapter) and
+    compares them against stored PNGs with a perceptual tolerance.
+
+    SealEngine --golden [tests/golden] [--update]
+
+    Manifest format (one case per line, '#' starts a comment):
+        <name> <scene.gltf> <WxH> <ex,ey,ez,tx,ty,tz> [forward|deferred] [material variant]
+
+*/
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::headless::HeadlessOptions;
+use crate::headless::parse_camera;
+use crate::headless::parse_size;
+use crate::headless::render_scene;
+
+
+///// TOLERANCES ///////////////////////////////////////////////////////////////////////////////////
+/// Perceptual (YIQ) difference above which a pixel counts as different (0..1).
+pub const PIXEL_THRESHOLD   : f32 = 0.1;
+/// Fraction of differing pixels that is still accepted (rasterizer/driver noise).
+pub const MAX_MISMATCH_RATIO: f32 = 0.005;
+///// TOLERANCES ///////////////////////////////////////////////////////////////////////////////////
+
+
+///// IMAGE COMPARISON STRUCTURE ///////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy)]
+pub struct ImageComparison {
+    pub max_delta        : f32,
+    pub mean_delta       : f32,
+    pub mismatched_pixels: usize,
+    pub total_pixels     : usize,
+}
+
+impl ImageComparison {
+    pub fn mismatch_ratio(&self) -> f32 {
+        self.mismatched_pixels as f32 / self.total_pixels.max(1) as f32
+    }
+
+    pub fn passed(&self) -> bool {
+        self.mismatch_ratio() <= MAX_MISMATCH_RATIO
+    }
+}
+
+/// Perceptual colour distance in YIQ space (as used by pixelmatch), normalized to 0..1.
+fn perceptual_delta(a: &image::Rgba<u8>, b: &image::Rgba<u8>) -> f32 {
+    // ---> Blend against white so differences in alpha are visible too:
+    let blend = |pixel: &image::Rgba<u8>| {
+        let alpha = pixel[3] as f32 / 255.0;
+        [0, 1, 2].map(|c| 255.0 + (pixel[c] as f32 - 255.0) * alpha)
+    };
+    let (a, b) = (blend(a), blend(b));
+
+    let y = |c: [f32; 3]| c[0] * 0.298_895_3 + c[1] * 0.586_622_5 + c[2] * 0.114_482_23;
+    let i = |c: [f32; 3]| c[0] * 0.595_977_97 - c[1] * 0.274_176_1 - c[2] * 0.321_801_9;
+    let q = |c: [f32; 3]| c[0] * 0.211_470_17 - c[1] * 0.522_617_1 + c[2] * 0.311_146_94;
+
+    let dy = y(a) - y(b);
+    let di = i(a) - i(b);
+    let dq = q(a) - q(b);
+
+    // ---> 35215 is the maximum possible squared delta:
+    ((0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq) / 35215.0).sqrt()
+}
+
+/// Compares two images and returns the statistics plus a diff image (red = mismatch).
+pub fn compare_images(actual  : &image::RgbaImage,
+                      expected: &image::RgbaImage) -> anyhow::Result<(ImageComparison, image::RgbaImage)> {
+    if actual.dimensions() != expected.dimensions() {
+        anyhow::bail!("Image size mismatch: {:?} vs. {:?}", actual.dimensions(), expected.dimensions());
+    }
+
+    let (width, height) = actual.dimensions();
+    let mut diff        = image::RgbaImage::new(width, height);
+    let mut comparison  = ImageComparison {
+        max_delta        : 0.0,
+        mean_delta       : 0.0,
+        mismatched_pixels: 0,
+        total_pixels     : (width * height) as usize,
+    };
+
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let delta = perceptual_delta(actual.get_pixel(x, y), expected_pixel);
+        comparison.max_delta   = comparison.max_delta.max(delta);
+        comparison.mean_delta += delta;
+
+        if delta > PIXEL_THRESHOLD {
+            comparison.mismatched_pixels += 1;
+            diff.put_pixel(x, y, image::Rgba([255, 0, 0, 255]));
+        } else {
+            // ---> Faded grayscale of the reference for orientation:
+            let gray = (expected_pixel[0] as u32 + expected_pixel[1] as u32 + expected_pixel[2] as u32) / 3;
+            let gray = (255 - (255 - gray) / 4) as u8;
+            diff.put_pixel(x, y, image::Rgba([gray, gray, gray, 255]));
+        }
+    }
+    comparison.mean_delta /= comparison.total_pixels.max(1) as f32;
+
+    Ok((comparison, diff))
+}
+///// IMAGE COMPARISON STRUCTURE ///////////////////////////////////////////////////////////////////
+
+
+///// GOLDEN CASE STRUCTURE ////////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct GoldenCase {
+    pub name   : String,
+    pub options: HeadlessOptions,
+}
+
+pub fn load_manifest(golden_dir: &Path) -> anyhow::Result<Vec<GoldenCase>> {
+    let manifest_path = golden_dir.join("manifest.txt");
+    let manifest      = std::fs::read_to_string(&manifest_path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", manifest_path.display(), e))?;
+
+    let mut cases = Vec::new();
+    for (line_number, line) in manifest.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            anyhow::bail!("{}:{}: expected '<name> <scene> <WxH> <camera> [path] [variant]'",
+                          manifest_path.display(), line_number + 1);
+        }
+
+        let (width, height) = parse_size(fields[2])?;
+        let deferred = match fields.get(4).copied() {
+            None | Some("forward") => false,
+            Some("deferred")       => true,
+            Some(other)            => anyhow::bail!("{}:{}: unknown render path '{}'",
+                                                    manifest_path.display(), line_number + 1, other),
+        };
+
+        cases.push(GoldenCase {
+            name   : fields[0].to_string(),
+            options: HeadlessOptions {
+                scene   : golden_dir.join(fields[1]).to_string_lossy().to_string(),
+                output  : String::new(),
+                width,
+                height,
+                camera  : Some(parse_camera(fields[3])?),
+                deferred,
+                fallback: true,
+                variant : fields.get(5).map(|variant| variant.to_string()),
+                sky     : None,
+                custom  : Vec::new(),
+            },
+        });
+    }
+
+    Ok(cases)
+}
+///// GOLDEN CASE STRUCTURE ////////////////////////////////////////////////////////////////////////
+
+
+///// GOLDEN HARNESS PROCEDURE /////////////////////////////////////////////////////////////////////
+/// Runs all cases; returns `true` if every case matched its reference image.
+/// Only with `update` the rendered images are written as the new goldens, a missing reference
+/// (or a case that fails to render) counts as a failure.
+pub fn run(golden_dir: &Path, update: bool) -> anyhow::Result<bool> {
+    let cases         = load_manifest(golden_dir)?;
+    let reference_dir = golden_dir.join("reference");
+    let failure_dir   = golden_dir.join("failures");
+    if update {
+        std::fs::create_dir_all(&reference_dir)?;
+    }
+
+    let mut all_passed = true;
+    for case in cases {
+        let actual = match render_scene(&case.options) {
+            Ok(actual) => actual,
+            Err(e)     => {
+                all_passed = false;
+                println!("[golden] {:<24} FAILED (render error: {:#})", case.name, e);
+                continue;
+            }
+        };
+        let reference_path = reference_dir.join(format!("{}.png", case.name));
+
+        if update {
+            actual.save(&reference_path)?;
+            println!("[golden] {:<24} written to {}", case.name, reference_path.display());
+            continue;
+        }
+
+        if !reference_path.exists() {
+            all_passed = false;
+            let (actual_path, _) = write_failure(&failure_dir, &case.name, &actual, None)?;
+            println!("[golden] {:<24} FAILED (missing reference {}, run with --update to create it) -> {}",
+                     case.name, reference_path.display(), actual_path.display());
+            continue;
+        }
+
+        let expected = image::open(&reference_path)?.to_rgba8();
+        let result   = compare_images(&actual, &expected);
+
+        match result {
+            Ok((comparison, _)) if comparison.passed() => {
+                println!("[golden] {:<24} ok     ({:.3}% differing, max delta {:.3})",
+                         case.name, comparison.mismatch_ratio() * 100.0, comparison.max_delta);
+            }
+            Ok((comparison, diff)) => {
+                all_passed = false;
+                let (actual_path, diff_path) = write_failure(&failure_dir, &case.name, &actual, Some(&diff))?;
+                println!("[golden] {:<24} FAILED ({:.3}% differing, max delta {:.3}, mean delta {:.4}) \
+                          -> {}, {}",
+                         case.name, comparison.mismatch_ratio() * 100.0, comparison.max_delta,
+                         comparison.mean_delta, actual_path.display(), diff_path.display());
+            }
+            Err(e) => {
+                all_passed = false;
+                let (actual_path, _) = write_failure(&failure_dir, &case.name, &actual, None)?;
+                println!("[golden] {:<24} FAILED ({}) -> {}", case.name, e, actual_path.display());
+            }
+        }
+    }
+
+    Ok(all_passed)
+}
+
+fn write_failure(failure_dir: &Path,
+                 name       : &str,
+                 actual     : &image::RgbaImage,
+                 diff       : Option<&image::RgbaImage>) -> anyhow::Result<(PathBuf, PathBuf)> {
+    std::fs::create_dir_all(failure_dir)?;
+
+    let actual_path = failure_dir.join(format!("{}.actual.png", name));
+    let diff_path   = failure_dir.join(format!("{}.diff.png", name));
+    actual.save(&actual_path)?;
+    if let Some(diff) = diff {
+        diff.save(&diff_path)?;
+    }
+
+    Ok((actual_path, diff_path))
+}
+///// GOLDEN HARNESS PROCEDURE /////////////////////////////////////////////////////////////////////
@@ -1,14 +1,18 @@
 use std::sync::Arc;
 use winit::window::Window;
 
+use crate::shader_library::ShaderDefines;
+use crate::shader_library::ShaderLibrary;
+
 ///// GPU STRUCTURE ////////////////////////////////////////////////////////////////////////////////
 pub struct GPU {
     //pub instance: wgpu::Instance,
-    pub surface: wgpu::Surface<'static>,
+    pub surface: Option<wgpu::Surface<'static>>,  // None when rendering headless...
     pub adapter: wgpu::Adapter,
     pub device : wgpu::Device,
     pub queue  : wgpu::Queue,
     pub config : wgpu::SurfaceConfiguration,
+    pub shaders: Arc<ShaderLibrary>,  // Compiled shader permutations...
 }
 
 impl GPU {
@@ -23,18 +27,20 @@ impl GPU {
             compatible_surface: Some(&surface), 
         }).await.unwrap();
     
-        let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
-            required_features: wgpu::Features::empty(),
-            required_limits: wgpu::Limits::default(),
-            memory_hints: wgpu::MemoryHints::default(),
-            label: None,
-        }, None).await.unwrap();
+        let (device, queue) = Self::request_device(&adapter).await.unwrap();
     
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps.formats[0];
     
+        // ---> Allow copying the swapchain image (screenshots) where supported:
+        let usage = if surface_caps.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        };
+    
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format: surface_format,
             width: size.width,
             height: size.height,
@@ -45,19 +51,137 @@ impl GPU {
         };
         surface.configure(&device, &config);
 
-        Self{ /*instance,*/ surface, adapter, device, queue, config }
+        Self{ /*instance,*/ surface: Some(surface), adapter, device, queue, config, shaders: Arc::default() }
+    }
+
+    /// Creates a GPU without window or surface, rendering goes into an offscreen target.
+    /// With `force_fallback_adapter` a software adapter (e.g. llvmpipe / WARP) is requested.
+    pub async fn new_headless(width: u32, 
+                              height: u32, 
+                              force_fallback_adapter: bool) -> anyhow::Result<Self> {
+        let instance = wgpu::Instance::default();
+
+        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptionsBase {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter,
+            compatible_surface: None,
+        }).await.ok_or_else(|| anyhow::anyhow!("No suitable (headless) adapter found!"))?;
+
+        let (device, queue) = Self::request_device(&adapter).await?;
+
+        // ---> No surface, but the config still describes the render target:
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+
+        Ok(Self{ surface: None, adapter, device, queue, config, shaders: Arc::default() })
+    }
+
+    /// Headless GPU (fallback adapter) for the `#[ignore]`d GPU tests, which only run on request
+    /// (`cargo test -- --ignored`) and then fail loudly instead of passing without an adapter.
+    #[cfg(test)]
+    pub fn for_tests(width: u32, height: u32) -> Self {
+        pollster::block_on(Self::new_headless(width, height, true))
+            .unwrap_or_else(|e| panic!("GPU test needs an adapter: {:#}", e))
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> anyhow::Result<(wgpu::Device, wgpu::Queue)> {
+        // ---> Lossless 16 bit / float and block compressed textures, where the adapter has them:
+        let optional_features = wgpu::Features::TEXTURE_FORMAT_16BIT_NORM | 
+                                wgpu::Features::FLOAT32_FILTERABLE        |
+                                wgpu::Features::TEXTURE_COMPRESSION_BC    |
+                                wgpu::Features::TEXTURE_COMPRESSION_ETC2  |
+                                wgpu::Features::TEXTURE_COMPRESSION_ASTC;
+
+        Ok(adapter.request_device(&wgpu::DeviceDescriptor {
+            required_features: adapter.features() & optional_features,
+            required_limits: wgpu::Limits::default(),
+            memory_hints: wgpu::MemoryHints::default(),
+            label: None,
+        }, None).await?)
+    }
+
+    /// Engine shader `path` (relative to `SHADER_ROOT`) with `defines`, see `ShaderLibrary::load`.
+    pub fn load_shader(&self, path: &str, defines: &ShaderDefines) -> wgpu::ShaderModule {
+        self.shaders.load(&self.device, path, defines).unwrap_or_else(|error| panic!("{:#}", error))
     }
 
-    pub fn load_shaders(&self) -> wgpu::ShaderModule{
-        // TODO: load multiple shaders... later ;)
-        self.device.create_shader_module(
-            wgpu::ShaderModuleDescriptor { 
-                label: Some("Shader"), 
-                source: wgpu::ShaderSource::Wgsl(
-                    std::fs::read_to_string("./src/shader.wgsl").unwrap().into(), 
-                ),
+    /// Copies a 4-byte-per-pixel colour texture back to the CPU (blocking).
+    pub fn read_texture(&self, texture: &wgpu::Texture) -> anyhow::Result<image::RgbaImage> {
+        let width  = texture.width();
+        let height = texture.height();
+        let format = texture.format();
+
+        if format.block_copy_size(None) != Some(4) {
+            anyhow::bail!("Readback of {:?} is not supported!", format);
+        }
+
+        // ---> Rows in the copy buffer must be 256-byte aligned:
+        let unpadded_bytes_per_row = 4 * width;
+        let align                  = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row   = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label             : Some("Readback Buffer"),
+            size              : (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage             : wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Readback Encoder") }
+        );
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                aspect   : wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin   : wgpu::Origin3d::ZERO,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset        : 0,
+                    bytes_per_row : Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        // ---> Map and wait for the GPU:
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| { let _ = sender.send(result); });
+        let _ = self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+
+        // ---> Strip row padding (and swizzle BGRA swapchain formats):
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
             }
-        )
+        }
+        buffer.unmap();
+
+        if matches!(format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or_else(|| anyhow::anyhow!("Failed to create image from readback!"))
     }
 }
-///// GPU STRUCTURE ////////////////////////////////////////////////////////////////////////////////
\ No newline at end of file
+///// GPU STRUCTURE ////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,166 @@
+/*
+
+    Headless CLI mode: render a scene from a given camera into an image file.
+
+    SealEngine --headless --scene models/Bridge.glb --output frame.png
+               [--size 1280x720] [--camera ex,ey,ez,tx,ty,tz] [--deferred] [--fallback]
+               [--variant <KHR_materials_variants name>] [--sky <equirectangular image>]
+               [--custom-material <mesh index>:<file.wgsl with material_color>]...
+
+*/
+
+use std::path::Path;
+use std::sync::Arc;
+use nalgebra_glm as glm;
+
+use crate::custom_material::CustomMaterial;
+use crate::custom_material::CustomShader;
+use crate::custom_material::MaterialDefinition;
+use crate::scene::NodeHandle;
+use crate::state::RenderPath;
+use crate::state::State;
+
+
+///// HEADLESS OPTIONS STRUCTURE ///////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    pub scene   : String,
+    pub output  : String,
+    pub width   : u32,
+    pub height  : u32,
+    pub camera  : Option<(glm::Vec3, glm::Vec3)>,  // (eye, target)
+    pub deferred: bool,
+    pub fallback: bool,                            // Force software adapter
+    pub variant : Option<String>,                  // Material variant to activate
+    pub sky     : Option<String>,                  // Environment map background
+    pub custom  : Vec<(usize, String)>,            // WGSL materials replacing meshes' ones (mesh index)
+}
+
+impl HeadlessOptions {
+    /// Returns `None` when `--headless` was not given (normal windowed mode).
+    pub fn from_args(args: &[String]) -> anyhow::Result<Option<Self>> {
+        if !args.iter().any(|arg| arg == "--headless") {
+            return Ok(None);
+        }
+
+        let mut options = Self {
+            scene   : "models/Bridge.glb".to_string(),
+            output  : "frame.png".to_string(),
+            width   : 1280,
+            height  : 720,
+            camera  : None,
+            deferred: false,
+            fallback: false,
+            variant : None,
+            sky     : None,
+            custom  : Vec::new(),
+        };
+
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg));
+
+            match arg.as_str() {
+                "--headless" => {}
+                "--deferred" => options.deferred = true,
+                "--fallback" => options.fallback = true,
+                "--scene"    => options.scene    = value()?.clone(),
+                "--output"   => options.output   = value()?.clone(),
+                "--size"     => {
+                    let (width, height) = parse_size(value()?)?;
+                    options.width  = width;
+                    options.height = height;
+                }
+                "--camera"   => options.camera = Some(parse_camera(value()?)?),
+                "--variant"  => options.variant = Some(value()?.clone()),
+                "--sky"      => options.sky     = Some(value()?.clone()),
+                "--custom-material" => {
+                    let (mesh, path) = value()?.split_once(':')
+                                               .ok_or_else(|| anyhow::anyhow!("Custom material must look like 0:material.wgsl"))?;
+                    options.custom.push((mesh.trim().parse()?, path.to_string()));
+                }
+                other        => anyhow::bail!("Unknown argument: {}", other),
+            }
+        }
+
+        Ok(Some(options))
+    }
+}
+
+pub fn parse_size(value: &str) -> anyhow::Result<(u32, u32)> {
+    let (width, height) = value.split_once('x')
+                               .ok_or_else(|| anyhow::anyhow!("Size must look like 1280x720"))?;
+    Ok((width.parse()?, height.parse()?))
+}
+
+pub fn parse_camera(value: &str) -> anyhow::Result<(glm::Vec3, glm::Vec3)> {
+    let numbers = value.split(',')
+                       .map(|number| number.trim().parse::<f32>())
+                       .collect::<Result<Vec<_>, _>>()?;
+    if numbers.len() != 6 {
+        anyhow::bail!("Camera must be given as ex,ey,ez,tx,ty,tz");
+    }
+
+    Ok((glm::vec3(numbers[0], numbers[1], numbers[2]),
+        glm::vec3(numbers[3], numbers[4], numbers[5])))
+}
+///// HEADLESS OPTIONS STRUCTURE ///////////////////////////////////////////////////////////////////
+
+
+///// HEADLESS RENDER PROCEDURE ////////////////////////////////////////////////////////////////////
+pub fn render_scene(options: &HeadlessOptions) -> anyhow::Result<image::RgbaImage> {
+    let mut state = pollster::block_on(State::new_headless(options.width, 
+                                                           options.height, 
+                                                           &options.scene, 
+                                                           options.fallback))?;
+
+    // ---> Software adapters render slightly differently, say which one was picked:
+    let adapter = state.gpu.adapter.get_info();
+    println!("Rendering on {} ({:?})", adapter.name, adapter.backend);
+
+    if let Some((eye, target)) = options.camera {
+        state.set_camera(eye, target);
+    }
+    if options.deferred {
+        state.render_path = RenderPath::Deferred;
+    }
+    if let Some(sky) = &options.sky {
+        state.set_sky(Some(sky))?;
+    }
+    if let Some(variant) = &options.variant {
+        let node = state.model_node.ok_or_else(|| anyhow::anyhow!("No model loaded from {}", options.scene))?;
+        state.set_variant(node, Some(variant)).map_err(|e| anyhow::anyhow!(e))?;
+    }
+    for (mesh, path) in &options.custom {
+        let node     = state.model_node.ok_or_else(|| anyhow::anyhow!("No model loaded from {}", options.scene))?;
+        let material = load_custom_material(&state, node, *mesh, path)?;
+        state.set_material_override(node, *mesh, Some(Arc::new(material))).map_err(|e| anyhow::anyhow!(e))?;
+    }
+
+    state.render_to_image()
+}
+
+/// Material without parameters or textures from a WGSL file defining `material_color`. It keeps
+/// the alpha mode and double sidedness of the glTF material it replaces on `mesh`.
+fn load_custom_material(state: &State, node: NodeHandle, mesh: usize, path: &str) -> anyhow::Result<CustomMaterial> {
+    let replaced = state.scene.get_node(node)
+                              .and_then(|node| node.model.as_ref())
+                              .and_then(|model| model.meshes.get(mesh).and_then(|mesh| model.materials.get(mesh.material_index)))
+                              .ok_or_else(|| anyhow::anyhow!("No mesh {} on this node", mesh))?;
+
+    let source     = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+    let name       = Path::new(path).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let definition = MaterialDefinition::new(&name, &source).with_alpha_mode(replaced.alpha_mode, replaced.alpha_cutoff)
+                                                            .with_double_sided(replaced.double_sided);
+    let shader     = CustomShader::new(&state.gpu.device, definition)?;
+    CustomMaterial::new(&state.gpu.device, &shader, Vec::new(), &name)
+}
+
+pub fn run(options: &HeadlessOptions) -> anyhow::Result<()> {
+    let image = render_scene(options)?;
+    image.save(&options.output)?;
+    println!("Rendered {} -> {}", options.scene, options.output);
+    Ok(())
+}
+///// HEADLESS RENDER PROCEDURE ////////////////////////////////////////////////////////////////////
+
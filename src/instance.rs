@@ -67,39 +67,39 @@ impl InstanceRaw {
                 // ---> Model matrix (4 * vec4s)
                 wgpu::VertexAttribute {
                     offset         : 0,
-                    shader_location: 5,
+                    shader_location: 7,
                     format         : wgpu::VertexFormat::Float32x4,
                 },
                 wgpu::VertexAttribute {
                     offset         : 16, // 4 Bytes * 4 + 0
-                    shader_location: 6,
+                    shader_location: 8,
                     format         : wgpu::VertexFormat::Float32x4,
                 },
                 wgpu::VertexAttribute {
                     offset         : 32, // 4 Bytes * 4 + 16
-                    shader_location: 7,
+                    shader_location: 9,
                     format         : wgpu::VertexFormat::Float32x4,
                 },
                 wgpu::VertexAttribute {
                     offset         : 48, // 4 Bytes * 4 + 32
-                    shader_location: 8,
+                    shader_location: 10,
                     format         : wgpu::VertexFormat::Float32x4,
                 },
 
                 // ---> Normal matrix (3 * vec4s)
                 wgpu::VertexAttribute {
                     offset         : 64, // 4 Bytes * 4 + 48
-                    shader_location: 9,
+                    shader_location: 11,
                     format         : wgpu::VertexFormat::Float32x4,
                 },
                 wgpu::VertexAttribute {
                     offset         : 80, // 4 Bytes * 4 + 64
-                    shader_location: 10,
+                    shader_location: 12,
                     format         : wgpu::VertexFormat::Float32x4,
                 },
                 wgpu::VertexAttribute {
                     offset         : 96, // 4 Bytes * 4 + 80
-                    shader_location: 11,
+                    shader_location: 13,
                     format         : wgpu::VertexFormat::Float32x4,
                 },
             ],
@@ -90,3 +90,40 @@ impl InstanceManager {
     }
 }
 ///// INSTANCE MANAGER STRUCTURE ///////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::RenderPath;
+    use crate::state::State;
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn instanced_nodes_draw_every_instance() {
+        let mut state = State::for_tests(64, 32, "tests/golden/scenes/cube.gltf");
+        state.set_camera(glm::vec3(0.0, 0.0, 10.0), glm::vec3(0.0, 0.0, 0.0));
+        let node = state.model_node.unwrap();
+        let at   = |x: f32| Instance { position: glm::vec3(x, 0.0, 0.0), ..Instance::new() };
+        let lit  = |image: &image::RgbaImage, x: u32| image.get_pixel(x, 16).0 != [0, 0, 0, 255];
+        let (left, centre, right) = (23, 32, 40);  // Columns of the three cube positions
+
+        for path in [RenderPath::Forward, RenderPath::Deferred] {
+            state.render_path = path;
+
+            // ---> Two instances left and right of the (empty) centre:
+            state.set_instances(node, vec![at(-3.0), at(3.0)]).unwrap();
+            assert!(state.model_uniform_state.model.as_ref().unwrap().instances.is_some());
+            let image = state.render_to_image().unwrap();
+            assert!(lit(&image, left) && lit(&image, right) && !lit(&image, centre), "{:?}", path);
+
+            // ---> A single instance goes back to the model uniform:
+            state.set_instances(node, vec![Instance::new()]).unwrap();
+            assert!(state.model_uniform_state.model.as_ref().unwrap().instances.is_none());
+            let image = state.render_to_image().unwrap();
+            assert!(lit(&image, centre) && !lit(&image, left) && !lit(&image, right), "{:?}", path);
+        }
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
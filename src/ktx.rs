@@ -0,0 +1,264 @@
+/*
+
+    KTX2 texture container support (.ktx2 image files and glTF KHR_texture_basisu images).
+
+    Block compressed payloads (BC1-7, ETC2/EAC, ASTC 4x4) and plain RGBA payloads are uploaded
+    as stored, including their pre-built mip chains. Zstandard supercompression is decoded on
+    the CPU. Basis Universal ETC1S payloads (BasisLZ supercompression) are transcoded to ETC2,
+    BC1/BC7 or ASTC, whichever the adapter supports, see `basis.rs`. UASTC payloads are not
+    supported: they are rejected with an error so callers can use the glTF fallback image.
+
+*/
+
+use std::io::Read;
+
+use crate::basis::Etc1sTranscoder;
+use crate::basis::target_format;
+use crate::texture::SamplerKey;
+use crate::texture::Texture;
+use crate::texture::TextureLoader;
+use crate::texture::TextureRole;
+use crate::texture::create_texture_from_levels;
+use crate::texture::create_texture_with_mipmaps;
+
+
+pub const KTX2_MAGIC: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(&KTX2_MAGIC)
+}
+
+
+///// KTX2 IMAGE STRUCTURE /////////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone)]
+pub struct Ktx2Image {
+    pub format: wgpu::TextureFormat,
+    pub width : u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,  // Level 0 (largest) first, already decompressed
+}
+
+impl Ktx2Image {
+    /// `features` picks the transcode target of Basis Universal ETC1S files, other payloads
+    /// are returned as stored.
+    pub fn parse(bytes: &[u8], features: wgpu::Features) -> anyhow::Result<Self> {
+        let reader = ktx2::Reader::new(bytes).map_err(|e| anyhow::anyhow!("Invalid KTX2 data: {:?}", e))?;
+        let header = reader.header();
+
+        if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+            anyhow::bail!("Only 2D KTX2 textures are supported (depth {}, layers {}, faces {})",
+                          header.pixel_depth, header.layer_count, header.face_count);
+        }
+        let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+
+        // ---> Basis Universal: ETC1S is transcoded, UASTC is not supported:
+        let color_model = reader.dfd_blocks()
+                                .filter_map(|block| ktx2::DfdBlockBasic::parse(block.data).ok())
+                                .find_map(|block| block.header.color_model);
+        if color_model == Some(ktx2::ColorModel::UASTC) {
+            anyhow::bail!("KTX2 contains UASTC data, only Basis Universal ETC1S (BasisLZ) is transcoded");
+        }
+        if header.supercompression_scheme == Some(ktx2::SupercompressionScheme::BasisLZ) {
+            let transcoder = Etc1sTranscoder::new(reader.supercompression_global_data(), header.level_count.max(1) as usize)?;
+            let format     = target_format(features, transcoder.has_alpha(), width, height);
+            let levels     = reader.levels().enumerate().map(|(level, data)| {
+                transcoder.transcode(level, data.data, (width >> level).max(1), (height >> level).max(1), format)
+            }).collect::<anyhow::Result<Vec<_>>>()?;
+
+            return Ok(Self { format, width, height, levels });
+        }
+        if color_model == Some(ktx2::ColorModel::ETC1S) {
+            anyhow::bail!("KTX2 contains ETC1S data without BasisLZ supercompression");
+        }
+
+        let vk_format = header.format.ok_or_else(|| anyhow::anyhow!("KTX2 without vkFormat"))?;
+        let format    = vk_format_to_wgpu(vk_format)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported KTX2 format {:?}", vk_format))?;
+
+        let levels = reader.levels().map(|level| {
+            match header.supercompression_scheme {
+                None => Ok(level.data.to_vec()),
+                Some(ktx2::SupercompressionScheme::Zstandard) => {
+                    let mut data    = Vec::with_capacity(level.uncompressed_byte_length as usize);
+                    let mut decoder = ruzstd::decoding::StreamingDecoder::new(level.data)
+                        .map_err(|e| anyhow::anyhow!("Invalid Zstandard data in KTX2: {}", e))?;
+                    decoder.read_to_end(&mut data)?;
+                    Ok(data)
+                },
+                Some(scheme) => anyhow::bail!("Unsupported KTX2 supercompression {:?}", scheme),
+            }
+        }).collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self { format, width, height, levels })
+    }
+
+    /// The material slot decides the colour space, not the file (glTF 2.0, 3.9.x).
+    pub fn with_role(self, role: TextureRole) -> Self {
+        self.with_srgb(role.is_srgb())
+    }
+
+    pub fn with_srgb(mut self, srgb: bool) -> Self {
+        self.format = if srgb { self.format.add_srgb_suffix() } else { self.format.remove_srgb_suffix() };
+        self
+    }
+
+    pub fn upload(&self,
+                  device : &wgpu::Device,
+                  queue  : &wgpu::Queue,
+                  loader : &TextureLoader,
+                  sampler: &SamplerKey,
+                  label  : Option<&str>) -> anyhow::Result<Texture> {
+        let required = self.format.required_features();
+        if !device.features().contains(required) {
+            anyhow::bail!("{:?} needs {:?}, which the adapter does not support", self.format, required);
+        }
+
+        // ---> A single uncompressed level gets its mip chain generated like any other image:
+        if self.levels.len() == 1 && !self.format.is_compressed() {
+            return Ok(create_texture_with_mipmaps(device, queue, loader, sampler, &self.levels[0],
+                                                  self.width, self.height, self.format, label));
+        }
+
+        create_texture_from_levels(device, queue, loader, sampler, &self.levels, 
+                                   self.width, self.height, self.format, label)
+    }
+}
+///// KTX2 IMAGE STRUCTURE /////////////////////////////////////////////////////////////////////////
+
+
+///// FORMAT MAPPING ///////////////////////////////////////////////////////////////////////////////
+fn vk_format_to_wgpu(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+    use ktx2::Format as Vk;
+    use wgpu::TextureFormat as Wgpu;
+    use wgpu::AstcBlock;
+    use wgpu::AstcChannel;
+
+    Some(match format {
+        Vk::R8G8B8A8_UNORM            => Wgpu::Rgba8Unorm,
+        Vk::R8G8B8A8_SRGB             => Wgpu::Rgba8UnormSrgb,
+        Vk::R16G16B16A16_SFLOAT       => Wgpu::Rgba16Float,
+        Vk::R32G32B32A32_SFLOAT       => Wgpu::Rgba32Float,
+
+        Vk::BC1_RGBA_UNORM_BLOCK      => Wgpu::Bc1RgbaUnorm,
+        Vk::BC1_RGBA_SRGB_BLOCK       => Wgpu::Bc1RgbaUnormSrgb,
+        Vk::BC3_UNORM_BLOCK           => Wgpu::Bc3RgbaUnorm,
+        Vk::BC3_SRGB_BLOCK            => Wgpu::Bc3RgbaUnormSrgb,
+        Vk::BC4_UNORM_BLOCK           => Wgpu::Bc4RUnorm,
+        Vk::BC5_UNORM_BLOCK           => Wgpu::Bc5RgUnorm,
+        Vk::BC6H_UFLOAT_BLOCK         => Wgpu::Bc6hRgbUfloat,
+        Vk::BC6H_SFLOAT_BLOCK         => Wgpu::Bc6hRgbFloat,
+        Vk::BC7_UNORM_BLOCK           => Wgpu::Bc7RgbaUnorm,
+        Vk::BC7_SRGB_BLOCK            => Wgpu::Bc7RgbaUnormSrgb,
+
+        Vk::ETC2_R8G8B8_UNORM_BLOCK   => Wgpu::Etc2Rgb8Unorm,
+        Vk::ETC2_R8G8B8_SRGB_BLOCK    => Wgpu::Etc2Rgb8UnormSrgb,
+        Vk::ETC2_R8G8B8A8_UNORM_BLOCK => Wgpu::Etc2Rgba8Unorm,
+        Vk::ETC2_R8G8B8A8_SRGB_BLOCK  => Wgpu::Etc2Rgba8UnormSrgb,
+        Vk::EAC_R11_UNORM_BLOCK       => Wgpu::EacR11Unorm,
+        Vk::EAC_R11G11_UNORM_BLOCK    => Wgpu::EacRg11Unorm,
+
+        Vk::ASTC_4x4_UNORM_BLOCK      => Wgpu::Astc { block: AstcBlock::B4x4, channel: AstcChannel::Unorm },
+        Vk::ASTC_4x4_SRGB_BLOCK       => Wgpu::Astc { block: AstcBlock::B4x4, channel: AstcChannel::UnormSrgb },
+
+        _ => return None,
+    })
+}
+///// FORMAT MAPPING ///////////////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal KTX2 file: header, level index, a basic DFD block carrying only `color_model`,
+    /// the supercompression global data and the level data.
+    fn build_ktx2(vk_format: u32, width: u32, height: u32, supercompression: u32,
+                  color_model: u8, global_data: &[u8], levels: &[Vec<u8>]) -> Vec<u8> {
+        let index_end = 80 + 24 * levels.len();
+        let mut dfd   = 28u32.to_le_bytes().to_vec();
+        dfd.extend_from_slice(&[0, 0, 0, 0, 2, 0, 24, 0, color_model]);
+        dfd.resize(28, 0);
+        let sgd_start = index_end + dfd.len();
+        let mut data  = Vec::new();
+        let mut index = Vec::new();
+
+        for level in levels {
+            let offset = (sgd_start + global_data.len() + data.len()) as u64;
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&(level.len() as u64).to_le_bytes());
+            index.extend_from_slice(&(level.len() as u64).to_le_bytes());
+            data.extend_from_slice(level);
+        }
+
+        let mut bytes = KTX2_MAGIC.to_vec();
+        for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, supercompression,
+                      index_end as u32, dfd.len() as u32, 0, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let sgd_offset = if global_data.is_empty() { 0 } else { sgd_start as u64 };
+        bytes.extend_from_slice(&sgd_offset.to_le_bytes());
+        bytes.extend_from_slice(&(global_data.len() as u64).to_le_bytes());
+        bytes.extend(index);
+        bytes.extend(dfd);
+        bytes.extend_from_slice(global_data);
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn parses_mip_chain() {
+        let levels = vec![vec![1u8; 4 * 4 * 4], vec![2u8; 2 * 2 * 4], vec![3u8; 4]];
+        let image  = Ktx2Image::parse(&build_ktx2(37, 4, 4, 0, 1, &[], &levels), wgpu::Features::empty()).unwrap();
+
+        assert_eq!(image.format, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!((image.width, image.height), (4, 4));
+        assert_eq!(image.levels, levels);
+
+        assert_eq!(image.clone().with_role(TextureRole::BaseColor).format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(image.with_role(TextureRole::Normal).format, wgpu::TextureFormat::Rgba8Unorm);
+    }
+
+    #[test]
+    fn decodes_zstandard_levels() {
+        let level      = (0..64u8).collect::<Vec<_>>();
+        let compressed = ruzstd::encoding::compress_to_vec(&level[..], ruzstd::encoding::CompressionLevel::Fastest);
+        let image      = Ktx2Image::parse(&build_ktx2(145, 4, 4, 2, 128, &[], &[compressed]), wgpu::Features::empty()).unwrap();
+
+        assert_eq!(image.format, wgpu::TextureFormat::Bc7RgbaUnorm);
+        assert_eq!(image.levels, vec![level]);
+    }
+
+    #[test]
+    fn transcodes_basis_etc1s() {
+        use crate::basis::tests::global_data;
+        use crate::basis::tests::slice_8x8;
+
+        // ---> Two levels (8x8 and 4x4) sharing one slice, 4x4 only decodes its first block:
+        let slice  = slice_8x8();
+        let length = slice.len() as u32;
+        let file   = build_ktx2(0, 8, 8, 1, 163, &global_data(&[((0, length), (0, 0)), ((0, length), (0, 0))]),
+                                &[slice.clone(), slice]);
+
+        let image = Ktx2Image::parse(&file, wgpu::Features::TEXTURE_COMPRESSION_ETC2).unwrap();
+        assert_eq!(image.format, wgpu::TextureFormat::Etc2Rgb8Unorm);
+        assert_eq!(image.levels.iter().map(Vec::len).collect::<Vec<_>>(), vec![4 * 8, 8]);
+        assert_eq!(image.levels[1], image.levels[0][..8]);
+
+        let image = Ktx2Image::parse(&file, wgpu::Features::TEXTURE_COMPRESSION_ASTC).unwrap();
+        assert!(matches!(image.format, wgpu::TextureFormat::Astc { block: wgpu::AstcBlock::B4x4, .. }));
+        assert_eq!(image.levels[0].len(), 4 * 16);
+
+        let image = Ktx2Image::parse(&file, wgpu::Features::empty()).unwrap().with_role(TextureRole::BaseColor);
+        assert_eq!(image.format, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(image.levels.iter().map(Vec::len).collect::<Vec<_>>(), vec![8 * 8 * 4, 4 * 4 * 4]);
+    }
+
+    #[test]
+    fn rejects_uastc() {
+        let file  = build_ktx2(0, 4, 4, 0, 166, &[], &[vec![0; 16]]);
+        let error = Ktx2Image::parse(&file, wgpu::Features::all()).unwrap_err();
+        assert!(error.to_string().contains("UASTC"), "{}", error);
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
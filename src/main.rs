@@ -1,14 +1,29 @@
+mod assets;
+mod atlas;
+mod basis;
 mod camera;
+mod capture;
+mod custom_material;
+mod deferred;
+mod golden;
 mod gpu;
+mod headless;
 mod material;
 mod model;
+mod pipeline;
 mod input;
 mod instance;
 mod instance_manager;
+mod ktx;
 mod lighting;
+mod render_graph;
 mod scene;
+mod shader_library;
+mod sky;
 mod state;
+mod streaming;
 mod texture;
+mod transmission;
 mod vertex;
 
 // ---> Intern dependencies:
@@ -110,6 +125,42 @@ impl ApplicationHandler for App {
 
 ///// MAIN PROGRAM /////////////////////////////////////////////////////////////////////////////////
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    // ---> Golden-image regression tests:
+    if let Some(index) = args.iter().position(|arg| arg == "--golden") {
+        let golden_dir = args.get(index + 1)
+                             .filter(|arg| !arg.starts_with("--"))
+                             .map(String::as_str)
+                             .unwrap_or("tests/golden");
+        let update     = args.iter().any(|arg| arg == "--update");
+
+        match golden::run(std::path::Path::new(golden_dir), update) {
+            Ok(true)  => return,
+            Ok(false) => std::process::exit(1),
+            Err(e)    => {
+                eprintln!("Golden tests failed to run: {}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // ---> Headless CLI mode (no window):
+    match headless::HeadlessOptions::from_args(&args) {
+        Ok(Some(options)) => {
+            if let Err(e) = headless::run(&options) {
+                eprintln!("Headless rendering failed: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+
     let event_loop = EventLoop::new().unwrap();
     
     let mut app = App::default();
@@ -1,32 +1,576 @@
+use std::sync::Arc;
+use std::sync::RwLock;
+use wgpu::util::DeviceExt;
+use crate::pipeline::AlphaMode;
+use crate::pipeline::PassKind;
+use crate::pipeline::PipelineKey;
 use crate::texture::Texture;
 
+///// MATERIAL UNIFORM STRUCTURE ///////////////////////////////////////////////////////////////////
+/// Material factors the shader needs besides its textures.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    pub base_color        : [f32; 4],  // Multiplies the base color texture (alpha included)
+    pub emissive          : [f32; 3],  // emissive_factor * KHR_materials_emissive_strength
+    pub occlusion_strength: f32,
+    pub alpha_cutoff      : f32,       // Only used by ALPHA_MASK pipelines
+    pub transmission      : f32,       // KHR_materials_transmission factor
+    pub ior               : f32,       // KHR_materials_ior
+    pub metallic          : f32,       // metallic_factor, multiplies the metallic roughness texture
+    pub roughness         : f32,       // roughness_factor
+    pub _padding          : [f32; 3],
+    pub clearcoat         : [f32; 4],  // Factor, roughness, normal scale, clearcoat normal map (0/1)
+    pub sheen             : [f32; 4],  // Colour factor, roughness
+    pub specular          : [f32; 4],  // Colour factor, strength (KHR_materials_specular)
+    pub uv_transforms     : [[[f32; 4]; 2]; TextureSlot::COUNT],  // See TextureTransform::rows
+}
+
+impl Default for MaterialUniform {
+    fn default() -> Self {
+        Self {
+            base_color        : [1.0; 4],
+            emissive          : [0.0; 3],
+            occlusion_strength: 1.0,
+            alpha_cutoff      : 0.5,
+            transmission      : 0.0,
+            ior               : 1.5,
+            metallic          : 1.0,
+            roughness         : 1.0,
+            _padding          : [0.0; 3],
+            clearcoat         : [0.0, 0.0, 1.0, 0.0],
+            sheen             : [0.0; 4],
+            specular          : [1.0; 4],
+            uv_transforms     : [TextureTransform::default().rows(); TextureSlot::COUNT],
+        }
+    }
+}
+///// MATERIAL UNIFORM STRUCTURE ///////////////////////////////////////////////////////////////////
+
+///// JSON HELPERS /////////////////////////////////////////////////////////////////////////////////
+// ---> Extensions gltf does not parse itself are read from their raw JSON:
+fn json_float(object: &gltf::json::Value, key: &str) -> Option<f32> {
+    object.get(key)?.as_f64().map(|value| value as f32)
+}
+
+fn json_floats<const N: usize>(object: &gltf::json::Value, key: &str) -> Option<[f32; N]> {
+    let values = object.get(key)?.as_array()?;
+    if values.len() != N {
+        return None;
+    }
+
+    let mut result = [0.0; N];
+    for (result, value) in result.iter_mut().zip(values) {
+        *result = value.as_f64()? as f32;
+    }
+    Some(result)
+}
+///// JSON HELPERS /////////////////////////////////////////////////////////////////////////////////
+
+///// TEXTURE TRANSFORM STRUCTURE //////////////////////////////////////////////////////////////////
+/// Material texture slots, in the order of `MaterialUniform::uv_transforms` (SLOT_* in the shader).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureSlot {
+    BaseColor,
+    Normal,
+    MetallicRoughness,
+    Emissive,
+    Occlusion,
+    Clearcoat,
+    ClearcoatRoughness,
+    ClearcoatNormal,
+    SheenColor,
+    SheenRoughness,
+    Transmission,
+    Specular,
+    SpecularColor,
+}
+
+impl TextureSlot {
+    pub const COUNT: usize = 13;
+}
+
+/// UV set and `KHR_texture_transform` of a texture reference.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureTransform {
+    pub tex_coord: u32,       // 0 = TEXCOORD_0, 1 = TEXCOORD_1 (higher sets are not loaded)
+    pub offset   : [f32; 2],
+    pub rotation : f32,       // Radians, counter-clockwise
+    pub scale    : [f32; 2],
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self { tex_coord: 0, offset: [0.0; 2], rotation: 0.0, scale: [1.0; 2] }
+    }
+}
+
+impl TextureTransform {
+    /// `extension` is the `KHR_texture_transform` object of a texture info, its `texCoord`
+    /// overrides the one of the texture info. UV sets above 1 are not loaded, a warning is
+    /// printed and TEXCOORD_1 is read instead.
+    pub fn from_gltf(tex_coord: u32, extension: Option<&gltf::json::Value>) -> Self {
+        let tex_coord = extension.and_then(|extension| extension.get("texCoord"))
+                                 .and_then(|value| value.as_u64())
+                                 .map_or(tex_coord, |value| value as u32);
+        if tex_coord > 1 {
+            eprintln!("Texture reads TEXCOORD_{}, only TEXCOORD_0 and TEXCOORD_1 are loaded (using TEXCOORD_1)", tex_coord);
+        }
+
+        let Some(extension) = extension else {
+            return Self { tex_coord, ..Default::default() };
+        };
+
+        Self {
+            tex_coord,
+            offset   : json_floats(extension, "offset").unwrap_or([0.0; 2]),
+            rotation : json_float(extension, "rotation").unwrap_or(0.0),
+            scale    : json_floats(extension, "scale").unwrap_or([1.0; 2]),
+        }
+    }
+
+    /// Offset * rotation * scale as the two rows of a 2x3 matrix; `w` of the first row holds
+    /// the UV set.
+    pub fn rows(&self) -> [[f32; 4]; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            [ cos * self.scale[0], sin * self.scale[1], self.offset[0], self.tex_coord.min(1) as f32],
+            [-sin * self.scale[0], cos * self.scale[1], self.offset[1], 0.0],
+        ]
+    }
+}
+///// TEXTURE TRANSFORM STRUCTURE //////////////////////////////////////////////////////////////////
+
+///// MATERIAL TEXTURES STRUCTURE //////////////////////////////////////////////////////////////////
+/// Binding of the `MaterialUniform`, after the texture/sampler pair of every `TextureSlot`.
+pub const MATERIAL_UNIFORM_BINDING: u32 = 2 * TextureSlot::COUNT as u32;
+
+#[derive(Debug, Clone, Default)]
+pub struct MaterialTextures {
+    pub diffuse_texture            : Option<Arc<Texture>>,
+    pub normal_texture             : Option<Arc<Texture>>,
+    pub metallic_roughness_texture : Option<Arc<Texture>>,
+    pub emissive_texture           : Option<Arc<Texture>>,
+    pub occlusion_texture          : Option<Arc<Texture>>,
+    pub clearcoat_texture          : Option<Arc<Texture>>,
+    pub clearcoat_roughness_texture: Option<Arc<Texture>>,
+    pub clearcoat_normal_texture   : Option<Arc<Texture>>,
+    pub sheen_color_texture        : Option<Arc<Texture>>,
+    pub sheen_roughness_texture    : Option<Arc<Texture>>,
+    pub transmission_texture       : Option<Arc<Texture>>,
+    pub specular_texture           : Option<Arc<Texture>>,
+    pub specular_color_texture     : Option<Arc<Texture>>,
+}
+
+impl MaterialTextures {
+    /// Textures in `TextureSlot` order (= bindings 0, 2, 4, ... of the material bind group).
+    pub fn slots(&self) -> [&Option<Arc<Texture>>; TextureSlot::COUNT] {
+        [&self.diffuse_texture, &self.normal_texture, &self.metallic_roughness_texture,
+         &self.emissive_texture, &self.occlusion_texture, 
+         &self.clearcoat_texture, &self.clearcoat_roughness_texture, &self.clearcoat_normal_texture,
+         &self.sheen_color_texture, &self.sheen_roughness_texture, &self.transmission_texture,
+         &self.specular_texture, &self.specular_color_texture]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Arc<Texture>> {
+        self.slots().into_iter().flatten()
+    }
+
+    pub fn slot_mut(&mut self, slot: TextureSlot) -> &mut Option<Arc<Texture>> {
+        self.slots_mut().into_iter().nth(slot as usize).unwrap()
+    }
+
+    fn slots_mut(&mut self) -> [&mut Option<Arc<Texture>>; TextureSlot::COUNT] {
+        [&mut self.diffuse_texture, &mut self.normal_texture, &mut self.metallic_roughness_texture,
+         &mut self.emissive_texture, &mut self.occlusion_texture,
+         &mut self.clearcoat_texture, &mut self.clearcoat_roughness_texture, &mut self.clearcoat_normal_texture,
+         &mut self.sheen_color_texture, &mut self.sheen_roughness_texture, &mut self.transmission_texture,
+         &mut self.specular_texture, &mut self.specular_color_texture]
+    }
+}
+///// MATERIAL TEXTURES STRUCTURE //////////////////////////////////////////////////////////////////
+
+///// MATERIAL BINDINGS STRUCTURE //////////////////////////////////////////////////////////////////
+/// Textures and the bind group built from them. Both can be swapped while the material is shared
+/// (texture streaming replaces textures when their resident resolution changes).
+#[derive(Debug)]
+pub struct MaterialBindings {
+    layout         : wgpu::BindGroupLayout,
+    default_texture: Arc<Texture>,  // For empty slots
+    uniform_buffer : wgpu::Buffer,
+    uniform        : RwLock<MaterialUniform>,  // What the uniform buffer holds
+    current        : RwLock<(MaterialTextures, wgpu::BindGroup)>,
+}
+
+impl MaterialBindings {
+    pub fn new(device         : &wgpu::Device,
+               layout         : &wgpu::BindGroupLayout,
+               default_texture: Arc<Texture>,
+               textures       : MaterialTextures,
+               uniform        : &MaterialUniform,
+               label          : &str) -> Self {
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label   : Some(&format!("Material Uniform Buffer: {}", label)),
+                contents: bytemuck::cast_slice(&[*uniform]),
+                usage   : wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let bind_group = Self::create_bind_group(device, layout, &default_texture, &uniform_buffer, &textures, label);
+        Self {
+            layout         : layout.clone(),
+            default_texture,
+            uniform_buffer,
+            uniform        : RwLock::new(*uniform),
+            current        : RwLock::new((textures, bind_group)),
+        }
+    }
+
+    pub fn uniform(&self) -> MaterialUniform {
+        *self.uniform.read().unwrap()
+    }
+
+    /// Applies `edit` in place: rewrites the uniform buffer and rebuilds the bind group if 
+    /// textures change. Everything sharing the material sees it from the next frame on.
+    pub fn edit(&self, 
+                device: &wgpu::Device, 
+                queue : &wgpu::Queue, 
+                edit  : &MaterialEdit, 
+                label : &str) {
+        let mut uniform = self.uniform.write().unwrap();
+        edit.apply_uniform(&mut uniform);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[*uniform]));
+
+        if !edit.textures.is_empty() {
+            let mut current = self.current.write().unwrap();
+            edit.apply_textures(&mut current.0);
+            current.1 = Self::create_bind_group(device, &self.layout, &self.default_texture, 
+                                                &self.uniform_buffer, &current.0, label);
+        }
+    }
+
+    /// Copy with its own uniform buffer and bind group, `edit` applied.
+    pub fn duplicate(&self, device: &wgpu::Device, edit: &MaterialEdit, label: &str) -> Self {
+        let mut uniform  = self.uniform();
+        let mut textures = self.textures();
+        edit.apply_uniform(&mut uniform);
+        edit.apply_textures(&mut textures);
+        Self::new(device, &self.layout, self.default_texture.clone(), textures, &uniform, label)
+    }
+
+    pub fn has_texture(&self, slot: TextureSlot) -> bool {
+        self.current.read().unwrap().0.slots()[slot as usize].is_some()
+    }
+
+    pub fn textures(&self) -> MaterialTextures {
+        self.current.read().unwrap().0.clone()
+    }
+
+    pub fn bind_group(&self) -> wgpu::BindGroup {
+        self.current.read().unwrap().1.clone()
+    }
+
+    /// Points every slot using `old` to `new` and rebuilds the bind group. Returns `false` if the
+    /// material does not use `old`.
+    pub fn replace_texture(&self,
+                           device: &wgpu::Device,
+                           old   : &Arc<Texture>,
+                           new   : &Arc<Texture>,
+                           label : &str) -> bool {
+        let mut current  = self.current.write().unwrap();
+        let mut replaced = false;
+        for slot in current.0.slots_mut().into_iter().flatten() {
+            if Arc::ptr_eq(slot, old) {
+                *slot    = new.clone();
+                replaced = true;
+            }
+        }
+
+        if replaced {
+            current.1 = Self::create_bind_group(device, &self.layout, &self.default_texture, 
+                                                &self.uniform_buffer, &current.0, label);
+        }
+        replaced
+    }
+
+    fn create_bind_group(device         : &wgpu::Device,
+                         layout         : &wgpu::BindGroupLayout,
+                         default_texture: &Arc<Texture>,
+                         uniform_buffer : &wgpu::Buffer,
+                         textures       : &MaterialTextures,
+                         label          : &str) -> wgpu::BindGroup {
+        let textures = textures.slots().map(|texture| texture.as_ref().unwrap_or(default_texture));
+
+        // ---> Texture + sampler per TextureSlot, the material factors after them:
+        let mut entries = Vec::with_capacity(2 * TextureSlot::COUNT + 1);
+        for (slot, texture) in textures.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding : 2 * slot as u32,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding : 2 * slot as u32 + 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            });
+        }
+        entries.push(wgpu::BindGroupEntry {
+            binding : MATERIAL_UNIFORM_BINDING,
+            resource: uniform_buffer.as_entire_binding(),
+        });
+
+        device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label  : Some(&format!("Material Bind Group: {}", label)),
+                layout,
+                entries: &entries,
+            },
+        )
+    }
+}
+///// MATERIAL BINDINGS STRUCTURE //////////////////////////////////////////////////////////////////
+
+///// MATERIAL EXTENSION STRUCTURES ////////////////////////////////////////////////////////////////
+/// `KHR_materials_clearcoat`: a thin reflective layer (lacquer, car paint) over the material.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clearcoat {
+    pub factor      : f32,  // 0 = no clearcoat
+    pub roughness   : f32,
+    pub normal_scale: f32,  // Of the clearcoat normal map
+}
+
+impl Default for Clearcoat {
+    fn default() -> Self {
+        Self { factor: 0.0, roughness: 0.0, normal_scale: 1.0 }
+    }
+}
+
+impl Clearcoat {
+    pub fn from_gltf(extension: &gltf::json::Value) -> Self {
+        Self {
+            factor      : json_float(extension, "clearcoatFactor").unwrap_or(0.0),
+            roughness   : json_float(extension, "clearcoatRoughnessFactor").unwrap_or(0.0),
+            normal_scale: extension.get("clearcoatNormalTexture")
+                                   .and_then(|texture| json_float(texture, "scale"))
+                                   .unwrap_or(1.0),
+        }
+    }
+}
+
+/// `KHR_materials_sheen`: back-scattering of cloth and velvet at grazing angles.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sheen {
+    pub color    : [f32; 3],  // Black = no sheen
+    pub roughness: f32,
+}
+
+impl Sheen {
+    pub fn from_gltf(extension: &gltf::json::Value) -> Self {
+        Self {
+            color    : json_floats(extension, "sheenColorFactor").unwrap_or([0.0; 3]),
+            roughness: json_float(extension, "sheenRoughnessFactor").unwrap_or(0.0),
+        }
+    }
+}
+
+/// `KHR_materials_specular`: strength and tint of the dielectric specular reflection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Specular {
+    pub factor: f32,
+    pub color : [f32; 3],
+}
+
+impl Default for Specular {
+    fn default() -> Self {
+        Self { factor: 1.0, color: [1.0; 3] }
+    }
+}
+///// MATERIAL EXTENSION STRUCTURES ////////////////////////////////////////////////////////////////
+
+///// MATERIAL EDIT STRUCTURE //////////////////////////////////////////////////////////////////////
+/// Runtime changes of a material (see `Material::edit` / `Material::edited`), `None` keeps the
+/// current value. Only uniform factors and textures change: alpha mode, double sidedness, unlit
+/// and the extensions select pipelines and draw order, so they stay as loaded.
+#[derive(Debug, Clone, Default)]
+pub struct MaterialEdit {
+    pub base_color_factor: Option<[f32; 4]>,
+    pub metallic_factor  : Option<f32>,
+    pub roughness_factor : Option<f32>,
+    pub emissive         : Option<[f32; 3]>,  // emissive_factor * emissive_strength
+    pub textures         : Vec<(TextureSlot, Option<Arc<Texture>>)>,  // None = empty slot
+}
+
+impl MaterialEdit {
+    fn apply_uniform(&self, uniform: &mut MaterialUniform) {
+        if let Some(base_color) = self.base_color_factor {
+            uniform.base_color = base_color;
+        }
+        if let Some(metallic) = self.metallic_factor {
+            uniform.metallic = metallic;
+        }
+        if let Some(roughness) = self.roughness_factor {
+            uniform.roughness = roughness;
+        }
+        if let Some(emissive) = self.emissive {
+            uniform.emissive = emissive;
+        }
+    }
+
+    fn apply_textures(&self, textures: &mut MaterialTextures) {
+        for (slot, texture) in &self.textures {
+            *textures.slot_mut(*slot) = texture.clone();
+        }
+    }
+}
+///// MATERIAL EDIT STRUCTURE //////////////////////////////////////////////////////////////////////
+
 ///// MATERIAL STRUCTURE ///////////////////////////////////////////////////////////////////////////
 #[derive(Debug)]
 pub struct Material {
-    pub name                      : String,
-    pub diffuse_texture           : Option<Texture>,
-    pub normal_texture            : Option<Texture>,
-    pub metallic_roughness_texture: Option<Texture>,
-    pub base_color_factor         : [f32; 4],  // RGBA values for color
-    pub metallic_factor           : f32,
-    pub roughness_factor          : f32,
-    pub bind_group                : wgpu::BindGroup,  // For the shader...
-}
-
-impl Clone for Material {
-    fn clone(&self) -> Self {
-        Self { 
-            name                      : self.name.clone(), 
-            diffuse_texture           : self.diffuse_texture.clone(), 
-            normal_texture            : self.normal_texture.clone(), 
-            metallic_roughness_texture: self.metallic_roughness_texture.clone(), 
-            base_color_factor         : self.base_color_factor.clone(), 
-            metallic_factor           : self.metallic_factor, 
-            roughness_factor          : self.roughness_factor, 
-            bind_group                : self.bind_group.clone(),
+    pub name              : String,
+    pub occlusion_strength: f32,
+    pub alpha_mode        : AlphaMode,
+    pub alpha_cutoff      : f32,
+    pub double_sided      : bool,      // No back face culling, back faces get flipped normals
+    pub unlit             : bool,      // KHR_materials_unlit: base colour only, no lighting
+    pub clearcoat         : Clearcoat,
+    pub sheen             : Sheen,
+    pub specular          : Specular,
+    pub transmission      : f32,       // KHR_materials_transmission (0 = opaque)
+    pub ior               : f32,       // KHR_materials_ior (1.5 without it)
+    pub texture_transforms: [TextureTransform; TextureSlot::COUNT],
+    pub bindings          : MaterialBindings,  // Textures, factors (`uniform()`) + bind group
+}
+
+impl Material {
+    pub fn pipeline_key(&self, pass: PassKind) -> PipelineKey {
+        PipelineKey {
+            normal_map: !self.unlit && self.bindings.has_texture(TextureSlot::Normal),
+            ..PipelineKey::new(pass, self.double_sided, self.alpha_mode, self.unlit)
         }
     }
+
+    /// Changes the shared material while it is drawn, every node using it sees the change.
+    pub fn edit(&self, device: &wgpu::Device, queue: &wgpu::Queue, edit: &MaterialEdit) {
+        self.bindings.edit(device, queue, edit, &self.name);
+    }
+
+    /// Independent copy with `edit` applied, for per-node overrides (`SceneGraph::replace_material`)
+    /// that leave the shared model alone.
+    pub fn edited(&self, device: &wgpu::Device, edit: &MaterialEdit) -> Self {
+        Self {
+            name              : self.name.clone(),
+            occlusion_strength: self.occlusion_strength,
+            alpha_mode        : self.alpha_mode,
+            alpha_cutoff      : self.alpha_cutoff,
+            double_sided      : self.double_sided,
+            unlit             : self.unlit,
+            clearcoat         : self.clearcoat,
+            sheen             : self.sheen,
+            specular          : self.specular,
+            transmission      : self.transmission,
+            ior               : self.ior,
+            texture_transforms: self.texture_transforms,
+            bindings          : self.bindings.duplicate(device, edit, &self.name),
+        }
+    }
+
+    /// Transmissive surfaces show the opaque scene behind them, so they are drawn after it.
+    pub fn is_transmissive(&self) -> bool {
+        !self.unlit && self.transmission > 0.0
+    }
+
+    /// Clearcoat, sheen, specular and IOR only exist in the forward shader (the G-buffer has no
+    /// room for them), the deferred path shades such materials forward. Unlit materials ignore them.
+    pub fn is_forward_only(&self) -> bool {
+        !self.unlit && (self.clearcoat.factor > 0.0 || self.sheen.color != [0.0; 3] || 
+                        self.specular != Specular::default() || self.ior != 1.5)
+    }
 }
 ///// MATERIAL STRUCTURE ///////////////////////////////////////////////////////////////////////////
 
 
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::State;
+
+    fn apply(rows: [[f32; 4]; 2], uv: [f32; 2]) -> [f32; 2] {
+        [
+            rows[0][0] * uv[0] + rows[0][1] * uv[1] + rows[0][2],
+            rows[1][0] * uv[0] + rows[1][1] * uv[1] + rows[1][2],
+        ]
+    }
+
+    #[test]
+    fn texture_transform_parses_extension_and_applies_in_spec_order() {
+        let extension: gltf::json::Value = gltf::json::deserialize::from_str(
+            r#"{ "offset": [0.5, 0.25], "rotation": 1.5707964, "scale": [2.0, 4.0], "texCoord": 1 }"#
+        ).unwrap();
+        let transform = TextureTransform::from_gltf(0, Some(&extension));
+        assert_eq!(transform.tex_coord, 1);
+        assert_eq!(transform.scale, [2.0, 4.0]);
+
+        // ---> Scale, then rotate (u, v) -> (v, -u), then offset:
+        let rows = transform.rows();
+        let uv   = apply(rows, [1.0, 0.0]);
+        assert!((uv[0] - 0.5).abs() < 1e-5 && (uv[1] - (0.25 - 2.0)).abs() < 1e-5, "{:?}", uv);
+        assert_eq!(rows[0][3], 1.0);
+    }
+
+    #[test]
+    fn texture_transform_without_extension_is_identity() {
+        let transform = TextureTransform::from_gltf(1, None);
+        assert_eq!(transform, TextureTransform { tex_coord: 1, ..Default::default() });
+        assert_eq!(apply(transform.rows(), [0.3, 0.7]), [0.3, 0.7]);
+    }
+
+    #[test]
+    fn material_edit_only_changes_given_values() {
+        let edit = MaterialEdit {
+            roughness_factor: Some(0.25),
+            emissive        : Some([1.0, 0.5, 0.0]),
+            textures        : vec![(TextureSlot::Normal, None)],
+            ..Default::default()
+        };
+
+        let mut uniform = MaterialUniform::default();
+        edit.apply_uniform(&mut uniform);
+        assert_eq!((uniform.base_color, uniform.metallic, uniform.roughness), ([1.0; 4], 1.0, 0.25));
+        assert_eq!(uniform.emissive, [1.0, 0.5, 0.0]);
+
+        let mut textures = MaterialTextures::default();
+        edit.apply_textures(&mut textures);
+        assert!(textures.iter().next().is_none());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn node_overrides_leave_the_shared_material_alone() {
+        let mut state = State::for_tests(32, 32, "tests/golden/scenes/cube.gltf");
+        state.set_camera(nalgebra_glm::vec3(3.0, 2.5, 5.0), nalgebra_glm::vec3(0.0, 0.0, 0.0));
+        let lit  = state.render_to_image().unwrap().get_pixel(16, 16).0;
+        let node = state.model_node.unwrap();
+        let black = MaterialEdit { base_color_factor: Some([0.0, 0.0, 0.0, 1.0]), ..Default::default() };
+
+        // ---> Per node copy:
+        let shared = state.model_uniform_state.model.as_ref().unwrap().materials[0].clone();
+        let loaded = shared.bindings.uniform().base_color;
+        let copy   = state.override_material(node, 0, &black).unwrap();
+        assert!(!Arc::ptr_eq(&copy, &shared));
+        assert_eq!(shared.bindings.uniform().base_color, loaded);
+        assert_eq!(copy.bindings.uniform().base_color, [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(state.render_to_image().unwrap().get_pixel(16, 16).0, [0, 0, 0, 255]);
+
+        // ---> Editing the copy again, then going back to the shared material:
+        copy.edit(&state.gpu.device, &state.gpu.queue, &MaterialEdit { base_color_factor: Some([1.0; 4]), ..Default::default() });
+        assert_ne!(state.render_to_image().unwrap().get_pixel(16, 16).0, [0, 0, 0, 255]);
+        state.replace_material(node, 0, shared.clone()).unwrap();
+        assert_eq!(state.render_to_image().unwrap().get_pixel(16, 16).0, lit);
+
+        // ---> In place, every user of the material sees it:
+        shared.edit(&state.gpu.device, &state.gpu.queue, &black);
+        assert_eq!(state.render_to_image().unwrap().get_pixel(16, 16).0, [0, 0, 0, 255]);
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,72 @@
+///// MIPMAP BLIT SHADER ///////////////////////////////////////////////////////////////////////////
+// Downsamples one mip level into the next. sRGB textures are sampled and written through sRGB
+// views, so the filtering itself happens in linear space.
+//
+// Array layers and cube faces are read through views of the texture's own dimension (the GL 
+// backend can't view a single layer as a plain 2D texture), `layer` selects the slice.
+// fs_equirect renders cube face `layer` from an equirectangular (latitude/longitude) panorama.
+@group(0) @binding(0) var source_texture: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var source_array  : texture_2d_array<f32>;
+@group(0) @binding(3) var source_cube   : texture_cube<f32>;
+@group(0) @binding(4) var<uniform> layer: u32;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0)       tex_coords   : vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    // ---> Fullscreen triangle:
+    let x = f32((vertex_index << 1u) & 2u);
+    let y = f32(vertex_index & 2u);
+    out.clip_position = vec4<f32>(x * 2.0 - 1.0, 1.0 - y * 2.0, 0.0, 1.0);
+    out.tex_coords    = vec2<f32>(x, y);
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(source_texture, source_sampler, in.tex_coords);
+}
+
+@fragment
+fn fs_array(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source_array, source_sampler, in.tex_coords, layer, 0.0);
+}
+
+/// Direction through `tex_coords` of cube face `face` (+X, -X, +Y, -Y, +Z, -Z, as wgpu lays them out).
+fn cube_face_direction(face: u32, tex_coords: vec2<f32>) -> vec3<f32> {
+    let s = tex_coords.x * 2.0 - 1.0;
+    let t = tex_coords.y * 2.0 - 1.0;
+
+    switch face {
+        case 0u:  { return vec3<f32>( 1.0,   -t,   -s); }
+        case 1u:  { return vec3<f32>(-1.0,   -t,    s); }
+        case 2u:  { return vec3<f32>(   s,  1.0,    t); }
+        case 3u:  { return vec3<f32>(   s, -1.0,   -t); }
+        case 4u:  { return vec3<f32>(   s,   -t,  1.0); }
+        default:  { return vec3<f32>(  -s,   -t, -1.0); }
+    }
+}
+
+@fragment
+fn fs_cube(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source_cube, source_sampler, cube_face_direction(layer, in.tex_coords), 0.0);
+}
+
+@fragment
+fn fs_equirect(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(cube_face_direction(layer, in.tex_coords));
+
+    // ---> Longitude 0 (image centre) looks down -Z, latitude 0 (top row) is +Y:
+    let u = 0.5 + atan2(direction.x, -direction.z) / 6.28318530718;
+    let v = acos(clamp(direction.y, -1.0, 1.0)) / 3.14159265359;
+
+    return textureSampleLevel(source_texture, source_sampler, vec2<f32>(u, v), 0.0);
+}
+///// MIPMAP BLIT SHADER ///////////////////////////////////////////////////////////////////////////
@@ -4,11 +4,35 @@
 
 */
 
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
 use wgpu::util::DeviceExt;
 use nalgebra_glm as glm;
 use crate::gpu::GPU;
+use crate::custom_material::CustomMaterial;
 use crate::material::Material;
-use crate::texture::create_default_texture;
+use crate::material::MaterialBindings;
+use crate::material::MaterialTextures;
+use crate::material::MaterialUniform;
+use crate::material::Clearcoat;
+use crate::material::Sheen;
+use crate::material::Specular;
+use crate::material::TextureSlot;
+use crate::material::TextureTransform;
+use crate::pipeline::AlphaMode;
+use crate::pipeline::PassKind;
+use crate::pipeline::PipelineCache;
+use crate::pipeline::PipelineKey;
+use crate::assets::AssetCache;
+use crate::ktx::Ktx2Image;
+use crate::ktx::is_ktx2;
+use crate::streaming::MipChain;
+use crate::streaming::StreamSource;
+use crate::texture::SamplerKey;
+use crate::texture::Texture;
+use crate::texture::TextureRole;
 use crate::texture::load_texture_from_image;
 use crate::vertex::Vertex;
 
@@ -17,21 +41,27 @@ use crate::vertex::Vertex;
 ///// MESH STRUCTURE ///////////////////////////////////////////////////////////////////////////////
 #[derive(Debug)]
 pub struct Mesh {
-    pub name          : String,
-    pub vertex_buffer : wgpu::Buffer,
-    pub index_buffer  : wgpu::Buffer,
-    pub num_indices   : u32,
-    pub material_index: usize,
+    pub name             : String,
+    pub vertex_buffer    : wgpu::Buffer,
+    pub index_buffer     : wgpu::Buffer,
+    pub num_indices      : u32,
+    pub material_index   : usize,
+    pub variant_materials: HashMap<usize, usize>,  // KHR_materials_variants: variant -> material index
+    pub center           : glm::Vec3,  // Bounding sphere (model space), used for texture streaming
+    pub radius           : f32,
 }
 
 impl Clone for Mesh {
     fn clone(&self) -> Self {
         Self { 
-            name          : self.name.clone(), 
-            vertex_buffer : self.vertex_buffer.clone(), 
-            index_buffer  : self.index_buffer.clone(), 
-            num_indices   : self.num_indices, 
-            material_index: self.material_index,
+            name             : self.name.clone(), 
+            vertex_buffer    : self.vertex_buffer.clone(), 
+            index_buffer     : self.index_buffer.clone(), 
+            num_indices      : self.num_indices, 
+            material_index   : self.material_index,
+            variant_materials: self.variant_materials.clone(),
+            center           : self.center,
+            radius           : self.radius,
         }
     }
 }
@@ -40,15 +70,171 @@ impl Clone for Mesh {
 ///// MODEL STRUCTURE //////////////////////////////////////////////////////////////////////////////
 #[derive(Debug)]
 pub struct Model {
-    pub meshes   : Vec<Mesh>,
-    pub materials: Vec<Material>,
+    pub meshes            : Vec<Arc<Mesh>>,      // Shared with the asset cache
+    pub materials         : Vec<Arc<Material>>,
+    pub material_overrides: HashMap<usize, Arc<CustomMaterial>>,  // Mesh index -> custom material
+    pub variants          : Vec<String>,    // KHR_materials_variants names
+    pub active_variant    : Option<usize>,  // None = the default materials
+    pub instances         : Option<(wgpu::Buffer, u32)>,  // Per instance transforms (INSTANCING), None = model uniform
 }
 
 impl Clone for Model {
     fn clone(&self) -> Self {
         Self { 
-            meshes   : self.meshes.clone(), 
-            materials: self.materials.clone(),
+            meshes            : self.meshes.clone(), 
+            materials         : self.materials.clone(),
+            material_overrides: self.material_overrides.clone(),
+            variants          : self.variants.clone(),
+            active_variant    : self.active_variant,
+            instances         : self.instances.clone(),
+        }
+    }
+}
+
+impl Model {
+    /// Draws opaque and alpha masked meshes, grouped by pipeline variant. The G-buffer pass skips
+    /// forward only materials (see `draw_forward_only`).
+    pub fn draw_opaque(&self, 
+                       render_pass: &mut wgpu::RenderPass, 
+                       pipelines  : &PipelineCache, 
+                       pass       : PassKind) {
+        let mut meshes = (0..self.meshes.len()).filter(|&index| !self.is_transparent(index))
+                                               .filter(|&index| pass == PassKind::Forward || !self.is_forward_only(index))
+                                               .collect::<Vec<_>>();
+        meshes.sort_by_key(|&index| self.sort_key(index));
+        self.draw_meshes(render_pass, pipelines, pass, &meshes);
+    }
+
+    /// Draws the opaque meshes the G-buffer pass skipped, forward shaded (deferred path only).
+    pub fn draw_forward_only(&self, 
+                             render_pass: &mut wgpu::RenderPass, 
+                             pipelines  : &PipelineCache) {
+        let mut meshes = (0..self.meshes.len()).filter(|&index| !self.is_transparent(index) && self.is_forward_only(index))
+                                               .collect::<Vec<_>>();
+        meshes.sort_by_key(|&index| self.sort_key(index));
+        self.draw_meshes(render_pass, pipelines, PassKind::Forward, &meshes);
+    }
+
+    /// Draws alpha blended and transmissive meshes back to front (forward shading, the G-buffer 
+    /// can't blend and transmission needs the opaque scene colour).
+    pub fn draw_transparent(&self, 
+                            render_pass: &mut wgpu::RenderPass, 
+                            pipelines  : &PipelineCache, 
+                            eye        : &glm::Vec3) {
+        let mut meshes = (0..self.meshes.len()).filter(|&index| self.is_transparent(index))
+                                               .collect::<Vec<_>>();
+        let distance = |index: usize| glm::distance2(&self.meshes[index].center, eye);
+        meshes.sort_by(|&a, &b| distance(b).total_cmp(&distance(a)));
+        self.draw_meshes(render_pass, pipelines, PassKind::Forward, &meshes);
+    }
+
+    /// Does any mesh need a copy of the opaque scene colour?
+    pub fn has_transmission(&self) -> bool {
+        (0..self.meshes.len()).any(|index| self.material(index).is_some_and(|material| material.is_transmissive()))
+    }
+
+    pub fn has_forward_only(&self) -> bool {
+        (0..self.meshes.len()).any(|index| !self.is_transparent(index) && self.is_forward_only(index))
+    }
+
+    /// Switches every mesh to its material of variant `name` (meshes without a mapping for it keep
+    /// their default material), `None` goes back to the defaults.
+    pub fn activate_variant(&mut self, name: Option<&str>) -> anyhow::Result<()> {
+        self.active_variant = match name {
+            Some(name) => Some(self.variants.iter()
+                                            .position(|variant| variant == name)
+                                            .ok_or_else(|| anyhow::anyhow!("Unknown material variant '{}'", name))?),
+            None       => None,
+        };
+        Ok(())
+    }
+
+    /// Material of a mesh under the active variant.
+    pub fn material_index(&self, index: usize) -> usize {
+        let mesh = &self.meshes[index];
+        self.active_variant.and_then(|variant| mesh.variant_materials.get(&variant).copied())
+                           .unwrap_or(mesh.material_index)
+    }
+
+    /// glTF material of a mesh, `None` if a custom material overrides it.
+    fn material(&self, index: usize) -> Option<&Arc<Material>> {
+        if self.material_overrides.contains_key(&index) {
+            return None;
+        }
+        self.materials.get(self.material_index(index))
+    }
+
+    fn is_transparent(&self, index: usize) -> bool {
+        match self.material_overrides.get(&index) {
+            Some(custom) => custom.shader.definition.alpha_mode == AlphaMode::Blend,
+            None         => self.material(index).is_some_and(|material| {
+                material.alpha_mode == AlphaMode::Blend || material.is_transmissive()
+            }),
+        }
+    }
+
+    fn is_forward_only(&self, index: usize) -> bool {
+        self.material(index).is_some_and(|material| material.is_forward_only())
+    }
+
+    fn pipeline_key(&self, index: usize, pass: PassKind) -> PipelineKey {
+        let key = match (self.material_overrides.get(&index), self.material(index)) {
+            (Some(custom), _)      => custom.shader.pipeline_key(pass),
+            (None, Some(material)) => material.pipeline_key(pass),
+            (None, None)           => PipelineKey::new(pass, false, AlphaMode::Opaque, false),
+        };
+        PipelineKey { instanced: self.instances.is_some(), ..key }
+    }
+
+    /// Groups meshes of the same pipeline variant.
+    fn sort_key(&self, index: usize) -> (AlphaMode, bool, bool, u64) {
+        let key = self.pipeline_key(index, PassKind::Forward);
+        (key.alpha_mode, key.cull_mode.is_none(), key.unlit, key.shader)
+    }
+
+    fn draw_meshes(&self, 
+                   render_pass: &mut wgpu::RenderPass, 
+                   pipelines  : &PipelineCache, 
+                   pass       : PassKind, 
+                   meshes     : &[usize]) {
+        let mut current = None;
+        for &index in meshes {
+            let mesh   = &self.meshes[index];
+            let custom = self.material_overrides.get(&index);
+
+            // ---> Only switch pipelines between variants:
+            let key = self.pipeline_key(index, pass);
+            if current != Some(key) {
+                let pipeline = match custom {
+                    Some(custom) => pipelines.get_custom(&custom.shader, &key),
+                    None         => pipelines.get(&key),
+                };
+                render_pass.set_pipeline(&pipeline);
+                current = Some(key);
+            }
+
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), 
+                                         wgpu::IndexFormat::Uint32);
+
+            // ---> Instanced models draw every mesh once per instance:
+            let instance_count = match &self.instances {
+                Some((buffer, count)) => {
+                    render_pass.set_vertex_buffer(1, buffer.slice(..));
+                    *count
+                }
+                None => 1,
+            };
+            
+            // ---> Set material bind group (if implemented):
+            if let Some(custom) = custom {
+                render_pass.set_bind_group(2, custom.bind_group(), &[]);
+            } else if let Some(material) = self.material(index) {
+                render_pass.set_bind_group(2, &material.bindings.bind_group(), &[]);
+            }
+            
+            // ===>>> DRAW !!!
+            render_pass.draw_indexed(0..mesh.num_indices, 0, 0..instance_count);
         }
     }
 }
@@ -154,19 +340,164 @@ impl ModelUniformState {
 }
 ///// MODEL UNIFORM STATE STRUCTURE ////////////////////////////////////////////////////////////////
 
+///// GLTF SOURCE STRUCTURE ////////////////////////////////////////////////////////////////////////
+const TEXTURE_TRANSFORM: &str = "KHR_texture_transform";
+
+/// A material texture with the UV set and transform it is sampled with.
+struct TextureRef<'a> {
+    texture  : gltf::Texture<'a>,
+    transform: TextureTransform,
+}
+
+impl<'a> TextureRef<'a> {
+    fn new(texture: gltf::Texture<'a>, tex_coord: u32, transform: Option<&gltf::json::Value>) -> Self {
+        Self { texture, transform: TextureTransform::from_gltf(tex_coord, transform) }
+    }
+}
+
+/// Where the images of a glTF file come from; they are decoded on first use only.
+struct GltfSource<'a> {
+    path    : &'a str,
+    document: &'a gltf::Document,
+    base    : &'a Path,
+    buffers : &'a [gltf::buffer::Data],
+}
+
+impl<'a> GltfSource<'a> {
+    /// Texture reference inside the raw JSON of an extension (`{ "index": 0, "texCoord": 0 }`).
+    fn extension_texture(&self, extension: &gltf::json::Value, key: &str) -> Option<TextureRef<'a>> {
+        let info    = extension.get(key)?;
+        let texture = self.document.textures().nth(info.get("index")?.as_u64()? as usize)?;
+        let uv_set  = info.get("texCoord").and_then(|tex_coord| tex_coord.as_u64()).unwrap_or(0) as u32;
+        Some(TextureRef::new(texture, uv_set, info.get("extensions").and_then(|extensions| extensions.get(TEXTURE_TRANSFORM))))
+    }
+
+
+    /// Loads the texture of a material slot. A `KHR_texture_basisu` (KTX2) image is preferred,
+    /// if it can't be used (UASTC payload, format unsupported by the adapter) the core glTF
+    /// image is the fallback.
+    fn load_texture(&self,
+                    assets : &mut AssetCache,
+                    texture: gltf::Texture,
+                    role   : TextureRole,
+                    device : &wgpu::Device,
+                    queue  : &wgpu::Queue) -> anyhow::Result<Option<Arc<Texture>>> {
+        let sampler = SamplerKey::from_gltf(&texture.sampler());
+
+        let basisu = texture.extensions()
+                            .and_then(|extensions| extensions.get("KHR_texture_basisu"))
+                            .and_then(|basisu| basisu.get("source"))
+                            .and_then(|source| source.as_u64())
+                            .and_then(|index| self.document.images().nth(index as usize));
+
+        if let Some(image) = basisu {
+            match self.load_image(assets, &image, role, &sampler, device, queue) {
+                Ok(texture) => return Ok(Some(texture)),
+                Err(e) if texture.source().is_some() => {
+                    eprintln!("{}: KTX2 image {} not usable ({}), using the fallback image", 
+                              self.path, image.index(), e);
+                },
+                Err(e) => return Err(e),
+            }
+        }
+
+        match texture.source() {
+            Some(image) => Ok(Some(self.load_image(assets, &image, role, &sampler, device, queue)?)),
+            None        => Ok(None),
+        }
+    }
+
+    fn load_image(&self,
+                  assets : &mut AssetCache,
+                  image  : &gltf::Image,
+                  role   : TextureRole,
+                  sampler: &SamplerKey,
+                  device : &wgpu::Device,
+                  queue  : &wgpu::Queue) -> anyhow::Result<Arc<Texture>> {
+        let label = format!("{}#{} ({:?})", self.path, image.index(), role);
+
+        // ---> Streamed: a placeholder now, the image is decoded in the background:
+        if assets.is_streaming() && !self.is_data_uri(image) {
+            return assets.streamed_texture(self.path, image.index(), role, sampler, device, queue, &label, || {
+                let bytes = self.image_bytes(image)?;
+                if self.is_ktx2(image) {
+                    let chain = MipChain::from_ktx2(Ktx2Image::parse(&bytes, device.features())?.with_role(role), device.features())?;
+                    return Ok(StreamSource::Chain(chain));
+                }
+                Ok(StreamSource::Encoded(bytes))
+            });
+        }
+
+        assets.texture(self.path, image.index(), role, sampler, |loader| {
+            if self.is_ktx2(image) {
+                let bytes = self.image_bytes(image)?;
+                return Ktx2Image::parse(&bytes, device.features())?.with_role(role)
+                                                .upload(device, queue, loader, sampler, Some(&label));
+            }
+
+            let data = gltf::image::Data::from_source(image.source(), Some(self.base), self.buffers)?;
+            load_texture_from_image(&data, role, sampler, device, queue, loader, Some(&label))
+        })
+    }
+
+    fn is_data_uri(&self, image: &gltf::Image) -> bool {
+        matches!(image.source(), gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:"))
+    }
+
+    fn is_ktx2(&self, image: &gltf::Image) -> bool {
+        match image.source() {
+            gltf::image::Source::View { mime_type, .. } => mime_type == "image/ktx2",
+            gltf::image::Source::Uri { uri, mime_type } => {
+                mime_type == Some("image/ktx2") || uri.ends_with(".ktx2") || 
+                (!uri.starts_with("data:") && self.file_has_ktx2_magic(uri))
+            },
+        }
+    }
+
+    /// Only the 12 byte identifier of an external file is read.
+    fn file_has_ktx2_magic(&self, uri: &str) -> bool {
+        let path      = self.base.join(uri.strip_prefix("file://").unwrap_or(uri));
+        let mut magic = [0u8; 12];
+        std::fs::File::open(path).and_then(|mut file| file.read_exact(&mut magic))
+                                 .is_ok_and(|_| is_ktx2(&magic))
+    }
+
+    /// Encoded bytes of an image (buffer view or external file).
+    fn image_bytes(&self, image: &gltf::Image) -> anyhow::Result<Vec<u8>> {
+        match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = &self.buffers[view.buffer().index()];
+                Ok(buffer[view.offset()..view.offset() + view.length()].to_vec())
+            },
+            gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+                anyhow::bail!("Embedded (data URI) KTX2 images are not supported")
+            },
+            gltf::image::Source::Uri { uri, .. } => {
+                let path = self.base.join(uri.strip_prefix("file://").unwrap_or(uri));
+                std::fs::read(&path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))
+            },
+        }
+    }
+}
+///// GLTF SOURCE STRUCTURE ////////////////////////////////////////////////////////////////////////
+
 ///// MODEL LOADING PROCEDURE //////////////////////////////////////////////////////////////////////
 pub fn load_model(file_name: &str, 
                   device: &wgpu::Device, 
                   queue: &wgpu::Queue,
-                  material_bind_group_layout: &wgpu::BindGroupLayout) -> anyhow::Result<Model> {
-    // ---> Load gltf-file:
-    let (document, buffers, images) = gltf::import(file_name)?;
+                  material_bind_group_layout: &wgpu::BindGroupLayout,
+                  assets: &mut AssetCache) -> anyhow::Result<Model> {
+    // ---> Load gltf-file (images are loaded per texture, see GltfSource):
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(file_name)?;
+    let base    = Path::new(file_name).parent().unwrap_or_else(|| Path::new("./"));
+    let buffers = gltf::import_buffers(&document, Some(base), blob)?;
+    let source  = GltfSource { path: file_name, document: &document, base, buffers: &buffers };
 
     let mut meshes = Vec::new();
     let mut materials = Vec::new();
 
     // ---> Create default white texture for materials without texture:
-    let default_texture = create_default_texture(device, queue)?;
+    let default_texture = assets.default_texture(device, queue)?;
     
     // ---> Load materials:
     for material in document.materials() {
@@ -179,89 +510,132 @@ pub fn load_model(file_name: &str,
         let base_color_factor = pbr.base_color_factor();
         let metallic_factor = pbr.metallic_factor();
         let roughness_factor = pbr.roughness_factor();
-
-        // ---> Load diffuse/albedo texture:
-        let diffuse_texture = if let Some(info) = pbr.base_color_texture() {
-            let image = &images[info.texture().index()];
-            Some(load_texture_from_image(image, device, queue, Some(&format!("{}_diffuse", name)))?)
-        } else {
-            None
-        };
-
-        // ---> Load normal map (optional):
-        let normal_texture = if let Some(info) = material.normal_texture() {
-            let image = &images[info.texture().index()];
-            Some(load_texture_from_image(image, device, queue, Some(&format!("{}_normal", name)))?)
-        } else {
-            None
+        let emissive_factor = material.emissive_factor();
+        let emissive_strength = material.emissive_strength().unwrap_or(1.0);
+        let occlusion_strength = material.occlusion_texture().map(|info| info.strength()).unwrap_or(1.0);
+        let alpha_mode = AlphaMode::from_gltf(material.alpha_mode());
+        let alpha_cutoff = material.alpha_cutoff().unwrap_or(0.5);
+        let double_sided = material.double_sided();
+        let unlit = material.unlit();
+
+        // ---> Extended PBR extensions (clearcoat and sheen are not parsed by gltf, see material.rs):
+        let clearcoat_json = material.extension_value("KHR_materials_clearcoat");
+        let sheen_json     = material.extension_value("KHR_materials_sheen");
+        let clearcoat      = clearcoat_json.map(Clearcoat::from_gltf).unwrap_or_default();
+        let sheen          = sheen_json.map(Sheen::from_gltf).unwrap_or_default();
+        let transmission   = material.transmission().map(|transmission| transmission.transmission_factor())
+                                                    .unwrap_or(0.0);
+        let ior            = material.ior().unwrap_or(1.5);
+        let specular       = material.specular().map(|specular| Specular {
+                                                        factor: specular.specular_factor(),
+                                                        color : specular.specular_color_factor(),
+                                                    })
+                                                    .unwrap_or_default();
+
+        // ---> Texture of every slot, with its UV set and KHR_texture_transform:
+        let mut texture_transforms = [TextureTransform::default(); TextureSlot::COUNT];
+        let mut load_slot = |slot: TextureSlot, role: TextureRole, texture: Option<TextureRef>| {
+            match texture {
+                Some(TextureRef { texture, transform }) => {
+                    texture_transforms[slot as usize] = transform;
+                    source.load_texture(assets, texture, role, device, queue)
+                },
+                None => Ok(None),
+            }
         };
 
-        // ---> Load metallic roughness texture (optional):
-        let metallic_roughness_texture = if let Some(info) = pbr.metallic_roughness_texture() {
-            let image = &images[info.texture().index()];
-            Some(load_texture_from_image(image, device, queue, Some(&format!("{}_metallic_roughness", name)))?)
-        } else {
-            None
-        };
+        let diffuse_texture = load_slot(TextureSlot::BaseColor, TextureRole::BaseColor, 
+            pbr.base_color_texture().map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                                 info.extension_value(TEXTURE_TRANSFORM))))?;
+        let normal_texture = load_slot(TextureSlot::Normal, TextureRole::Normal, 
+            material.normal_texture().map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                                  info.extension_value(TEXTURE_TRANSFORM))))?;
+        let metallic_roughness_texture = load_slot(TextureSlot::MetallicRoughness, TextureRole::MetallicRoughness, 
+            pbr.metallic_roughness_texture().map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                                         info.extension_value(TEXTURE_TRANSFORM))))?;
+        let emissive_texture = load_slot(TextureSlot::Emissive, TextureRole::Emissive, 
+            material.emissive_texture().map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                                    info.extension_value(TEXTURE_TRANSFORM))))?;
+        let occlusion_texture = load_slot(TextureSlot::Occlusion, TextureRole::Occlusion, 
+            material.occlusion_texture().map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                                     info.extension_value(TEXTURE_TRANSFORM))))?;
+        let clearcoat_texture = load_slot(TextureSlot::Clearcoat, TextureRole::Clearcoat, 
+            clearcoat_json.and_then(|json| source.extension_texture(json, "clearcoatTexture")))?;
+        let clearcoat_roughness_texture = load_slot(TextureSlot::ClearcoatRoughness, TextureRole::Clearcoat, 
+            clearcoat_json.and_then(|json| source.extension_texture(json, "clearcoatRoughnessTexture")))?;
+        let clearcoat_normal_texture = load_slot(TextureSlot::ClearcoatNormal, TextureRole::ClearcoatNormal, 
+            clearcoat_json.and_then(|json| source.extension_texture(json, "clearcoatNormalTexture")))?;
+        let sheen_color_texture = load_slot(TextureSlot::SheenColor, TextureRole::SheenColor, 
+            sheen_json.and_then(|json| source.extension_texture(json, "sheenColorTexture")))?;
+        let sheen_roughness_texture = load_slot(TextureSlot::SheenRoughness, TextureRole::SheenRoughness, 
+            sheen_json.and_then(|json| source.extension_texture(json, "sheenRoughnessTexture")))?;
+        let transmission_texture = load_slot(TextureSlot::Transmission, TextureRole::Transmission, 
+            material.transmission().and_then(|transmission| transmission.transmission_texture())
+                    .map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                info.extension_value(TEXTURE_TRANSFORM))))?;
+        let specular_texture = load_slot(TextureSlot::Specular, TextureRole::Specular, 
+            material.specular().and_then(|specular| specular.specular_texture())
+                    .map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                info.extension_value(TEXTURE_TRANSFORM))))?;
+        let specular_color_texture = load_slot(TextureSlot::SpecularColor, TextureRole::SpecularColor, 
+            material.specular().and_then(|specular| specular.specular_color_texture())
+                    .map(|info| TextureRef::new(info.texture(), info.tex_coord(), 
+                                                info.extension_value(TEXTURE_TRANSFORM))))?;
 
         // ---> Create bind group for this material:
-        let bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor { 
-                label  : Some(&format!("Material Bind Group: {}", name)), 
-                layout : &material_bind_group_layout, 
-                entries: &[
-                    wgpu::BindGroupEntry { // Diffuse texture
-                        binding : 0,
-                        resource: wgpu::BindingResource::TextureView(
-                            &diffuse_texture.as_ref().unwrap_or(&default_texture).view,
-                        ),
-                    },
-                    wgpu::BindGroupEntry { // Diffuse sampler
-                        binding : 1,
-                        resource: wgpu::BindingResource::Sampler(
-                            &diffuse_texture.as_ref().unwrap_or(&default_texture).sampler,
-                        ),
-                    },
-                    wgpu::BindGroupEntry { // Normal texture
-                        binding : 2,
-                        resource: wgpu::BindingResource::TextureView(
-                            &normal_texture.as_ref().unwrap_or(&default_texture).view,
-                        ),
-                    },
-                    wgpu::BindGroupEntry { // Normal sampler
-                        binding : 3,
-                        resource: wgpu::BindingResource::Sampler(
-                            &normal_texture.as_ref().unwrap_or(&default_texture).sampler,
-                        ),
-                    },
-                    wgpu::BindGroupEntry { // Metallic roughness texture
-                        binding : 4,
-                        resource: wgpu::BindingResource::TextureView(
-                            &metallic_roughness_texture.as_ref().unwrap_or(&default_texture).view,
-                        ),
-                    },
-                    wgpu::BindGroupEntry { // Metallic roughness sampler
-                        binding : 5,
-                        resource: wgpu::BindingResource::Sampler(
-                            &metallic_roughness_texture.as_ref().unwrap_or(&default_texture).sampler,
-                        ),
-                    },
-                ],
-            },
-        );
+        let clearcoat_has_normal = clearcoat_normal_texture.is_some();
+        let textures = MaterialTextures { 
+            diffuse_texture, 
+            normal_texture, 
+            metallic_roughness_texture, 
+            emissive_texture, 
+            occlusion_texture,
+            clearcoat_texture,
+            clearcoat_roughness_texture,
+            clearcoat_normal_texture,
+            sheen_color_texture,
+            sheen_roughness_texture,
+            transmission_texture,
+            specular_texture,
+            specular_color_texture,
+        };
+        let uniform  = MaterialUniform {
+            base_color        : base_color_factor,
+            emissive          : emissive_factor.map(|value| value * emissive_strength),
+            occlusion_strength,
+            // ---> 0 outside MASK, the GL backend can reuse a program across ALPHA_MASK variants:
+            alpha_cutoff      : if alpha_mode == AlphaMode::Mask { alpha_cutoff } else { 0.0 },
+            transmission,
+            ior,
+            metallic          : metallic_factor,
+            roughness         : roughness_factor,
+            clearcoat         : [clearcoat.factor, clearcoat.roughness, clearcoat.normal_scale, 
+                                 if clearcoat_has_normal { 1.0 } else { 0.0 }],
+            sheen             : [sheen.color[0], sheen.color[1], sheen.color[2], sheen.roughness],
+            specular          : [specular.color[0], specular.color[1], specular.color[2], specular.factor],
+            uv_transforms     : texture_transforms.map(|transform| transform.rows()),
+            ..Default::default()
+        };
+        let bindings = MaterialBindings::new(device, material_bind_group_layout, default_texture.clone(), 
+                                             textures, &uniform, &name);
 
-        materials.push(
+        materials.push(Arc::new(
             Material { 
                 name, 
-                diffuse_texture, 
-                normal_texture, 
-                metallic_roughness_texture, 
-                base_color_factor, 
-                metallic_factor, 
-                roughness_factor, 
-                bind_group,
+                occlusion_strength,
+                alpha_mode,
+                alpha_cutoff,
+                double_sided,
+                unlit,
+                clearcoat,
+                sheen,
+                specular,
+                transmission,
+                ior,
+                texture_transforms,
+                bindings,
             },
-        );
+        ));
     }
 
     // ---> Load all meshes:
@@ -284,6 +658,16 @@ pub fn load_model(file_name: &str,
                                    .map(|iter| iter.into_f32().collect::<Vec<_>>())
                                    .unwrap_or_else(|| vec![[0.0, 0.0]; positions.len()]);
             
+            // ---> Load second texture coordinate set (falls back to the first one):
+            let tex_coords_1 = reader.read_tex_coords(1)
+                                     .map(|iter| iter.into_f32().collect::<Vec<_>>())
+                                     .unwrap_or_else(|| tex_coords.clone());
+            
+            // ---> Load vertex colours (u8/u16 normalized or float, RGB or RGBA):
+            let colors = reader.read_colors(0)
+                               .map(|iter| iter.into_rgba_f32().collect::<Vec<_>>())
+                               .unwrap_or_else(|| vec![[1.0; 4]; positions.len()]);
+            
             // ---> Load tangents:
             let tangents = reader.read_tangents()
                                  .map(|iter| iter.map(|t| [t[0], t[1], t[2]]).collect())
@@ -310,13 +694,17 @@ pub fn load_model(file_name: &str,
                                                  .zip(tex_coords.iter())
                                                  .zip(tangents.iter())
                                                  .zip(bitangents.iter())
-                                                 .map(|((((p, n), tc), t), b)| {
+                                                 .zip(tex_coords_1.iter())
+                                                 .zip(colors.iter())
+                                                 .map(|((((((p, n), tc), t), b), tc1), c)| {
                                                     Vertex {
-                                                        position  : *p,
-                                                        normal    : *n,
-                                                        tex_coords: *tc,
-                                                        tangent   : *t,
-                                                        bitangent : *b,
+                                                        position    : *p,
+                                                        normal      : *n,
+                                                        tex_coords  : *tc,
+                                                        tangent     : *t,
+                                                        bitangent   : *b,
+                                                        tex_coords_1: *tc1,
+                                                        color       : *c,
                                                     }
                                                  }).collect::<Vec<_>>();
 
@@ -336,18 +724,54 @@ pub fn load_model(file_name: &str,
                 }
             );
             let material_index = primitive.material().index().unwrap_or(0);
+            let variant_materials = primitive.mappings()
+                                             .flat_map(|mapping| {
+                                                 let material = mapping.material().index().unwrap_or(0);
+                                                 mapping.variants().iter().map(move |&variant| (variant as usize, material))
+                                             })
+                                             .collect::<HashMap<_, _>>();
+
+            // ---> Bounding sphere around the box centre:
+            let (center, radius) = bounding_sphere(&positions);
 
             // ---> Create Mesh and push to list:
-            meshes.push(Mesh { 
+            meshes.push(Arc::new(Mesh { 
                 name: mesh.name().unwrap_or("unnamed").to_string(), 
                 vertex_buffer: vertex_buffer, 
                 index_buffer: index_buffer, 
                 num_indices: indices.len() as u32, 
                 material_index: material_index, 
-            });
+                variant_materials,
+                center,
+                radius,
+            }));
         }
     }
 
-    Ok(Model { meshes, materials })
+    // ---> Material variants (KHR_materials_variants), mapped per primitive above:
+    let variants = document.variants()
+                           .map(|variants| variants.map(|variant| variant.name().to_string()).collect())
+                           .unwrap_or_default();
+
+    Ok(Model { meshes, materials, material_overrides: HashMap::new(), variants, active_variant: None, instances: None })
+}
+
+fn bounding_sphere(positions: &[[f32; 3]]) -> (glm::Vec3, f32) {
+    if positions.is_empty() {
+        return (glm::Vec3::zeros(), 0.0);
+    }
+
+    let mut min = glm::Vec3::repeat(f32::MAX);
+    let mut max = glm::Vec3::repeat(f32::MIN);
+    for p in positions {
+        min = glm::min2(&min, &glm::Vec3::from_row_slice(p));
+        max = glm::max2(&max, &glm::Vec3::from_row_slice(p));
+    }
+
+    let center = (min + max) * 0.5;
+    let radius = positions.iter()
+                          .map(|p| glm::distance(&center, &glm::Vec3::from_row_slice(p)))
+                          .fold(0.0, f32::max);
+    (center, radius)
 }
 ///// MODEL LOADING PROCEDURE //////////////////////////////////////////////////////////////////////
@@ -0,0 +1,297 @@
+/*
+
+    Render pipeline variants per material state.
+
+    Materials differ in face culling (glTF `doubleSided`), blending, alpha mode and shading
+    (KHR_materials_unlit materials use their own fragment entry points). Each
+    combination (per pass) is its own pipeline, created on first use and then shared through
+    the cache, so switching between materials only costs a `set_pipeline`.
+
+    Alpha masking is compiled in through the `ALPHA_MASK` pipeline-overridable constant of
+    `shader.wgsl`; opaque materials do not pay for the test. Normal mapping is a shader
+    permutation instead (`NORMAL_MAP`, see shader_library.rs), materials without a normal texture
+    use a module that does not sample it. Instanced models use the `INSTANCING` permutation, which
+    reads the model transform from a second, per instance vertex buffer (instance.rs).
+
+    Custom materials (custom_material.rs) bring their own shader module and material bind group
+    layout, their variants are cached under the id of their `CustomShader`.
+
+*/
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::deferred::GBUFFER_ALBEDO_FORMAT;
+use crate::deferred::GBUFFER_EMISSIVE_FORMAT;
+use crate::deferred::GBUFFER_MATERIAL_FORMAT;
+use crate::deferred::GBUFFER_NORMAL_FORMAT;
+use crate::custom_material::CustomShader;
+use crate::instance::InstanceRaw;
+use crate::shader_library::ShaderDefines;
+use crate::shader_library::ShaderLibrary;
+use crate::vertex::Vertex;
+
+
+///// MATERIAL STATE ENUMS /////////////////////////////////////////////////////////////////////////
+/// glTF `alphaMode`. Ordered the way meshes are drawn: opaque, masked, blended last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask,
+    Blend,
+}
+
+impl AlphaMode {
+    pub fn from_gltf(mode: gltf::material::AlphaMode) -> Self {
+        match mode {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask   => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend  => AlphaMode::Blend,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Replace,
+    Alpha,    // Straight (non premultiplied) alpha, no depth writes
+}
+
+/// Which pass a pipeline renders into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PassKind {
+    Forward,  // Lit colour into the frame (fs_main)
+    GBuffer,  // Material data into the G-buffer (fs_gbuffer)
+}
+///// MATERIAL STATE ENUMS /////////////////////////////////////////////////////////////////////////
+
+
+///// PIPELINE KEY STRUCTURE ///////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub pass      : PassKind,
+    pub cull_mode : Option<wgpu::Face>,  // None = double sided
+    pub blend     : BlendMode,
+    pub alpha_mode: AlphaMode,
+    pub unlit     : bool,                // fs_unlit / fs_gbuffer_unlit
+    pub normal_map: bool,                // NORMAL_MAP permutation of shader.wgsl
+    pub instanced : bool,                // INSTANCING permutation, transforms in vertex buffer 1
+    pub shader    : u64,                 // 0 = shader.wgsl, else CustomShader::id
+}
+
+impl PipelineKey {
+    pub fn new(pass: PassKind, double_sided: bool, alpha_mode: AlphaMode, unlit: bool) -> Self {
+        Self {
+            pass,
+            cull_mode : if double_sided { None } else { Some(wgpu::Face::Back) },
+            blend     : if alpha_mode == AlphaMode::Blend { BlendMode::Alpha } else { BlendMode::Replace },
+            alpha_mode,
+            unlit,
+            normal_map: false,
+            instanced : false,
+            shader    : 0,
+        }
+    }
+}
+///// PIPELINE KEY STRUCTURE ///////////////////////////////////////////////////////////////////////
+
+
+///// PIPELINE CACHE STRUCTURE /////////////////////////////////////////////////////////////////////
+pub struct PipelineCache {
+    device        : wgpu::Device,
+    shaders       : Arc<ShaderLibrary>,
+    forward_layout: wgpu::PipelineLayout,
+    gbuffer_layout: wgpu::PipelineLayout,
+    camera_bgl    : wgpu::BindGroupLayout,  // For the layouts of custom materials
+    model_bgl     : wgpu::BindGroupLayout,
+    scene_bgl     : wgpu::BindGroupLayout,
+    surface_format: wgpu::TextureFormat,
+    pipelines     : RefCell<HashMap<PipelineKey, wgpu::RenderPipeline>>,
+}
+
+impl PipelineCache {
+    pub fn new(device        : &wgpu::Device,
+               shaders       : Arc<ShaderLibrary>,
+               surface_format: wgpu::TextureFormat,
+               camera_bgl    : &wgpu::BindGroupLayout,
+               model_bgl     : &wgpu::BindGroupLayout,
+               material_bgl  : &wgpu::BindGroupLayout,
+               scene_bgl     : &wgpu::BindGroupLayout) -> Self {
+        let forward_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label               : Some("Render Pipeline Layout"),
+                bind_group_layouts  : &[
+                    camera_bgl,    // @group(0)
+                    model_bgl,     // @group(1)
+                    material_bgl,  // @group(2)
+                    scene_bgl,     // @group(3) light + scene colour (see transmission.rs)
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        // ---> The G-buffer pass does not light anything:
+        let gbuffer_layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label               : Some("G-Buffer Pipeline Layout"),
+                bind_group_layouts  : &[
+                    camera_bgl,    // @group(0)
+                    model_bgl,     // @group(1)
+                    material_bgl,  // @group(2)
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        Self {
+            device        : device.clone(),
+            shaders,
+            forward_layout,
+            gbuffer_layout,
+            camera_bgl    : camera_bgl.clone(),
+            model_bgl     : model_bgl.clone(),
+            scene_bgl     : scene_bgl.clone(),
+            surface_format,
+            pipelines     : RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &PipelineKey) -> wgpu::RenderPipeline {
+        self.pipelines.borrow_mut().entry(*key).or_insert_with(|| self.create(key)).clone()
+    }
+
+    /// Variant of a custom material shader, `key` comes from `CustomShader::pipeline_key`.
+    pub fn get_custom(&self, shader: &CustomShader, key: &PipelineKey) -> wgpu::RenderPipeline {
+        self.pipelines.borrow_mut().entry(*key).or_insert_with(|| self.create_custom(shader, key)).clone()
+    }
+
+    /// Number of pipeline variants created so far.
+    pub fn variant_count(&self) -> usize {
+        self.pipelines.borrow().len()
+    }
+
+    fn create(&self, key: &PipelineKey) -> wgpu::RenderPipeline {
+        let constants = HashMap::from([
+            ("ALPHA_MASK".to_string(), if key.alpha_mode == AlphaMode::Mask { 1.0 } else { 0.0 }),
+        ]);
+        let (label, layout, entry_point) = match key.pass {
+            PassKind::Forward => ("Render Pipeline", &self.forward_layout, 
+                                  if key.unlit { "fs_unlit" } else { "fs_main" }),
+            PassKind::GBuffer => ("G-Buffer Pipeline", &self.gbuffer_layout, 
+                                  if key.unlit { "fs_gbuffer_unlit" } else { "fs_gbuffer" }),
+        };
+        let mut defines = ShaderDefines::new();
+        if key.normal_map {
+            defines = defines.with("NORMAL_MAP");
+        }
+        if key.instanced {
+            defines = defines.with("INSTANCING");
+        }
+        let shader  = self.shaders.load(&self.device, "shader.wgsl", &defines).unwrap_or_else(|error| panic!("{:#}", error));
+        self.create_pipeline(key, label, layout, &shader, entry_point, &constants)
+    }
+
+    fn create_custom(&self, shader: &CustomShader, key: &PipelineKey) -> wgpu::RenderPipeline {
+        let mut bind_group_layouts = vec![&self.camera_bgl, &self.model_bgl, &shader.bind_group_layout];
+        if key.pass == PassKind::Forward {
+            bind_group_layouts.push(&self.scene_bgl);
+        }
+        let layout = self.device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label               : Some(&format!("Custom Pipeline Layout: {}", shader.definition.name)),
+                bind_group_layouts  : &bind_group_layouts,
+                push_constant_ranges: &[],
+            },
+        );
+
+        let (label, entry_point) = match key.pass {
+            PassKind::Forward => ("Custom Pipeline", "fs_custom"),
+            PassKind::GBuffer => ("Custom G-Buffer Pipeline", "fs_gbuffer_custom"),
+        };
+        let label = format!("{} ({})", label, shader.definition.name);
+        let module = if key.instanced { &shader.instanced_module } else { &shader.module };
+        self.create_pipeline(key, &label, &layout, module, entry_point, &HashMap::new())
+    }
+
+    fn create_pipeline(&self,
+                       key        : &PipelineKey,
+                       label      : &str,
+                       layout     : &wgpu::PipelineLayout,
+                       module     : &wgpu::ShaderModule,
+                       entry_point: &str,
+                       constants  : &HashMap<String, f64>) -> wgpu::RenderPipeline {
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants,
+            ..Default::default()
+        };
+
+        let buffers = if key.instanced { vec![Vertex::desc(), InstanceRaw::desc()] } else { vec![Vertex::desc()] };
+
+        let blend = match key.blend {
+            BlendMode::Replace => wgpu::BlendState::REPLACE,
+            BlendMode::Alpha   => wgpu::BlendState::ALPHA_BLENDING,
+        };
+        let gbuffer_target = |format| Some(wgpu::ColorTargetState {
+            format,
+            blend     : None,
+            write_mask: wgpu::ColorWrites::ALL,
+        });
+
+        let targets = match key.pass {
+            PassKind::Forward => vec![
+                Some(wgpu::ColorTargetState {
+                    format    : self.surface_format,
+                    blend     : Some(blend),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            PassKind::GBuffer => vec![
+                gbuffer_target(GBUFFER_ALBEDO_FORMAT),
+                gbuffer_target(GBUFFER_NORMAL_FORMAT),
+                gbuffer_target(GBUFFER_MATERIAL_FORMAT),
+                gbuffer_target(GBUFFER_EMISSIVE_FORMAT),
+            ],
+        };
+
+        self.device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label        : Some(&format!("{} {:?}", label, key)),
+                layout       : Some(layout),
+                vertex       : wgpu::VertexState {
+                    module,
+                    entry_point        : Some("vs_main"),
+                    compilation_options: compilation_options.clone(),
+                    buffers            : &buffers,
+                },
+                primitive    : wgpu::PrimitiveState {
+                    topology          : wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face        : wgpu::FrontFace::Ccw,  // Right handed coordinate space!
+                    cull_mode         : key.cull_mode,
+                    unclipped_depth   : false,
+                    polygon_mode      : wgpu::PolygonMode::Fill,
+                    conservative      : false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format             : wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: key.blend == BlendMode::Replace,  // Blended surfaces are sorted instead
+                    depth_compare      : wgpu::CompareFunction::Less,
+                    stencil            : wgpu::StencilState::default(),
+                    bias               : wgpu::DepthBiasState::default(),
+                }),
+                multisample  : wgpu::MultisampleState::default(),
+                fragment     : Some(wgpu::FragmentState {
+                    module,
+                    entry_point        : Some(entry_point),
+                    compilation_options,
+                    targets            : &targets,
+                }),
+                multiview    : None,
+                cache        : None,
+             },
+        )
+    }
+}
+///// PIPELINE CACHE STRUCTURE /////////////////////////////////////////////////////////////////////
@@ -0,0 +1,686 @@
+/*
+
+    Render graph: passes declare the resources they read and write, the graph orders and culls
+    them by dependency, allocates (and aliases) transient resources and records the commands.
+
+    Passes may be declared in any order. Passes writing the same resource run in declaration
+    order, a pass that only reads a resource sees the last write declared before it (or the first
+    write, for a transient nobody wrote yet). Imported resources start with their contents from
+    outside the graph instead.
+
+*/
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+
+///// RESOURCE HANDLE STRUCTURE ////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceHandle(usize);
+///// RESOURCE HANDLE STRUCTURE ////////////////////////////////////////////////////////////////////
+
+
+///// RESOURCE DESCRIPTION STRUCTURES //////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureDesc {
+    pub width : u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage : wgpu::TextureUsages,
+}
+
+impl TextureDesc {
+    pub fn render_target(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        Self {
+            width,
+            height,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferDesc {
+    pub size : wgpu::BufferAddress,
+    pub usage: wgpu::BufferUsages,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransientDesc {
+    Texture(TextureDesc),
+    Buffer(BufferDesc),
+}
+///// RESOURCE DESCRIPTION STRUCTURES //////////////////////////////////////////////////////////////
+
+
+///// GRAPH RESOURCE STRUCTURE /////////////////////////////////////////////////////////////////////
+enum ResourceKind<'a> {
+    Transient(TransientDesc),
+    ImportedTexture(&'a wgpu::TextureView),
+    ImportedBuffer(&'a wgpu::Buffer),
+}
+
+struct GraphResource<'a> {
+    name     : String,
+    kind     : ResourceKind<'a>,
+    is_output: bool,
+}
+///// GRAPH RESOURCE STRUCTURE /////////////////////////////////////////////////////////////////////
+
+
+///// PASS BUILDER STRUCTURE ///////////////////////////////////////////////////////////////////////
+#[derive(Default)]
+pub struct PassBuilder {
+    reads : Vec<ResourceHandle>,
+    writes: Vec<ResourceHandle>,
+}
+
+impl PassBuilder {
+    pub fn read(&mut self, handle: ResourceHandle) -> ResourceHandle {
+        if !self.reads.contains(&handle) {
+            self.reads.push(handle);
+        }
+        handle
+    }
+
+    pub fn write(&mut self, handle: ResourceHandle) -> ResourceHandle {
+        if !self.writes.contains(&handle) {
+            self.writes.push(handle);
+        }
+        handle
+    }
+}
+///// PASS BUILDER STRUCTURE ///////////////////////////////////////////////////////////////////////
+
+
+///// PASS RESOURCES STRUCTURE /////////////////////////////////////////////////////////////////////
+pub struct PassResources<'r> {
+    texture_views: HashMap<ResourceHandle, &'r wgpu::TextureView>,
+    buffers      : HashMap<ResourceHandle, &'r wgpu::Buffer>,
+}
+
+impl PassResources<'_> {
+    pub fn texture_view(&self, handle: ResourceHandle) -> &wgpu::TextureView {
+        self.texture_views.get(&handle)
+            .expect("Texture was not declared as read or write by this pass!")
+    }
+
+    pub fn buffer(&self, handle: ResourceHandle) -> &wgpu::Buffer {
+        self.buffers.get(&handle)
+            .expect("Buffer was not declared as read or write by this pass!")
+    }
+}
+///// PASS RESOURCES STRUCTURE /////////////////////////////////////////////////////////////////////
+
+
+///// GRAPH PASS STRUCTURE /////////////////////////////////////////////////////////////////////////
+type PassExecute<'a> = Box<dyn FnOnce(&mut wgpu::CommandEncoder, &PassResources) + 'a>;
+
+struct GraphPass<'a> {
+    name   : String,
+    reads  : Vec<ResourceHandle>,
+    writes : Vec<ResourceHandle>,
+    execute: Option<PassExecute<'a>>,
+}
+///// GRAPH PASS STRUCTURE /////////////////////////////////////////////////////////////////////////
+
+
+///// COMPILED GRAPH STRUCTURE /////////////////////////////////////////////////////////////////////
+#[derive(Debug, Default)]
+pub struct CompiledGraph {
+    pub order       : Vec<usize>,                       // Pass indices in execution order
+    pub culled      : Vec<usize>,                       // Passes that contribute to no output
+    pub dependencies: Vec<(usize, usize)>,              // (producer, consumer) pass indices
+    pub lifetimes   : HashMap<ResourceHandle, (usize, usize)>,  // First/last use (order index)
+    pub aliases     : HashMap<ResourceHandle, usize>,  // Transient -> physical slot
+    pub slot_descs  : Vec<TransientDesc>,              // Description per physical slot
+}
+///// COMPILED GRAPH STRUCTURE /////////////////////////////////////////////////////////////////////
+
+
+///// TRANSIENT POOL STRUCTURE /////////////////////////////////////////////////////////////////////
+/// Keeps the physical transient resources alive across frames so they are not re-created
+/// every time the graph is built.
+#[derive(Default)]
+pub struct TransientPool {
+    textures: HashMap<TextureDesc, Vec<(wgpu::Texture, wgpu::TextureView)>>,
+    buffers : HashMap<BufferDesc, Vec<wgpu::Buffer>>,
+}
+
+impl TransientPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn ensure(&mut self, device: &wgpu::Device, slot_descs: &[TransientDesc]) {
+        let mut needed: HashMap<TransientDesc, usize> = HashMap::new();
+        for desc in slot_descs {
+            *needed.entry(*desc).or_insert(0) += 1;
+        }
+
+        for (desc, count) in needed {
+            match desc {
+                TransientDesc::Texture(desc) => {
+                    let textures = self.textures.entry(desc).or_default();
+                    while textures.len() < count {
+                        let texture = device.create_texture(&wgpu::TextureDescriptor {
+                            label          : Some("Render Graph Transient Texture"),
+                            size           : wgpu::Extent3d {
+                                width                : desc.width.max(1),
+                                height               : desc.height.max(1),
+                                depth_or_array_layers: 1,
+                            },
+                            mip_level_count: 1,
+                            sample_count   : 1,
+                            dimension      : wgpu::TextureDimension::D2,
+                            format         : desc.format,
+                            usage          : desc.usage,
+                            view_formats   : &[],
+                        });
+                        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                        textures.push((texture, view));
+                    }
+                }
+                TransientDesc::Buffer(desc) => {
+                    let buffers = self.buffers.entry(desc).or_default();
+                    while buffers.len() < count {
+                        buffers.push(device.create_buffer(&wgpu::BufferDescriptor {
+                            label             : Some("Render Graph Transient Buffer"),
+                            size              : desc.size,
+                            usage             : desc.usage,
+                            mapped_at_creation: false,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drops every pooled resource (e.g. after a resize made the old sizes useless).
+    pub fn clear(&mut self) {
+        self.textures.clear();
+        self.buffers.clear();
+    }
+}
+///// TRANSIENT POOL STRUCTURE /////////////////////////////////////////////////////////////////////
+
+
+///// RENDER GRAPH STRUCTURE ///////////////////////////////////////////////////////////////////////
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<GraphResource<'a>>,
+    passes   : Vec<GraphPass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //===== RESOURCE DECLARATION ===================================================================
+    pub fn create_texture(&mut self, name: &str, desc: TextureDesc) -> ResourceHandle {
+        self.add_resource(name, ResourceKind::Transient(TransientDesc::Texture(desc)), false)
+    }
+
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> ResourceHandle {
+        self.add_resource(name, ResourceKind::Transient(TransientDesc::Buffer(desc)), false)
+    }
+
+    /// Imported resources live outside of the graph and always count as outputs.
+    pub fn import_texture(&mut self, name: &str, view: &'a wgpu::TextureView) -> ResourceHandle {
+        self.add_resource(name, ResourceKind::ImportedTexture(view), true)
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: &'a wgpu::Buffer) -> ResourceHandle {
+        self.add_resource(name, ResourceKind::ImportedBuffer(buffer), true)
+    }
+
+    fn add_resource(&mut self, name: &str, kind: ResourceKind<'a>, is_output: bool) -> ResourceHandle {
+        self.resources.push(GraphResource { name: name.to_string(), kind, is_output });
+        ResourceHandle(self.resources.len() - 1)
+    }
+    //===== RESOURCE DECLARATION ===================================================================
+
+    //===== PASS DECLARATION =======================================================================
+    pub fn add_pass(&mut self,
+                    name   : &str,
+                    setup  : impl FnOnce(&mut PassBuilder),
+                    execute: impl FnOnce(&mut wgpu::CommandEncoder, &PassResources) + 'a) {
+        let mut builder = PassBuilder::default();
+        setup(&mut builder);
+
+        self.passes.push(GraphPass {
+            name   : name.to_string(),
+            reads  : builder.reads,
+            writes : builder.writes,
+            execute: Some(Box::new(execute)),
+        });
+    }
+    //===== PASS DECLARATION =======================================================================
+
+    //===== COMPILATION ============================================================================
+    pub fn compile(&self) -> anyhow::Result<CompiledGraph> {
+        let pass_count = self.passes.len();
+
+        // ---> Writers of every resource, in declaration order:
+        let mut writers: HashMap<ResourceHandle, Vec<usize>> = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            for &write in &pass.writes {
+                writers.entry(write).or_default().push(index);
+            }
+        }
+
+        // ---> Build dependency edges (write-after-write, read-after-write, write-after-read):
+        let mut dependencies = Vec::new();
+        for chain in writers.values() {
+            dependencies.extend(chain.windows(2).map(|pair| (pair[0], pair[1])));
+        }
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for &read in &pass.reads {
+                if pass.writes.contains(&read) {
+                    continue;  // Read-modify-write, ordered by the writer chain
+                }
+                let chain = writers.get(&read).map(Vec::as_slice).unwrap_or_default();
+
+                // ---> The pass sees the last write declared before it. A transient read before
+                //      any write comes from its first writer, wherever that was declared:
+                let version = match chain.iter().rposition(|&writer| writer < index) {
+                    Some(position) => Some(position),
+                    None => match self.resources[read.0].kind {
+                        ResourceKind::Transient(_) if chain.is_empty() => {
+                            anyhow::bail!("Pass '{}' reads transient '{}' that no pass writes!",
+                                          pass.name, self.resources[read.0].name);
+                        }
+                        ResourceKind::Transient(_) => Some(0),
+                        _ => None,  // Imported contents from before the frame
+                    },
+                };
+
+                if let Some(position) = version {
+                    dependencies.push((chain[position], index));
+                }
+                let next = version.map_or(0, |position| position + 1);
+                if let Some(&writer) = chain.get(next) {
+                    dependencies.push((index, writer));
+                }
+            }
+        }
+        dependencies.sort_unstable();
+        dependencies.dedup();
+
+        // ---> Cull passes that do not contribute to an output:
+        let mut alive = vec![false; pass_count];
+        let mut stack: Vec<usize> = (0..pass_count).filter(|&index| {
+            self.passes[index].writes.iter().any(|w| self.resources[w.0].is_output)
+        }).collect();
+
+        while let Some(index) = stack.pop() {
+            if alive[index] {
+                continue;
+            }
+            alive[index] = true;
+            stack.extend(dependencies.iter()
+                                     .filter(|(_, consumer)| *consumer == index)
+                                     .map(|(producer, _)| *producer));
+        }
+
+        // ---> Topological order (declaration order breaks ties):
+        let mut in_degree = vec![0usize; pass_count];
+        for &(producer, consumer) in &dependencies {
+            if alive[producer] && alive[consumer] {
+                in_degree[consumer] += 1;
+            }
+        }
+
+        let mut order = Vec::new();
+        let mut ready: Vec<usize> = (0..pass_count).filter(|&i| alive[i] && in_degree[i] == 0)
+                                                   .collect();
+        while !ready.is_empty() {
+            ready.sort_unstable_by(|a, b| b.cmp(a));
+            let index = ready.pop().unwrap();
+            order.push(index);
+
+            for &(producer, consumer) in &dependencies {
+                if producer == index && alive[consumer] {
+                    in_degree[consumer] -= 1;
+                    if in_degree[consumer] == 0 {
+                        ready.push(consumer);
+                    }
+                }
+            }
+        }
+
+        if order.len() != alive.iter().filter(|&&a| a).count() {
+            anyhow::bail!("Render graph contains a dependency cycle!");
+        }
+
+        let culled = (0..pass_count).filter(|&i| !alive[i]).collect();
+
+        // ---> Resource lifetimes over the execution order:
+        let mut lifetimes: HashMap<ResourceHandle, (usize, usize)> = HashMap::new();
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            for &handle in pass.reads.iter().chain(pass.writes.iter()) {
+                let lifetime = lifetimes.entry(handle).or_insert((position, position));
+                lifetime.1 = position;
+            }
+        }
+
+        // ---> Alias transients with identical descriptions and disjoint lifetimes:
+        let mut transients: Vec<(ResourceHandle, TransientDesc, (usize, usize))> = lifetimes
+            .iter()
+            .filter_map(|(&handle, &lifetime)| match self.resources[handle.0].kind {
+                ResourceKind::Transient(desc) => Some((handle, desc, lifetime)),
+                _ => None,
+            })
+            .collect();
+        transients.sort_by_key(|(handle, _, lifetime)| (lifetime.0, handle.0));
+
+        let mut aliases   : HashMap<ResourceHandle, usize> = HashMap::new();
+        let mut slot_descs: Vec<TransientDesc> = Vec::new();
+        let mut slot_free : Vec<usize> = Vec::new();  // Order index after which a slot is free
+
+        for (handle, desc, (first, last)) in transients {
+            let reusable = (0..slot_descs.len()).find(|&slot| {
+                slot_descs[slot] == desc && slot_free[slot] < first
+            });
+
+            let slot = match reusable {
+                Some(slot) => slot,
+                None => {
+                    slot_descs.push(desc);
+                    slot_free.push(0);
+                    slot_descs.len() - 1
+                }
+            };
+            slot_free[slot] = last;
+            aliases.insert(handle, slot);
+        }
+
+        Ok(CompiledGraph { order, culled, dependencies, lifetimes, aliases, slot_descs })
+    }
+    //===== COMPILATION ============================================================================
+
+    //===== EXECUTION ==============================================================================
+    pub fn execute(mut self,
+                   device : &wgpu::Device,
+                   encoder: &mut wgpu::CommandEncoder,
+                   pool   : &mut TransientPool) -> anyhow::Result<()> {
+        let compiled = self.compile()?;
+        pool.ensure(device, &compiled.slot_descs);
+
+        // ---> Map every slot to a physical pooled resource:
+        let mut used: HashMap<TransientDesc, usize> = HashMap::new();
+        let mut slot_physical = Vec::with_capacity(compiled.slot_descs.len());
+        for desc in &compiled.slot_descs {
+            let next = used.entry(*desc).or_insert(0);
+            slot_physical.push(*next);
+            *next += 1;
+        }
+
+        for &index in &compiled.order {
+            let pass = &mut self.passes[index];
+
+            let mut resources = PassResources {
+                texture_views: HashMap::new(),
+                buffers      : HashMap::new(),
+            };
+
+            for &handle in pass.reads.iter().chain(pass.writes.iter()) {
+                match &self.resources[handle.0].kind {
+                    ResourceKind::ImportedTexture(view) => {
+                        resources.texture_views.insert(handle, *view);
+                    }
+                    ResourceKind::ImportedBuffer(buffer) => {
+                        resources.buffers.insert(handle, *buffer);
+                    }
+                    ResourceKind::Transient(desc) => {
+                        let slot     = compiled.aliases[&handle];
+                        let physical = slot_physical[slot];
+                        match desc {
+                            TransientDesc::Texture(desc) => {
+                                resources.texture_views.insert(handle, &pool.textures[desc][physical].1);
+                            }
+                            TransientDesc::Buffer(desc) => {
+                                resources.buffers.insert(handle, &pool.buffers[desc][physical]);
+                            }
+                        }
+                    }
+                }
+            }
+
+            if let Some(execute) = pass.execute.take() {
+                encoder.push_debug_group(&pass.name);
+                execute(encoder, &resources);
+                encoder.pop_debug_group();
+            }
+        }
+
+        Ok(())
+    }
+    //===== EXECUTION ==============================================================================
+
+    //===== DEBUGGING ==============================================================================
+    /// Dumps the graph in Graphviz dot format (passes = boxes, resources = ellipses).
+    pub fn to_dot(&self) -> String {
+        let compiled = self.compile().unwrap_or_default();
+        let mut dot  = String::new();
+
+        writeln!(dot, "digraph RenderGraph {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let style = if compiled.culled.contains(&index) { "dashed" } else { "filled" };
+            let order = compiled.order.iter().position(|&i| i == index)
+                                .map(|position| format!("#{} ", position))
+                                .unwrap_or_default();
+            writeln!(dot, "    pass_{} [shape=box, style={}, fillcolor=lightblue, label=\"{}{}\"];",
+                     index, style, order, pass.name).unwrap();
+        }
+
+        for (index, resource) in self.resources.iter().enumerate() {
+            let (kind, color) = match resource.kind {
+                ResourceKind::Transient(_)       => ("transient", "lightyellow"),
+                ResourceKind::ImportedTexture(_) |
+                ResourceKind::ImportedBuffer(_)  => ("imported",  "lightgreen"),
+            };
+            let handle   = ResourceHandle(index);
+            let slot     = compiled.aliases.get(&handle)
+                                           .map(|slot| format!("\\nslot {}", slot))
+                                           .unwrap_or_default();
+            let lifetime = compiled.lifetimes.get(&handle)
+                                             .map(|(first, last)| format!("\\n#{}..#{}", first, last))
+                                             .unwrap_or_default();
+            writeln!(dot, "    res_{} [shape=ellipse, style=filled, fillcolor={}, \
+                           label=\"{}\\n({}){}{}\"];",
+                     index, color, resource.name, kind, slot, lifetime).unwrap();
+        }
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for read in &pass.reads {
+                writeln!(dot, "    res_{} -> pass_{};", read.0, index).unwrap();
+            }
+            for write in &pass.writes {
+                writeln!(dot, "    pass_{} -> res_{} [color=red];", index, write.0).unwrap();
+            }
+        }
+
+        // ---> Ordering constraints between passes (incl. write-after-read):
+        for (producer, consumer) in &compiled.dependencies {
+            writeln!(dot, "    pass_{} -> pass_{} [style=dotted, color=gray];", producer, consumer).unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+    //===== DEBUGGING ==============================================================================
+}
+///// RENDER GRAPH STRUCTURE ///////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(width: u32) -> TextureDesc {
+        TextureDesc::render_target(width, width, wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    /// Transient texture that counts as an output (stands in for an imported frame).
+    fn output(graph: &mut RenderGraph, name: &str) -> ResourceHandle {
+        let desc = TextureDesc::render_target(64, 64, wgpu::TextureFormat::Rgba16Float);
+        graph.add_resource(name, ResourceKind::Transient(TransientDesc::Texture(desc)), true)
+    }
+
+    fn pass(graph: &mut RenderGraph, name: &str, reads: &[ResourceHandle], writes: &[ResourceHandle]) {
+        graph.add_pass(name, |builder| {
+            reads.iter().for_each(|&handle| { builder.read(handle); });
+            writes.iter().for_each(|&handle| { builder.write(handle); });
+        }, |_, _| {});
+    }
+
+    #[test]
+    fn passes_are_ordered_by_dependency() {
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let gbuffer   = graph.create_texture("G-Buffer", target(64));
+        let lit       = graph.create_texture("Lit", target(64));
+
+        // ---> Declared out of order: 0 needs 2, 2 needs 1:
+        pass(&mut graph, "Composite", &[lit],     &[frame]);
+        pass(&mut graph, "Geometry",  &[],        &[gbuffer]);
+        pass(&mut graph, "Lighting",  &[gbuffer], &[lit]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.order, [1, 2, 0]);
+        assert_eq!(compiled.dependencies, [(1, 2), (2, 0)]);
+        assert_eq!(compiled.lifetimes[&lit], (1, 2));
+
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let gbuffer   = graph.create_texture("G-Buffer", target(64));
+        let shadow    = graph.create_texture("Shadow", target(32));
+
+        pass(&mut graph, "Geometry", &[],                &[gbuffer]);
+        pass(&mut graph, "Shadow",   &[],                &[shadow]);
+        pass(&mut graph, "Lighting", &[gbuffer, shadow], &[frame]);
+        pass(&mut graph, "Overlay",  &[frame],           &[frame]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.order, [0, 1, 2, 3]);
+        assert!(compiled.culled.is_empty());
+        assert_eq!(compiled.dependencies, [(0, 2), (1, 2), (2, 3)]);
+        assert_eq!(compiled.lifetimes[&gbuffer], (0, 2));
+        assert_eq!(compiled.lifetimes[&frame],   (2, 3));
+    }
+
+    #[test]
+    fn reads_come_before_later_writes() {
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let depth     = output(&mut graph, "Depth");
+        let lit       = graph.create_texture("Lit", target(64));
+
+        // ---> Lighting reads the depth of the first pass, the third one overwrites it:
+        pass(&mut graph, "Geometry",    &[],      &[depth]);
+        pass(&mut graph, "Lighting",    &[depth], &[lit]);
+        pass(&mut graph, "Transparent", &[],      &[lit, depth]);
+        pass(&mut graph, "Composite",   &[lit],   &[frame]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.order, [0, 1, 2, 3]);
+        assert!(compiled.dependencies.contains(&(1, 2)));
+
+        // ---> A transient nobody writes cannot be read, a read/write cycle is rejected:
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let missing   = graph.create_texture("Missing", target(64));
+        pass(&mut graph, "Composite", &[missing], &[frame]);
+        assert!(graph.compile().unwrap_err().to_string().contains("no pass writes"));
+
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let a         = graph.create_texture("A", target(64));
+        let b         = graph.create_texture("B", target(64));
+        pass(&mut graph, "A", &[b], &[a]);
+        pass(&mut graph, "B", &[a], &[b, frame]);
+        assert!(graph.compile().unwrap_err().to_string().contains("cycle"));
+    }
+
+    #[test]
+    fn buffers_are_transient_resources_too() {
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let desc      = BufferDesc { size: 256, usage: wgpu::BufferUsages::STORAGE };
+        let lights    = graph.create_buffer("Light List", desc);
+        let counts    = graph.create_buffer("Light Counts", desc);
+
+        pass(&mut graph, "Cull Lights",  &[],       &[lights]);  // #0  Lights: 0..1
+        pass(&mut graph, "Count Lights", &[lights], &[counts]);  // #1  Counts: 1..2
+        pass(&mut graph, "Shade",        &[counts], &[frame]);   // #2
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.order, [0, 1, 2]);
+        assert_ne!(compiled.aliases[&lights], compiled.aliases[&counts]);
+        assert_eq!(compiled.slot_descs[compiled.aliases[&lights]], TransientDesc::Buffer(desc));
+    }
+
+    #[test]
+    fn passes_without_used_outputs_are_culled() {
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let color     = graph.create_texture("Color", target(64));
+        let debug     = graph.create_texture("Debug", target(64));
+        let unused    = graph.create_texture("Unused", target(64));
+
+        pass(&mut graph, "Scene",     &[],      &[color]);
+        pass(&mut graph, "Debug",     &[color], &[debug]);   // Nobody reads `debug`...
+        pass(&mut graph, "Debug Viz", &[debug], &[unused]);  // ...or `unused`
+        pass(&mut graph, "Tonemap",   &[color], &[frame]);
+
+        let compiled = graph.compile().unwrap();
+        assert_eq!(compiled.order,  [0, 3]);
+        assert_eq!(compiled.culled, [1, 2]);
+        assert!(!compiled.aliases.contains_key(&debug));
+        assert!(!compiled.aliases.contains_key(&unused));
+        let dot = graph.to_dot();
+        assert!(dot.contains("pass_1 [shape=box, style=dashed"));
+        assert!(dot.contains("pass_0 -> pass_3 [style=dotted"));
+    }
+
+    #[test]
+    fn transients_with_disjoint_lifetimes_share_a_slot() {
+        let mut graph = RenderGraph::new();
+        let frame     = output(&mut graph, "Frame");
+        let a         = graph.create_texture("A", target(64));
+        let b         = graph.create_texture("B", target(64));
+        let c         = graph.create_texture("C", target(64));
+        let small     = graph.create_texture("Small", target(32));
+
+        pass(&mut graph, "Write A",   &[],         &[a]);      // #0  A: 0..1
+        pass(&mut graph, "A -> B",    &[a],        &[b]);      // #1  B: 1..2
+        pass(&mut graph, "B -> C",    &[b],        &[c]);      // #2  C: 2..4
+        pass(&mut graph, "Small",     &[],         &[small]);  // #3  Small: 3..4
+        pass(&mut graph, "Composite", &[c, small], &[frame]);  // #4
+
+        let compiled = graph.compile().unwrap();
+        let slot     = |handle| compiled.aliases[&handle];
+
+        // ---> A and B overlap in #1, B and C in #2, but A is free again for C:
+        assert_ne!(slot(a), slot(b));
+        assert_ne!(slot(b), slot(c));
+        assert_eq!(slot(a), slot(c));
+
+        // ---> Different descriptions never alias:
+        assert_ne!(slot(small), slot(a));
+        assert_ne!(slot(small), slot(b));
+
+        // ---> Output transient + A/C + B + Small:
+        assert_eq!(compiled.slot_descs.len(), 4);
+        assert_eq!(compiled.slot_descs[slot(small)], TransientDesc::Texture(target(32)));
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -1,8 +1,10 @@
 use nalgebra_glm as glm;
 use std::collections::HashMap;
+use std::sync::Arc;
 use wgpu::Device;
 
 use crate::instance;
+use crate::custom_material::CustomMaterial;
 use crate::model::Model;
 use crate::material::Material;
 use crate::instance::Instance;
@@ -158,6 +160,49 @@ impl SceneGraph {
         }
     }
 
+    /// Swaps material `material_index` of the node's model (its own copy of the model, the shared
+    /// one stays untouched). Returns the previous material, to restore it later.
+    pub fn replace_material(&mut self, 
+                            handle        : NodeHandle, 
+                            material_index: usize, 
+                            material      : Arc<Material>) -> Result<Arc<Material>, String> {
+        let Some(model) = self.nodes.get_mut(&handle).and_then(|node| node.model.as_mut()) else {
+            return Err("Invalid node handle or node without model".to_string());
+        };
+        let Some(slot) = model.materials.get_mut(material_index) else {
+            return Err(format!("Material index {} out of range ({} materials)", material_index, model.materials.len()));
+        };
+        Ok(std::mem::replace(slot, material))
+    }
+
+    /// Activates a material variant of the node's model (`Model::activate_variant`).
+    pub fn set_variant(&mut self, handle: NodeHandle, variant: Option<&str>) -> Result<(), String> {
+        let Some(model) = self.nodes.get_mut(&handle).and_then(|node| node.model.as_mut()) else {
+            return Err("Invalid node handle or node without model".to_string());
+        };
+        model.activate_variant(variant).map_err(|e| e.to_string())
+    }
+
+    /// Draws mesh `mesh_index` of the node's model with `material` instead of its glTF material
+    /// (`None` restores it).
+    pub fn set_material_override(&mut self, 
+                                 handle    : NodeHandle, 
+                                 mesh_index: usize, 
+                                 material  : Option<Arc<CustomMaterial>>) -> Result<(), String> {
+        let Some(model) = self.nodes.get_mut(&handle).and_then(|node| node.model.as_mut()) else {
+            return Err("Invalid node handle or node without model".to_string());
+        };
+        if mesh_index >= model.meshes.len() {
+            return Err(format!("Mesh index {} out of range ({} meshes)", mesh_index, model.meshes.len()));
+        }
+
+        match material {
+            Some(material) => model.material_overrides.insert(mesh_index, material),
+            None           => model.material_overrides.remove(&mesh_index),
+        };
+        Ok(())
+    }
+
     pub fn mark_transform_dirty(&mut self, handle: NodeHandle) {
         if !self.dirty_transforms.contains(&handle) {
             self.dirty_transforms.push(handle);
@@ -1,17 +1,9 @@
-///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-    position : vec3<f32>,    // Camera position for specular...
-    _pad     : f32,
-};
-@group(0) @binding(0) var<uniform> camera: CameraUniform;
+// Forward, G-buffer and unlit fragment shaders of the glTF materials. Permutations (see
+// pipeline.rs):
+//
+// NORMAL_MAP: the normal texture perturbs the normal, without it the interpolated normal is used.
 
-struct ModelUniform {
-    model        : mat4x4<f32>,
-    normal_matrix: mat3x3<f32>,  // Inverse transpose for normals...
-};
-@group(1) @binding(0) var<uniform> model: ModelUniform;
-///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
+#include "common.wgsl"
 
 ///// MATERIAL TEXTURES ////////////////////////////////////////////////////////////////////////////
 @group(2) @binding(0) var diffuse_texture           : texture_2d<f32>;
@@ -20,8 +12,167 @@ struct ModelUniform {
 @group(2) @binding(3) var normal_sampler            : sampler;
 @group(2) @binding(4) var metallic_roughness_texture: texture_2d<f32>;
 @group(2) @binding(5) var metallic_roughness_sampler: sampler;
+@group(2) @binding(6) var emissive_texture          : texture_2d<f32>;
+@group(2) @binding(7) var emissive_sampler          : sampler;
+@group(2) @binding(8) var occlusion_texture         : texture_2d<f32>;
+@group(2) @binding(9) var occlusion_sampler         : sampler;
+
+// ---> Extended PBR (KHR_materials_clearcoat, _sheen, _transmission, _specular):
+@group(2) @binding(10) var clearcoat_texture          : texture_2d<f32>;
+@group(2) @binding(11) var clearcoat_sampler          : sampler;
+@group(2) @binding(12) var clearcoat_roughness_texture: texture_2d<f32>;
+@group(2) @binding(13) var clearcoat_roughness_sampler: sampler;
+@group(2) @binding(14) var clearcoat_normal_texture   : texture_2d<f32>;
+@group(2) @binding(15) var clearcoat_normal_sampler   : sampler;
+@group(2) @binding(16) var sheen_color_texture        : texture_2d<f32>;
+@group(2) @binding(17) var sheen_color_sampler        : sampler;
+@group(2) @binding(18) var sheen_roughness_texture    : texture_2d<f32>;
+@group(2) @binding(19) var sheen_roughness_sampler    : sampler;
+@group(2) @binding(20) var transmission_texture       : texture_2d<f32>;
+@group(2) @binding(21) var transmission_sampler       : sampler;
+@group(2) @binding(22) var specular_texture           : texture_2d<f32>;
+@group(2) @binding(23) var specular_sampler           : sampler;
+@group(2) @binding(24) var specular_color_texture     : texture_2d<f32>;
+@group(2) @binding(25) var specular_color_sampler     : sampler;
+
+// ---> KHR_texture_transform as the two rows of a 2x3 matrix, row0.w selects the UV set:
+struct UvTransform {
+    row0: vec4<f32>,
+    row1: vec4<f32>,
+};
+
+const SLOT_BASE_COLOR          = 0u;
+const SLOT_NORMAL              = 1u;
+const SLOT_METALLIC_ROUGHNESS  = 2u;
+const SLOT_EMISSIVE            = 3u;
+const SLOT_OCCLUSION           = 4u;
+const SLOT_CLEARCOAT           = 5u;
+const SLOT_CLEARCOAT_ROUGHNESS = 6u;
+const SLOT_CLEARCOAT_NORMAL    = 7u;
+const SLOT_SHEEN_COLOR         = 8u;
+const SLOT_SHEEN_ROUGHNESS     = 9u;
+const SLOT_TRANSMISSION        = 10u;
+const SLOT_SPECULAR            = 11u;
+const SLOT_SPECULAR_COLOR      = 12u;
+
+struct MaterialUniform {
+    base_color        : vec4<f32>,
+    emissive          : vec3<f32>,  // emissive_factor * emissive_strength
+    occlusion_strength: f32,
+    alpha_cutoff      : f32,
+    transmission      : f32,
+    ior               : f32,
+    metallic          : f32,        // Multiply the metallic roughness texture
+    roughness         : f32,
+    _padding0         : f32,
+    _padding1         : f32,
+    _padding2         : f32,
+    clearcoat         : vec4<f32>,  // x = factor, y = roughness, z = normal scale, w = has normal map
+    sheen             : vec4<f32>,  // rgb = colour, a = roughness
+    specular          : vec4<f32>,  // rgb = colour, a = strength
+    uv_transforms     : array<UvTransform, 13>,  // Per texture slot, see material_uv
+};
+@group(2) @binding(26) var<uniform> material: MaterialUniform;
+
+// ---> Set per pipeline variant (see pipeline.rs):
+override ALPHA_MASK: bool = false;
+
+fn alpha_test(alpha: f32) {
+    if ALPHA_MASK && alpha < material.alpha_cutoff {
+        discard;
+    }
+}
+
+// ---> Texture coordinates of a material slot (UV set + transform):
+fn material_uv(in: VertexOutput, slot: u32) -> vec2<f32> {
+    let transform = material.uv_transforms[slot];
+    let uv        = vec3<f32>(select(in.tex_coords, in.tex_coords_1, transform.row0.w > 0.5), 1.0);
+    return vec2<f32>(dot(transform.row0.xyz, uv), dot(transform.row1.xyz, uv));
+}
+
+// ---> Base colour texture * factor * vertex colour (alpha included):
+fn sample_base_color(in: VertexOutput) -> vec4<f32> {
+    return textureSample(diffuse_texture, diffuse_sampler, material_uv(in, SLOT_BASE_COLOR)) * material.base_color
+           * in.color;
+}
+
+// ---> Emitted radiance and ambient occlusion (glTF: occlusion is read from the red channel):
+fn sample_emissive(tex_coords: vec2<f32>) -> vec3<f32> {
+    return textureSample(emissive_texture, emissive_sampler, tex_coords).rgb * material.emissive;
+}
+
+fn sample_occlusion(tex_coords: vec2<f32>) -> f32 {
+    let occlusion = textureSample(occlusion_texture, occlusion_sampler, tex_coords).r;
+    return 1.0 + material.occlusion_strength * (occlusion - 1.0);
+}
 ///// MATERIAL TEXTURES ////////////////////////////////////////////////////////////////////////////
 
+///// EXTENDED PBR /////////////////////////////////////////////////////////////////////////////////
+const PI = 3.14159265;
+
+// ---> KHR_materials_specular + _ior, relative to the F0 of 0.04 the lighting assumes (ior 1.5):
+fn specular_weight(in: VertexOutput) -> vec3<f32> {
+    let strength = textureSample(specular_texture, specular_sampler, material_uv(in, SLOT_SPECULAR)).a 
+                   * material.specular.a;
+    let color    = textureSample(specular_color_texture, specular_color_sampler, material_uv(in, SLOT_SPECULAR_COLOR)).rgb 
+                   * material.specular.rgb;
+    let r        = (material.ior - 1.0) / (material.ior + 1.0);
+    return min(r * r * color, vec3<f32>(1.0)) / 0.04 * strength;
+}
+
+// ---> KHR_materials_sheen: Charlie distribution with a simplified visibility term:
+fn sheen_light(in: VertexOutput, normal: vec3<f32>, light_dir: vec3<f32>, view_dir: vec3<f32>, 
+               halfway_dir: vec3<f32>) -> vec3<f32> {
+    let color     = textureSample(sheen_color_texture, sheen_color_sampler, material_uv(in, SLOT_SHEEN_COLOR)).rgb 
+                    * material.sheen.rgb;
+    let roughness = textureSample(sheen_roughness_texture, sheen_roughness_sampler, material_uv(in, SLOT_SHEEN_ROUGHNESS)).a 
+                    * material.sheen.a;
+    let alpha     = max(roughness * roughness, 0.01);
+
+    let n_dot_l      = max(dot(normal, light_dir), 0.0);
+    let n_dot_v      = max(dot(normal, view_dir), 0.0);
+    let n_dot_h      = max(dot(normal, halfway_dir), 0.0);
+    let distribution = (2.0 + 1.0 / alpha) * pow(max(1.0 - n_dot_h * n_dot_h, 0.0), 0.5 / alpha) / (2.0 * PI);
+    let visibility   = 1.0 / (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v) + 0.0001);
+    return color * distribution * visibility * n_dot_l;
+}
+
+// ---> KHR_materials_transmission: share of the diffuse part replaced by the scene behind:
+fn sample_transmission(in: VertexOutput) -> f32 {
+    return textureSample(transmission_texture, transmission_sampler, material_uv(in, SLOT_TRANSMISSION)).r 
+           * material.transmission;
+}
+
+fn transmitted_light(clip_position: vec4<f32>) -> vec3<f32> {
+    let uv = clip_position.xy / vec2<f32>(textureDimensions(scene_color));
+    return textureSampleLevel(scene_color, scene_sampler, uv, 0.0).rgb;
+}
+
+// ---> KHR_materials_clearcoat: a dielectric layer (normalized Blinn-Phong) over `base`:
+fn clearcoat_layer(in: VertexOutput, base: vec3<f32>, tbn_matrix: mat3x3<f32>, front_facing: bool,
+                   light_dir: vec3<f32>, view_dir: vec3<f32>, radiance: vec3<f32>) -> vec3<f32> {
+    let factor    = textureSample(clearcoat_texture, clearcoat_sampler, material_uv(in, SLOT_CLEARCOAT)).r 
+                    * material.clearcoat.x;
+    let roughness = textureSample(clearcoat_roughness_texture, clearcoat_roughness_sampler, 
+                                  material_uv(in, SLOT_CLEARCOAT_ROUGHNESS)).g * material.clearcoat.y;
+
+    // ---> Own normal map, or the geometric normal (not the base normal map):
+    let tangent_normal = (textureSample(clearcoat_normal_texture, clearcoat_normal_sampler, 
+                                        material_uv(in, SLOT_CLEARCOAT_NORMAL)).rgb * 2.0 - 1.0)
+                         * vec3<f32>(material.clearcoat.z, material.clearcoat.z, 1.0);
+    let normal         = face_normal(select(normalize(in.normal), normalize(tbn_matrix * tangent_normal), 
+                                            material.clearcoat.w > 0.5), front_facing);
+
+    let alpha     = max(roughness * roughness, 0.03);
+    let exponent  = 2.0 / (alpha * alpha) - 2.0;
+    let n_dot_l   = max(dot(normal, light_dir), 0.0);
+    let n_dot_h   = max(dot(normal, normalize(light_dir + view_dir)), 0.0);
+    let highlight = (exponent + 8.0) / (8.0 * PI) * pow(n_dot_h, exponent) * n_dot_l * radiance;
+    let fresnel   = 0.04 + 0.96 * pow(1.0 - max(dot(normal, view_dir), 0.0), 5.0);
+    return base * (1.0 - factor * fresnel) + factor * fresnel * highlight;
+}
+///// EXTENDED PBR /////////////////////////////////////////////////////////////////////////////////
+
 ///// LIGHT STRUCTURE //////////////////////////////////////////////////////////////////////////////
 struct Light {
     position : vec3<f32>,
@@ -30,62 +181,40 @@ struct Light {
     intensity: f32,
 };
 @group(3) @binding(0) var<uniform> light: Light;
-///// LIGHT STRUCTURE //////////////////////////////////////////////////////////////////////////////
 
-///// INPUT / OUTPUT STRUCTURES ////////////////////////////////////////////////////////////////////
-// ---> Input Vertex Structure:
-struct VertexInput {
-    @location(0) position  : vec3<f32>,
-    @location(1) normal    : vec3<f32>,
-    @location(2) tex_coords: vec2<f32>,
-    @location(3) tangent   : vec3<f32>,
-    @location(4) bitangent : vec3<f32>,
-};
+// ---> Opaque scene behind transmissive surfaces (see transmission.rs):
+@group(3) @binding(1) var scene_color  : texture_2d<f32>;
+@group(3) @binding(2) var scene_sampler: sampler;
+///// LIGHT STRUCTURE //////////////////////////////////////////////////////////////////////////////
 
-// ---> Output from fragment shader:
-struct VertexOutput {
-    @builtin(position)                             clip_position: vec4<f32>,
-    @location(0) @interpolate(perspective, center) frag_pos     : vec3<f32>,
-    @location(1) @interpolate(perspective, center) tex_coords   : vec2<f32>,
-    @location(2) @interpolate(perspective, center) tangent      : vec3<f32>,
-    @location(3) @interpolate(perspective, center) bitangent    : vec3<f32>,
-    @location(4) @interpolate(perspective, center) normal       : vec3<f32>,
-}
-///// INPUT / OUTPUT STRUCTURES ////////////////////////////////////////////////////////////////////
-
-///// VERTEX SHADER ////////////////////////////////////////////////////////////////////////////////
-@vertex
-fn vs_main(vertex: VertexInput) -> VertexOutput {
-    var out: VertexOutput;
-
-    // ---> World space transformation:
-    let world_position = model.model * vec4<f32>(vertex.position, 1.0);
-    out.clip_position  = camera.view_proj * world_position;
-    out.frag_pos       = world_position.xyz;
-    out.tex_coords     = vertex.tex_coords;
-
-    // ---> Construction of TBN Matrix:
-    out.tangent   = normalize(model.normal_matrix * vertex.tangent);
-    out.bitangent = normalize(model.normal_matrix * vertex.bitangent);
-    out.normal    = normalize(model.normal_matrix * vertex.normal);
+///// FRAGMENT SHADER //////////////////////////////////////////////////////////////////////////////
+// ---> Back faces (only drawn for double sided materials) are lit from their own side:
+fn face_normal(normal: vec3<f32>, front_facing: bool) -> vec3<f32> {
+    return select(-normal, normal, front_facing);
+}
 
-    return out;
+// ---> Normal of the lit surface (NORMAL_MAP permutation):
+fn surface_normal(in: VertexOutput, tbn_matrix: mat3x3<f32>, front_facing: bool) -> vec3<f32> {
+#ifdef NORMAL_MAP
+    let tangent_normal = textureSample(normal_texture, normal_sampler, material_uv(in, SLOT_NORMAL)).rgb * 2.0 - 1.0;
+    return face_normal(normalize(tbn_matrix * tangent_normal), front_facing);
+#else
+    return face_normal(normalize(in.normal), front_facing);
+#endif
 }
-///// VERTEX SHADER ////////////////////////////////////////////////////////////////////////////////
 
-///// FRAGMENT SHADER //////////////////////////////////////////////////////////////////////////////
 @fragment // Simplified...
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
     // ---> Material properties:
-    let diffuse_color      = textureSample(diffuse_texture, diffuse_sampler, in.tex_coords);
+    let diffuse_color      = sample_base_color(in);
+    alpha_test(diffuse_color.a);
     let metallic_roughness = textureSample(metallic_roughness_texture, 
                                            metallic_roughness_sampler,
-                                           in.tex_coords);
+                                           material_uv(in, SLOT_METALLIC_ROUGHNESS));
 
     // ---> Normal mapping:
-    let tangent_normal = textureSample(normal_texture, normal_sampler, in.tex_coords).rgb * 2.0 - 1.0;
-    let tbn_matrix     = mat3x3<f32>(in.tangent, in.bitangent, in.normal);
-    let world_normal   = normalize(tbn_matrix * tangent_normal);
+    let tbn_matrix   = mat3x3<f32>(in.tangent, in.bitangent, in.normal);
+    let world_normal = surface_normal(in, tbn_matrix, front_facing);
 
     // ---> Light calculation:
     let light_dir   = normalize( light.position - in.frag_pos);
@@ -102,12 +231,75 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let distance    = length(light.position - in.frag_pos);
     let attenuation = 1.0 / (distance * distance);
 
-    // ---> Combine components:
-    let ambient     = 0.1 * diffuse_color.rgb;
-    let diffuse     = diff * light.color * light.intensity * attenuation;
-    let specular    = spec * light.color * light.intensity * attenuation;
-    let final_color = (ambient + diffuse + specular) * diffuse_color.rgb;
+    // ---> Combine components (occlusion only darkens the ambient term, emission is added last):
+    let occlusion   = sample_occlusion(material_uv(in, SLOT_OCCLUSION));
+    let emissive    = sample_emissive(material_uv(in, SLOT_EMISSIVE));
+    let radiance    = light.color * light.intensity * attenuation;
+    let ambient     = 0.1 * diffuse_color.rgb * occlusion;
+    let diffuse     = diff * radiance;
+    let specular    = spec * radiance * specular_weight(in);
+
+    // ---> Transmission swaps the diffuse part for the (tinted) scene behind the surface:
+    let surface     = mix((ambient + diffuse) * diffuse_color.rgb, 
+                          transmitted_light(in.clip_position) * diffuse_color.rgb, 
+                          sample_transmission(in));
+    let base_layer  = surface + specular * diffuse_color.rgb 
+                      + sheen_light(in, world_normal, light_dir, view_dir, halfway_dir) * radiance;
+    let final_color = clearcoat_layer(in, base_layer, tbn_matrix, front_facing, light_dir, view_dir, radiance) 
+                      + emissive;
     
     return vec4<f32>(final_color, diffuse_color.a);
 }
-///// FRAGMENT SHADER //////////////////////////////////////////////////////////////////////////////
\ No newline at end of file
+///// FRAGMENT SHADER //////////////////////////////////////////////////////////////////////////////
+
+///// G-BUFFER FRAGMENT SHADER /////////////////////////////////////////////////////////////////////
+@fragment
+fn fs_gbuffer(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> GBufferOutput {
+    var out: GBufferOutput;
+
+    // ---> Material properties:
+    let diffuse_color      = sample_base_color(in);
+    alpha_test(diffuse_color.a);
+    let metallic_roughness = textureSample(metallic_roughness_texture, 
+                                           metallic_roughness_sampler,
+                                           material_uv(in, SLOT_METALLIC_ROUGHNESS));
+
+    // ---> Normal mapping:
+    let tbn_matrix   = mat3x3<f32>(in.tangent, in.bitangent, in.normal);
+    let world_normal = surface_normal(in, tbn_matrix, front_facing);
+
+    out.albedo   = diffuse_color;
+    out.normal   = vec4<f32>(world_normal, 0.0);
+    out.material = vec4<f32>(metallic_roughness.b * material.metallic, metallic_roughness.g * material.roughness, 
+                             sample_occlusion(material_uv(in, SLOT_OCCLUSION)), 0.0);
+    out.emissive = vec4<f32>(sample_emissive(material_uv(in, SLOT_EMISSIVE)), 0.0);
+
+    return out;
+}
+///// G-BUFFER FRAGMENT SHADER /////////////////////////////////////////////////////////////////////
+
+///// UNLIT FRAGMENT SHADERS ///////////////////////////////////////////////////////////////////////
+// ---> KHR_materials_unlit: the base colour as is, no lighting:
+@fragment
+fn fs_unlit(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = sample_base_color(in);
+    alpha_test(color.a);
+    return color;
+}
+
+// ---> Deferred: stored as emission over a black albedo, so the lighting pass adds nothing else:
+@fragment
+fn fs_gbuffer_unlit(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> GBufferOutput {
+    var out: GBufferOutput;
+
+    let color = sample_base_color(in);
+    alpha_test(color.a);
+
+    out.albedo   = vec4<f32>(0.0, 0.0, 0.0, color.a);
+    out.normal   = vec4<f32>(face_normal(normalize(in.normal), front_facing), 0.0);
+    out.material = vec4<f32>(0.0, 1.0, 1.0, 0.0);
+    out.emissive = vec4<f32>(color.rgb, 0.0);
+
+    return out;
+}
+///// UNLIT FRAGMENT SHADERS ///////////////////////////////////////////////////////////////////////
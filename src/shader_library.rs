@@ -0,0 +1,404 @@
+/*
+
+    Shader preprocessor and module cache.
+
+    WGSL has no include or conditional compilation, so the engine shaders go through a small
+    preprocessor before they are compiled. Directives start a line with `#`:
+
+        #include "common.wgsl"   Relative to the including file, else to the shader root. Every
+                                 file is included once per module (repeated includes are skipped).
+        #define NAME [value]     A valued define replaces NAME (whole words) in the code below it.
+        #undef NAME
+        #ifdef NAME / #ifndef NAME / #else / #endif
+
+    Feature permutations (e.g. NORMAL_MAP, INSTANCING) are selected with `ShaderDefines`. The
+    `ShaderLibrary` compiles every (file, defines) combination once and shares the module
+    afterwards. Each line of the flattened source remembers its file and line, so preprocessor
+    and WGSL errors point into the original files instead of the generated module.
+
+*/
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::bail;
+use anyhow::Context;
+
+/// Directory the engine shaders are loaded from (the crate's `src`, independent of the working
+/// directory).
+pub const SHADER_ROOT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+
+///// SHADER DEFINES STRUCTURE /////////////////////////////////////////////////////////////////////
+/// Defines a shader is preprocessed with, sorted so equal sets hash equally (cache key).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines(BTreeMap<String, String>);
+
+impl ShaderDefines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flag for `#ifdef NAME`.
+    pub fn with(mut self, name: &str) -> Self {
+        self.0.insert(name.to_string(), String::new());
+        self
+    }
+
+    /// `NAME` is replaced by `value` in the code.
+    pub fn with_value(mut self, name: &str, value: impl ToString) -> Self {
+        self.0.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Replaces every valued define in `line` (whole identifiers only).
+    fn substitute(&self, line: &str) -> String {
+        if self.0.values().all(|value| value.is_empty()) {
+            return line.to_string();
+        }
+
+        let mut result = String::with_capacity(line.len());
+        let mut word   = String::new();
+        for c in line.chars().chain(std::iter::once('\n')) {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                word.push(c);
+                continue;
+            }
+            match self.0.get(&word) {
+                Some(value) if !value.is_empty() => result.push_str(value),
+                _                                => result.push_str(&word),
+            }
+            word.clear();
+            result.push(c);
+        }
+        result.pop();  // The '\n' sentinel
+        result
+    }
+}
+///// SHADER DEFINES STRUCTURE /////////////////////////////////////////////////////////////////////
+
+
+///// SHADER SOURCE STRUCTURE //////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SourceLine {
+    file: usize,  // Index into `ShaderSource::files`
+    line: usize,  // 1-based
+}
+
+/// Preprocessed WGSL with the origin of each of its lines.
+#[derive(Debug, Clone, Default)]
+pub struct ShaderSource {
+    pub code: String,
+    files   : Vec<String>,
+    lines   : Vec<SourceLine>,
+}
+
+impl ShaderSource {
+    /// Preprocesses `path` (relative to `root`) and everything it includes.
+    pub fn load(root: &Path, path: &str, defines: &ShaderDefines) -> anyhow::Result<Self> {
+        let mut preprocessor = Preprocessor {
+            root,
+            defines : defines.clone(),
+            included: HashSet::new(),
+            source  : Self::default(),
+        };
+        preprocessor.process_file(&root.join(path), path)?;
+        Ok(preprocessor.source)
+    }
+
+    /// Appends generated code, errors in it are reported against `name`.
+    pub fn append(&mut self, name: &str, code: &str) {
+        let file = self.files.len();
+        self.files.push(name.to_string());
+        for (index, line) in code.lines().enumerate() {
+            self.push_line(line, SourceLine { file, line: index + 1 });
+        }
+    }
+
+    /// `file:line` of a (1-based) line of `code`.
+    pub fn location(&self, line: usize) -> Option<String> {
+        let origin = self.lines.get(line.checked_sub(1)?)?;
+        Some(format!("{}:{}", self.files[origin.file], origin.line))
+    }
+
+    fn push_line(&mut self, line: &str, origin: SourceLine) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.lines.push(origin);
+    }
+}
+
+/// Open `#ifdef` / `#ifndef` block.
+struct Branch {
+    active       : bool,  // Lines of the current branch are kept
+    parent_active: bool,
+    has_else     : bool,
+    line         : usize,
+}
+
+struct Preprocessor<'a> {
+    root    : &'a Path,
+    defines : ShaderDefines,  // #define / #undef change the set while processing
+    included: HashSet<PathBuf>,
+    source  : ShaderSource,
+}
+
+impl Preprocessor<'_> {
+    fn process_file(&mut self, path: &Path, name: &str) -> anyhow::Result<()> {
+        // ---> Include once, which also ends include cycles:
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if !self.included.insert(canonical) {
+            return Ok(());
+        }
+
+        let code = std::fs::read_to_string(path).with_context(|| format!("Failed to read shader '{}'", path.display()))?;
+        let file = self.source.files.len();
+        self.source.files.push(name.to_string());
+
+        let mut branches: Vec<Branch> = Vec::new();
+        for (index, text) in code.lines().enumerate() {
+            let line   = index + 1;
+            let active = branches.last().is_none_or(|branch| branch.active);
+
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    let text = self.defines.substitute(text);
+                    self.source.push_line(&text, SourceLine { file, line });
+                }
+                continue;
+            };
+
+            let (keyword, argument) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+            let argument            = argument.trim();
+            let required            = || -> anyhow::Result<&str> {
+                match argument.split_whitespace().next() {
+                    Some(argument) => Ok(argument),
+                    None           => bail!("{}:{}: #{} needs an argument", name, line, keyword),
+                }
+            };
+
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = self.defines.contains(required()?);
+                    branches.push(Branch {
+                        active       : active && defined == (keyword == "ifdef"),
+                        parent_active: active,
+                        has_else     : false,
+                        line,
+                    });
+                }
+                "else" => {
+                    let Some(branch) = branches.last_mut() else {
+                        bail!("{}:{}: #else without #ifdef", name, line);
+                    };
+                    if branch.has_else {
+                        bail!("{}:{}: second #else for the #ifdef in line {}", name, line, branch.line);
+                    }
+                    branch.has_else = true;
+                    branch.active   = branch.parent_active && !branch.active;
+                }
+                "endif" => {
+                    if branches.pop().is_none() {
+                        bail!("{}:{}: #endif without #ifdef", name, line);
+                    }
+                }
+                "define" if active => {
+                    let define = required()?;
+                    let value  = argument[define.len()..].trim();
+                    self.defines.0.insert(define.to_string(), value.to_string());
+                }
+                "undef" if active => {
+                    self.defines.0.remove(required()?);
+                }
+                "include" if active => {
+                    let Some(include) = argument.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) else {
+                        bail!("{}:{}: expected #include \"file\"", name, line);
+                    };
+                    // ---> Next to the including file first, then in the shader root:
+                    let local = path.parent().unwrap_or(self.root).join(include);
+                    let path  = if local.exists() { local } else { self.root.join(include) };
+                    self.process_file(&path, include).with_context(|| format!("included from {}:{}", name, line))?;
+                }
+                "define" | "undef" | "include" => {}
+                _ => bail!("{}:{}: unknown directive #{}", name, line, keyword),
+            }
+        }
+
+        if let Some(branch) = branches.last() {
+            bail!("{}:{}: #ifdef without #endif", name, branch.line);
+        }
+        Ok(())
+    }
+}
+///// SHADER SOURCE STRUCTURE //////////////////////////////////////////////////////////////////////
+
+
+///// SHADER LIBRARY STRUCTURE /////////////////////////////////////////////////////////////////////
+/// Compiled shader permutations, shared by everything that creates pipelines.
+#[derive(Debug)]
+pub struct ShaderLibrary {
+    root   : PathBuf,
+    modules: Mutex<HashMap<(String, ShaderDefines), wgpu::ShaderModule>>,
+}
+
+impl Default for ShaderLibrary {
+    fn default() -> Self {
+        Self::new(SHADER_ROOT)
+    }
+}
+
+impl ShaderLibrary {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into(), modules: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn source(&self, path: &str, defines: &ShaderDefines) -> anyhow::Result<ShaderSource> {
+        ShaderSource::load(&self.root, path, defines)
+    }
+
+    /// Module of `path` (relative to the root) with `defines`, compiled on first use.
+    pub fn load(&self, device: &wgpu::Device, path: &str, defines: &ShaderDefines) -> anyhow::Result<wgpu::ShaderModule> {
+        let key = (path.to_string(), defines.clone());
+        if let Some(module) = self.modules.lock().unwrap().get(&key) {
+            return Ok(module.clone());
+        }
+
+        let source = self.source(path, defines)?;
+        let module = compile(device, &format!("{} {:?}", path, defines.0), &source)?;
+        self.modules.lock().unwrap().insert(key, module.clone());
+        Ok(module)
+    }
+
+    /// Number of permutations compiled so far.
+    pub fn module_count(&self) -> usize {
+        self.modules.lock().unwrap().len()
+    }
+}
+
+/// Compiles preprocessed WGSL, errors are returned (not raised on the device) and point into
+/// the original files.
+pub fn compile(device: &wgpu::Device, label: &str, source: &ShaderSource) -> anyhow::Result<wgpu::ShaderModule> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(
+        wgpu::ShaderModuleDescriptor {
+            label : Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.code.as_str().into()),
+        }
+    );
+    let Some(error) = pollster::block_on(device.pop_error_scope()) else {
+        return Ok(module);
+    };
+
+    // ---> Map the messages from the flattened module back to file and line:
+    let info     = pollster::block_on(module.get_compilation_info());
+    let messages = info.messages.iter()
+                                .filter(|message| message.message_type == wgpu::CompilationMessageType::Error)
+                                .map(|message| describe(source, message))
+                                .collect::<Vec<_>>();
+    if messages.is_empty() {
+        bail!("Shader '{}': {}", label, error);
+    }
+    bail!("Shader '{}':\n{}", label, messages.join("\n"));
+}
+
+/// `file:line:column: message` followed by the offending line.
+fn describe(source: &ShaderSource, message: &wgpu::CompilationMessage) -> String {
+    let Some(location) = message.location else {
+        return message.message.clone();
+    };
+    let line = location.line_number as usize;
+    match (source.location(line), source.code.lines().nth(line.saturating_sub(1))) {
+        (Some(origin), Some(code)) => format!("{}:{}: {}\n    {}", origin, location.line_position, message.message, code.trim()),
+        _                          => message.message.clone(),
+    }
+}
+///// SHADER LIBRARY STRUCTURE /////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::GPU;
+
+    /// Writes `files` into a fresh directory below the temp dir.
+    fn shader_dir(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("seal_shaders_{}_{}", test, std::process::id()));
+        let _   = std::fs::remove_dir_all(&dir);
+        for (name, code) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, code).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn includes_and_defines_are_resolved() {
+        let dir = shader_dir("resolve", &[
+            ("main.wgsl",        "#include \"lib/common.wgsl\"\n#include \"other.wgsl\"\nfn main() {}\n"),
+            ("lib/common.wgsl",  "#include \"other.wgsl\"\nconst A = 1;\n"),
+            ("other.wgsl",       "#ifdef FAST\nconst B = SAMPLES;\n#else\nconst B = 0;\n#endif\n"),
+        ]);
+
+        let source = ShaderSource::load(&dir, "main.wgsl", &ShaderDefines::new()).unwrap();
+        assert_eq!(source.code, "const B = 0;\nconst A = 1;\nfn main() {}\n");
+        assert_eq!(source.location(1).as_deref(), Some("other.wgsl:4"));
+        assert_eq!(source.location(2).as_deref(), Some("lib/common.wgsl:2"));
+        assert_eq!(source.location(3).as_deref(), Some("main.wgsl:3"));
+
+        let defines = ShaderDefines::new().with("FAST").with_value("SAMPLES", 4);
+        let source  = ShaderSource::load(&dir, "main.wgsl", &defines).unwrap();
+        assert!(source.code.starts_with("const B = 4;\n"));
+    }
+
+    #[test]
+    fn directive_errors_name_file_and_line() {
+        let dir = shader_dir("errors", &[
+            ("open.wgsl",    "const A = 1;\n#ifndef X\n"),
+            ("unknown.wgsl", "\n\n#pragma once\n"),
+            ("missing.wgsl", "#include \"nowhere.wgsl\"\n"),
+            ("nested.wgsl",  "#ifdef A\n#ifdef B\n#else\n#else\n#endif\n#endif\n"),
+        ]);
+        let error = |path| format!("{:#}", ShaderSource::load(&dir, path, &ShaderDefines::new()).unwrap_err());
+
+        assert!(error("open.wgsl").starts_with("open.wgsl:2: #ifdef without #endif"));
+        assert!(error("unknown.wgsl").starts_with("unknown.wgsl:3: unknown directive #pragma"));
+        assert!(error("missing.wgsl").contains("included from missing.wgsl:1"));
+        assert!(error("nested.wgsl").starts_with("nested.wgsl:4: second #else for the #ifdef in line 2"));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn permutations_compile_once_and_report_original_lines() {
+        let gpu = GPU::for_tests(32, 32);
+
+        // ---> Every permutation of the engine shaders is valid WGSL:
+        let library = ShaderLibrary::default();
+        for defines in [ShaderDefines::new(),
+                        ShaderDefines::new().with("NORMAL_MAP"),
+                        ShaderDefines::new().with("INSTANCING"),
+                        ShaderDefines::new().with("NORMAL_MAP").with("INSTANCING")] {
+            library.load(&gpu.device, "shader.wgsl", &defines).unwrap();
+        }
+        library.load(&gpu.device, "shader.wgsl", &ShaderDefines::new().with("NORMAL_MAP")).unwrap();
+        library.load(&gpu.device, "mipmap.wgsl", &ShaderDefines::new()).unwrap();
+        assert_eq!(library.module_count(), 5);
+
+        // ---> WGSL errors point into the included file:
+        let dir     = shader_dir("compile", &[
+            ("main.wgsl",   "#include \"broken.wgsl\"\nfn main() {}\n"),
+            ("broken.wgsl", "// Comment\n\nfn broken() -> f32 { return 1u; }\n"),
+        ]);
+        let error = ShaderLibrary::new(dir).load(&gpu.device, "main.wgsl", &ShaderDefines::new()).unwrap_err();
+        assert!(error.to_string().contains("broken.wgsl:3:"), "{}", error);
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,232 @@
+/*
+
+    Sky background from an environment map.
+
+    An equirectangular (latitude/longitude) panorama, e.g. an .hdr or .ktx2 file, is projected onto
+    a cube map on the GPU once at load time (create_cube_texture_from_equirect). Every frame a fullscreen triangle
+    on the far plane samples it in the view direction; the depth test keeps it behind the scene.
+    The forward path draws it first inside the forward pass, the deferred path in its own pass
+    after the lighting pass.
+
+*/
+
+use std::path::Path;
+
+use crate::gpu::GPU;
+use crate::render_graph::RenderGraph;
+use crate::render_graph::ResourceHandle;
+use crate::shader_library::ShaderDefines;
+use crate::texture::SamplerKey;
+use crate::texture::Texture;
+use crate::texture::TextureLoader;
+use crate::texture::TextureOptions;
+use crate::texture::create_cube_texture_from_equirect;
+
+
+///// SKY STRUCTURE ////////////////////////////////////////////////////////////////////////////////
+pub struct Sky {
+    pipeline  : wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    _texture  : Texture,
+}
+
+impl Sky {
+    /// Loads the panorama at `path` (PNG, JPEG, HDR, EXR, KTX2...), faces get a quarter of its width.
+    pub fn from_path(gpu       : &GPU,
+                     camera_bgl: &wgpu::BindGroupLayout,
+                     loader    : &TextureLoader,
+                     path      : impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path  = path.as_ref();
+        let label = format!("Sky {}", path.display());
+
+        // ---> Colour data: 8 bit panoramas are sRGB, float ones linear already:
+        let options  = TextureOptions { srgb: true, generate_mipmaps: false, sampler: SamplerKey::clamped() };
+        let panorama = Texture::from_path(path, &gpu.device, &gpu.queue, loader, &options)?;
+        let texture  = create_cube_texture_from_equirect(&gpu.device, &gpu.queue, loader, &SamplerKey::clamped(),
+                                                         &panorama, (panorama.texture.width() / 4).max(1), Some(&label))?;
+
+        Ok(Self::new(gpu, camera_bgl, texture))
+    }
+
+    /// `texture` has to be viewed as a cube map (see `create_cube_texture`).
+    pub fn new(gpu: &GPU, camera_bgl: &wgpu::BindGroupLayout, texture: Texture) -> Self {
+        let device = &gpu.device;
+
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label  : Some("Sky Bind Group Layout"),
+                entries: &[
+                    // Environment cube map:
+                    wgpu::BindGroupLayoutEntry {
+                        binding   : 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty        : wgpu::BindingType::Texture {
+                            sample_type   : wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            multisampled  : false,
+                        },
+                        count     : None,
+                    },
+                    // Environment sampler:
+                    wgpu::BindGroupLayoutEntry {
+                        binding   : 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count     : None,
+                    },
+                ],
+            },
+        );
+
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label  : Some("Sky Bind Group"),
+                layout : &bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding : 0,
+                        resource: wgpu::BindingResource::TextureView(&texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding : 1,
+                        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                    },
+                ],
+            },
+        );
+
+        let shader = gpu.load_shader("sky.wgsl", &ShaderDefines::new());
+        let layout = device.create_pipeline_layout(
+            &wgpu::PipelineLayoutDescriptor {
+                label               : Some("Sky Pipeline Layout"),
+                bind_group_layouts  : &[
+                    camera_bgl,          // @group(0)
+                    &bind_group_layout,  // @group(1)
+                ],
+                push_constant_ranges: &[],
+            },
+        );
+
+        let pipeline = device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
+                label        : Some("Sky Pipeline"),
+                layout       : Some(&layout),
+                vertex       : wgpu::VertexState {
+                    module             : &shader,
+                    entry_point        : Some("vs_sky"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers            : &[],
+                },
+                primitive    : wgpu::PrimitiveState::default(),
+                // ---> On the far plane: only where nothing was drawn, and nothing is hidden by it:
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format             : wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare      : wgpu::CompareFunction::LessEqual,
+                    stencil            : wgpu::StencilState::default(),
+                    bias               : wgpu::DepthBiasState::default(),
+                }),
+                multisample  : wgpu::MultisampleState::default(),
+                fragment     : Some(wgpu::FragmentState {
+                    module             : &shader,
+                    entry_point        : Some("fs_sky"),
+                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    targets            : &[Some(wgpu::ColorTargetState {
+                        format    : gpu.config.format,
+                        blend     : Some(wgpu::BlendState::REPLACE),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview    : None,
+                cache        : None,
+            },
+        );
+
+        Self { pipeline, bind_group, _texture: texture }
+    }
+
+    /// Draws the sky into a pass with the frame format and the depth buffer (bind groups 0 and 1
+    /// have to be set again afterwards).
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass, camera_bind_group: &wgpu::BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Own pass over an already shaded `target` (deferred path, after the lighting pass).
+    pub fn add_pass<'a>(&'a self,
+                        graph            : &mut RenderGraph<'a>,
+                        target           : ResourceHandle,
+                        depth            : ResourceHandle,
+                        camera_bind_group: &'a wgpu::BindGroup) {
+        graph.add_pass("Sky Pass", |builder| {
+            builder.read(depth);
+            builder.write(target);
+        }, move |encoder, resources| {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label                   : Some("Sky Pass"),
+                color_attachments       : &[Some(wgpu::RenderPassColorAttachment {
+                    view          : resources.texture_view(target),
+                    resolve_target: None,
+                    ops           : wgpu::Operations {
+                        load : wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view       : resources.texture_view(depth),
+                    depth_ops  : Some(wgpu::Operations {
+                        load : wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes        : None,
+                occlusion_query_set     : None,
+            });
+
+            self.draw(&mut render_pass, camera_bind_group);
+        });
+    }
+}
+///// SKY STRUCTURE ////////////////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use crate::state::RenderPath;
+    use crate::state::State;
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn sky_fills_the_background_behind_the_scene() {
+        // ---> Blue above the horizon, green below:
+        let path = std::env::temp_dir().join(format!("seal_engine_sky_{}.png", std::process::id()));
+        image::RgbaImage::from_fn(64, 32, |_, y| {
+            image::Rgba(if y < 16 { [0, 0, 255, 255] } else { [0, 255, 0, 255] })
+        }).save(&path).unwrap();
+
+        let mut state = State::for_tests(32, 32, "tests/golden/scenes/cube.gltf");
+        state.set_camera(nalgebra_glm::vec3(0.0, -2.0, 6.0), nalgebra_glm::vec3(0.0, -2.0, 0.0));
+
+        for render_path in [RenderPath::Forward, RenderPath::Deferred] {
+            state.render_path = render_path;
+
+            state.set_sky(None).unwrap();
+            let image = state.render_to_image().unwrap();
+            assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255], "{:?}", render_path);
+
+            state.set_sky(Some(path.to_str().unwrap())).unwrap();
+            let image = state.render_to_image().unwrap();
+            assert_eq!(image.get_pixel(0, 0).0,   [0, 0, 255, 255], "{:?}", render_path);
+            assert_eq!(image.get_pixel(0, 31).0,  [0, 255, 0, 255], "{:?}", render_path);
+            assert_ne!(image.get_pixel(16, 16).0, [0, 0, 255, 255], "{:?}: the cube covers the sky", render_path);
+        }
+
+        std::fs::remove_file(&path).ok();
+        assert!(state.set_sky(Some("missing/sky.hdr")).unwrap_err().to_string().contains("missing/sky.hdr"));
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,48 @@
+// Sky background (sky.rs): a fullscreen triangle on the far plane samples the environment cube
+// map in the view direction of every pixel. Depth tested, so only uncovered pixels show it.
+
+///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
+struct CameraUniform {
+    view_proj    : mat4x4<f32>,
+    position     : vec3<f32>,
+    _pad         : f32,
+    inv_view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: CameraUniform;
+///// UNIFORM STRUCTURES ///////////////////////////////////////////////////////////////////////////
+
+///// ENVIRONMENT MAP //////////////////////////////////////////////////////////////////////////////
+@group(1) @binding(0) var sky_texture: texture_cube<f32>;
+@group(1) @binding(1) var sky_sampler: sampler;
+///// ENVIRONMENT MAP //////////////////////////////////////////////////////////////////////////////
+
+///// VERTEX SHADER (FULLSCREEN TRIANGLE) //////////////////////////////////////////////////////////
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0)       ndc          : vec2<f32>,
+};
+
+@vertex
+fn vs_sky(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    // ---> One triangle covering the whole screen, on the far plane:
+    let x = f32((vertex_index << 1u) & 2u);
+    let y = f32(vertex_index & 2u);
+    out.ndc           = vec2<f32>(x * 2.0 - 1.0, y * 2.0 - 1.0);
+    out.clip_position = vec4<f32>(out.ndc, 1.0, 1.0);
+
+    return out;
+}
+///// VERTEX SHADER (FULLSCREEN TRIANGLE) //////////////////////////////////////////////////////////
+
+///// FRAGMENT SHADER //////////////////////////////////////////////////////////////////////////////
+@fragment
+fn fs_sky(in: VertexOutput) -> @location(0) vec4<f32> {
+    // ---> World space view direction through this pixel:
+    let far       = camera.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = far.xyz / far.w - camera.position;
+
+    return vec4<f32>(textureSample(sky_texture, sky_sampler, direction).rgb, 1.0);
+}
+///// FRAGMENT SHADER //////////////////////////////////////////////////////////////////////////////
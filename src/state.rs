@@ -5,25 +5,68 @@ use winit::keyboard::KeyCode;
 use winit::window::Window;
 
 use crate::gpu::GPU;
+use crate::custom_material::CustomMaterial;
+use crate::material::Material;
+use crate::material::MaterialEdit;
 use crate::camera::CameraState;
 use crate::camera::CameraController;
 use crate::model::ModelUniformState;
-use crate::model::load_model;
+use crate::material::TextureSlot;
+use crate::material::MATERIAL_UNIFORM_BINDING;
 use crate::texture::Texture;
 use crate::texture::create_depth_texture;
+use crate::texture::create_render_target;
+use crate::texture::TextureSettings;
+use crate::streaming::StreamingSettings;
+use crate::assets::AssetCache;
 use crate::input::InputState;
 use crate::lighting::LightingSystem;
-use crate::vertex::Vertex;
 use crate::scene::SceneGraph;
 use crate::scene::NodeHandle;
 use crate::scene::Transform;
+use crate::instance::Instance;
+use crate::instance_manager::InstanceManager;
+use crate::capture::FrameCapture;
+use crate::deferred::DeferredRenderer;
+use crate::pipeline::PassKind;
+use crate::pipeline::PipelineCache;
+use crate::render_graph::RenderGraph;
+use crate::render_graph::ResourceHandle;
+use crate::render_graph::TransientPool;
+use crate::transmission::SceneColor;
+use crate::sky::Sky;
+
+
+///// RENDER PATH ENUM /////////////////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderPath {
+    Forward,
+    Deferred,
+}
+
+impl RenderPath {
+    pub fn toggled(self) -> Self {
+        match self {
+            RenderPath::Forward  => RenderPath::Deferred,
+            RenderPath::Deferred => RenderPath::Forward,
+        }
+    }
+}
+///// RENDER PATH ENUM /////////////////////////////////////////////////////////////////////////////
 
 
 ///// STATE STRUCTURE //////////////////////////////////////////////////////////////////////////////
 pub struct State {
     pub gpu                : GPU,
     pub size               : winit::dpi::PhysicalSize<u32>,
-    pub render_pipeline    : wgpu::RenderPipeline,
+    pub pipelines          : PipelineCache,
+    pub render_path        : RenderPath,
+    pub deferred           : DeferredRenderer,
+    pub scene_color        : SceneColor,
+    pub transient_pool     : TransientPool,
+    pub dump_render_graph  : bool,
+    pub offscreen_target   : Option<Texture>,  // Headless target / readback copy
+    pub capture            : FrameCapture,
 
     // Camera:
     pub camera_state       : CameraState,
@@ -31,6 +74,9 @@ pub struct State {
 
     // Model:
     pub model_uniform_state: ModelUniformState,
+    pub instance_manager   : InstanceManager,  // Instance buffers of nodes with several instances
+    pub assets             : AssetCache,
+    pub material_bind_group_layout: wgpu::BindGroupLayout,
 
     // Depth-buffer:
     pub depth_texture      : Texture,
@@ -41,143 +87,57 @@ pub struct State {
 
     // Lighting:
     pub lighting           : LightingSystem,
+    pub sky                : Option<Sky>,  // Environment map background (black without)
 
     // Scene:
     pub scene              : SceneGraph,
     pub camera_node        : NodeHandle,
+    pub model_node         : Option<NodeHandle>,  // Node of the drawn model
+    pub highlight          : Option<(Arc<Material>, Arc<Material>, f32)>,  // Shared, pulsing copy, phase
 }
 
 impl State {
-    fn create_material_bind_group(gpu: &GPU) -> wgpu::BindGroupLayout {
+    pub fn create_material_bind_group(gpu: &GPU) -> wgpu::BindGroupLayout {
         let device = &gpu.device;
 
-        device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor {
-                label: Some("Material bind group layout"),
-                entries: &[
-                    // Diffuse texture:
-                    wgpu::BindGroupLayoutEntry {
-                        binding   : 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty        : wgpu::BindingType::Texture { 
-                            sample_type   : wgpu::TextureSampleType::Float { filterable: true }, 
-                            view_dimension: wgpu::TextureViewDimension::D2, 
-                            multisampled  : false,
-                        },
-                        count     : None,
-                    },
-                    // Diffuse sampler:
-                    wgpu::BindGroupLayoutEntry {
-                        binding   : 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count     : None,
-                    },
-                    // Normal texture (optional):
-                    wgpu::BindGroupLayoutEntry {
-                        binding   : 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty        : wgpu::BindingType::Texture { 
-                            sample_type   : wgpu::TextureSampleType::Float { filterable: true }, 
-                            view_dimension: wgpu::TextureViewDimension::D2, 
-                            multisampled  : false,
-                        },
-                        count     : None,
-                    },
-                    // Normal sampler:
-                    wgpu::BindGroupLayoutEntry {
-                        binding   : 3,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count     : None,
-                    },
-                    // Metallic roughness texture:
-                    wgpu::BindGroupLayoutEntry {
-                        binding   : 4,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty        : wgpu::BindingType::Texture { 
-                            sample_type   : wgpu::TextureSampleType::Float { filterable: true }, 
-                            view_dimension: wgpu::TextureViewDimension::D2, 
-                            multisampled  : false,
-                        },
-                        count: None,
-                    },
-                    // Metallic roughness sampler:
-                    wgpu::BindGroupLayoutEntry {
-                        binding   : 5,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count     : None,
-                    },
-                ],
-            },
-        )
-    }
-
-    fn create_render_pipeline(gpu         : &GPU, 
-                              camera_bgl  : &wgpu::BindGroupLayout, 
-                              model_bgl   : &wgpu::BindGroupLayout, 
-                              material_bgl: &wgpu::BindGroupLayout,
-                              lighting_bgl: &wgpu::BindGroupLayout,
-                              shader      : wgpu::ShaderModule) -> wgpu::RenderPipeline{
-        let device = &gpu.device;
+        // ---> Texture + sampler per TextureSlot (base color, normal, metallic roughness, ...):
+        let mut entries = Vec::with_capacity(2 * TextureSlot::COUNT + 1);
+        for slot in 0..TextureSlot::COUNT as u32 {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding   : 2 * slot,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty        : wgpu::BindingType::Texture { 
+                    sample_type   : wgpu::TextureSampleType::Float { filterable: true }, 
+                    view_dimension: wgpu::TextureViewDimension::D2, 
+                    multisampled  : false,
+                },
+                count     : None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding   : 2 * slot + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count     : None,
+            });
+        }
 
-        let surface_caps   = gpu.surface.get_capabilities(&gpu.adapter);
-        let surface_format = surface_caps.formats[0];
-
-        let render_pipeline_layout = device.create_pipeline_layout(
-            &wgpu::PipelineLayoutDescriptor { 
-                label               : Some("Render Pipeline Layout"), 
-                bind_group_layouts  : &[
-                    &camera_bgl,    // @group(0)
-                    &model_bgl,     // @group(1)
-                    &material_bgl,  // @group(2)
-                    &lighting_bgl,  // @group(3)
-                ], 
-                push_constant_ranges: &[],
+        // ---> Material factors (MaterialUniform):
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding   : MATERIAL_UNIFORM_BINDING,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty        : wgpu::BindingType::Buffer {
+                ty                : wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size  : None,
             },
-        );
+            count     : None,
+        });
 
-        device.create_render_pipeline(
-            &wgpu::RenderPipelineDescriptor { 
-                label        : Some("Render Pipeline"), 
-                layout       : Some(&render_pipeline_layout), 
-                vertex       : wgpu::VertexState { 
-                    module             : &shader, 
-                    entry_point        : Some("vs_main"), 
-                    compilation_options: wgpu::PipelineCompilationOptions::default(), 
-                    buffers            : &[Vertex::desc()], 
-                }, 
-                primitive    : wgpu::PrimitiveState {
-                    topology          : wgpu::PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face        : wgpu::FrontFace::Ccw,  // Right handed coordinate space!
-                    cull_mode         : Some(wgpu::Face::Back),
-                    unclipped_depth   : false,
-                    polygon_mode      : wgpu::PolygonMode::Fill,
-                    conservative      : false,
-                }, 
-                depth_stencil: Some(wgpu::DepthStencilState { 
-                    format             : wgpu::TextureFormat::Depth32Float, 
-                    depth_write_enabled: true, 
-                    depth_compare      : wgpu::CompareFunction::Less, 
-                    stencil            : wgpu::StencilState::default(), 
-                    bias               : wgpu::DepthBiasState::default(),
-                }), 
-                multisample  : wgpu::MultisampleState::default(), 
-                fragment     : Some(wgpu::FragmentState { 
-                    module             : &shader, 
-                    entry_point        : Some("fs_main"), 
-                    compilation_options: wgpu::PipelineCompilationOptions::default(), 
-                    targets            : &[Some(wgpu::ColorTargetState {
-                        format    : surface_format,
-                        blend     : Some(wgpu::BlendState::REPLACE),
-                        write_mask: wgpu::ColorWrites::ALL,
-                    })],
-                }), 
-                multiview    : None, 
-                cache        : None,
-             },
+        device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label  : Some("Material bind group layout"),
+                entries: &entries,
+            },
         )
     }
 
@@ -187,14 +147,52 @@ impl State {
         // ---> Initialize GPU:
         let gpu = GPU::new(window, size).await;
 
-        // ---> Load shaders:
-        let shader = gpu.load_shaders();
+        // ---> Interactive: textures stream in while the scene is already shown:
+        let texture_settings = TextureSettings { streaming: Some(StreamingSettings::default()), ..Default::default() };
+
+        let mut state = Self::from_gpu(gpu, size, texture_settings);
+
+        // ---> A missing model still opens the window (with an empty scene):
+        if let Err(e) = state.load_scene("models/Bridge.glb") {
+            eprintln!("{:#}", e);
+        }
+
+        // ---> The sky is optional, a black background without it:
+        if std::path::Path::new("models/Sky.hdr").exists() {
+            if let Err(e) = state.set_sky(Some("models/Sky.hdr")) {
+                eprintln!("{:#}", e);
+            }
+        }
+        state
+    }
+
+    /// Renders without window into an offscreen target (see `render_to_image`).
+    pub async fn new_headless(width     : u32, 
+                              height    : u32, 
+                              scene_path: &str, 
+                              fallback  : bool) -> anyhow::Result<Self> {
+        let gpu = GPU::new_headless(width, height, fallback).await?;
+        // ---> Without streaming, so every image is loaded before the first frame:
+        let mut state = Self::from_gpu(gpu, winit::dpi::PhysicalSize::new(width, height), TextureSettings::default());
+        state.load_scene(scene_path)?;
+        Ok(state)
+    }
+
+    /// Headless state with `scene_path` loaded for the `#[ignore]`d GPU tests (see `GPU::for_tests`).
+    #[cfg(test)]
+    pub fn for_tests(width: u32, height: u32, scene_path: &str) -> Self {
+        pollster::block_on(Self::new_headless(width, height, scene_path, true))
+            .unwrap_or_else(|e| panic!("GPU test needs an adapter: {:#}", e))
+    }
 
+    fn from_gpu(gpu             : GPU, 
+                size            : winit::dpi::PhysicalSize<u32>, 
+                texture_settings: TextureSettings) -> Self {
         // ---> Create Camera:
         let camera_state = CameraState::new(&gpu);
 
         // ---> Create ModelUniform:
-        let mut model_uniform_state = ModelUniformState::new(&gpu);
+        let model_uniform_state = ModelUniformState::new(&gpu);
 
         // ---> Create material bind group:
         let material_bind_group_layout = Self::create_material_bind_group(&gpu);
@@ -205,20 +203,27 @@ impl State {
         // ---> Create Lighting System:
         let lighting = LightingSystem::new(&gpu.device);
 
-        // ---> Create pipeline:
-        let render_pipeline = Self::create_render_pipeline(
-            &gpu, 
+        // ---> Light + opaque scene colour for the forward pipelines (transmission):
+        let scene_color = SceneColor::new(&gpu.device, &lighting.buffer, gpu.config.format);
+
+        // ---> Create pipeline variants (created per material state on first use):
+        let pipelines = PipelineCache::new(
+            &gpu.device,
+            gpu.shaders.clone(),
+            gpu.config.format,
             &camera_state.camera_bind_group_layout, 
             &model_uniform_state.model_bind_group_layout, 
             &material_bind_group_layout,
+            &scene_color.bind_group_layout,
+        );
+
+        // ---> Create deferred renderer (G-buffer + lighting pass):
+        let deferred = DeferredRenderer::new(
+            &gpu,
+            &camera_state.camera_bind_group_layout,
             &lighting.bind_group_layout,
-            shader,
         );
 
-        // ---> Load a Model (test):
-        // model_uniform_state.model = load_model("models/Bridge.glb", &gpu.device, &gpu.queue, 
-        //                                        &material_bind_group_layout).ok();
-        
         // ---> Create Camera Controller:
         let camera_controller = CameraController::new(10.0);
 
@@ -238,32 +243,196 @@ impl State {
         camera_transform.position = camera_state.camera.eye;
         scene.set_transform(camera_node, camera_transform);
 
-        // ---> Load model and add it to scene:
-        if let Ok(model) = load_model("models/Bridge.glb", 
-                                      &gpu.device, 
-                                      &gpu.queue, 
-                                      &material_bind_group_layout) {
-            // ---> Create scene node for the model:
-            let model_node = scene.create_node("Bridge".to_string());
-            scene.attach_to_root(model_node).unwrap();
-
-            // ---> Set model in scene:
-            scene.set_model(model_node, model);
-
-            // ---> Position the model:
-            let mut model_transform  = Transform::new();
-            model_transform.position = nalgebra_glm::vec3(0.0, -2.0, 0.0);
-            scene.set_transform(model_node, model_transform);
-            model_uniform_state.model = scene.get_node(model_node)
-                                             .and_then(|node| node.model.as_ref())
-                                             .cloned();
-        }
-
         // ---> Update scene transforms initially:
         scene.update_transforms();
 
-        Self { gpu, size, render_pipeline, camera_state, camera_controller, model_uniform_state,
-               depth_texture, input, last_update_time, lighting, scene, camera_node }
+        let assets = AssetCache::new(&gpu.device, &gpu.shaders, texture_settings);
+
+        // ---> Instance buffers (created once a node gets more than one instance):
+        let instance_manager = InstanceManager::new(64);
+
+        Self { gpu, size, pipelines, render_path: RenderPath::Forward, deferred, scene_color,
+               transient_pool: TransientPool::new(), dump_render_graph: false, 
+               offscreen_target: None, capture: FrameCapture::new(), camera_state,
+               camera_controller, model_uniform_state, instance_manager, assets, material_bind_group_layout, 
+               depth_texture, input, last_update_time, lighting, sky: None, scene, camera_node, model_node: None,
+               highlight: None }
+    }
+
+    /// Loads an equirectangular panorama as the background (see `Sky`), `None` removes it.
+    pub fn set_sky(&mut self, path: Option<&str>) -> anyhow::Result<()> {
+        self.sky = match path {
+            Some(path) => Some(Sky::from_path(&self.gpu, &self.camera_state.camera_bind_group_layout, 
+                                              &self.assets.loader, path)?),
+            None       => None,
+        };
+        Ok(())
+    }
+
+    /// Loads the model at `scene_path`, adds it to the scene and makes it the drawn model.
+    pub fn load_scene(&mut self, scene_path: &str) -> anyhow::Result<NodeHandle> {
+        let model = self.assets.load_model(scene_path, 
+                                           &self.gpu.device, 
+                                           &self.gpu.queue, 
+                                           &self.material_bind_group_layout)
+                        .map_err(|e| anyhow::anyhow!("Failed to load scene {}: {:#}", scene_path, e))?;
+
+        // ---> Create scene node for the model:
+        let node_name = std::path::Path::new(scene_path)
+                                        .file_stem()
+                                        .map(|stem| stem.to_string_lossy().to_string())
+                                        .unwrap_or_else(|| "Model".to_string());
+        let node = self.scene.create_node(node_name);
+        self.scene.attach_to_root(node).map_err(|e| anyhow::anyhow!(e))?;
+
+        // ---> Set model in scene:
+        self.scene.set_model(node, model);
+
+        // ---> Position the model:
+        let mut model_transform  = Transform::new();
+        model_transform.position = nalgebra_glm::vec3(0.0, -2.0, 0.0);
+        self.scene.set_transform(node, model_transform);
+        self.scene.update_transforms();
+
+        self.model_node = Some(node);
+        self.sync_drawn_model(node);
+        Ok(node)
+    }
+
+    /// `SceneGraph::set_material_override`, the drawn model picks the change up right away.
+    pub fn set_material_override(&mut self, 
+                                 node      : NodeHandle, 
+                                 mesh_index: usize, 
+                                 material  : Option<Arc<CustomMaterial>>) -> Result<(), String> {
+        self.scene.set_material_override(node, mesh_index, material)?;
+        self.sync_drawn_model(node);
+        Ok(())
+    }
+
+    /// Gives `node` its own copy of material `material_index` with `edit` applied (the shared model
+    /// is left alone). The copy is returned for further edits (`Material::edit`).
+    pub fn override_material(&mut self, 
+                             node          : NodeHandle, 
+                             material_index: usize, 
+                             edit          : &MaterialEdit) -> Result<Arc<Material>, String> {
+        let material = self.scene.get_node(node)
+                                 .and_then(|node| node.model.as_ref())
+                                 .and_then(|model| model.materials.get(material_index))
+                                 .ok_or_else(|| format!("No material {} on this node", material_index))?;
+        let copy     = Arc::new(material.edited(&self.gpu.device, edit));
+        self.scene.replace_material(node, material_index, copy.clone())?;
+        self.sync_drawn_model(node);
+        Ok(copy)
+    }
+
+    /// `SceneGraph::set_variant`, the drawn model switches right away.
+    pub fn set_variant(&mut self, node: NodeHandle, variant: Option<&str>) -> Result<(), String> {
+        self.scene.set_variant(node, variant)?;
+        self.sync_drawn_model(node);
+        Ok(())
+    }
+
+    /// `SceneGraph::replace_material`, e.g. to undo `override_material`.
+    pub fn replace_material(&mut self, 
+                            node          : NodeHandle, 
+                            material_index: usize, 
+                            material      : Arc<Material>) -> Result<Arc<Material>, String> {
+        let previous = self.scene.replace_material(node, material_index, material)?;
+        self.sync_drawn_model(node);
+        Ok(previous)
+    }
+
+    // ---> Draws the model as a row of three instances (instanced draw path), or once again:
+    fn toggle_instances(&mut self) {
+        let Some(node) = self.model_node else {
+            return;
+        };
+        let instanced = self.scene.get_node(node).is_some_and(|node| node.instances.len() > 1);
+        let instances = if instanced {
+            vec![Instance::new()]
+        } else {
+            [-1.0, 0.0, 1.0].map(|x| Instance { position: nalgebra_glm::vec3(4.0 * x, 0.0, 0.0), ..Instance::new() }).to_vec()
+        };
+        if let Err(e) = self.set_instances(node, instances) {
+            eprintln!("Instances: {}", e);
+        }
+    }
+
+    // ---> Highlights the first material of the drawn model with a per node copy, or restores it:
+    fn toggle_highlight(&mut self) {
+        let Some(node) = self.model_node else {
+            return;
+        };
+        let result = match self.highlight.take() {
+            Some((shared, _, _)) => self.replace_material(node, 0, shared).map(|_| ()),
+            None                 => {
+                let shared = self.scene.get_node(node)
+                                       .and_then(|node| node.model.as_ref())
+                                       .and_then(|model| model.materials.first())
+                                       .cloned();
+                match shared {
+                    Some(shared) => self.override_material(node, 0, &MaterialEdit::default())
+                                        .map(|copy| self.highlight = Some((shared, copy, 0.0))),
+                    None         => Ok(()),
+                }
+            }
+        };
+        if let Err(e) = result {
+            eprintln!("Highlight: {}", e);
+        }
+    }
+
+    // ---> Default materials -> first variant -> ... -> last variant -> default materials:
+    fn cycle_variant(&mut self) {
+        let Some(model) = self.model_uniform_state.model.as_ref() else {
+            return;
+        };
+        let next = match model.active_variant {
+            None                                              => model.variants.first(),
+            Some(active) if active + 1 < model.variants.len() => model.variants.get(active + 1),
+            Some(_)                                           => None,
+        }.cloned();
+
+        if let (Some(node), Some(_)) = (self.model_node, model.variants.first()) {
+            match self.set_variant(node, next.as_deref()) {
+                Ok(()) => println!("Material variant: {}", next.as_deref().unwrap_or("(default)")),
+                Err(e) => eprintln!("Material variant: {}", e),
+            }
+        }
+    }
+
+    /// Draws the model of `node` once per instance (transforms replace the model uniform).
+    /// A single instance goes back to the regular, non instanced draw.
+    pub fn set_instances(&mut self, node: NodeHandle, instances: Vec<Instance>) -> Result<(), String> {
+        self.scene.get_node_mut(node)
+                  .ok_or_else(|| format!("Node {:?} does not exist", node))?
+                  .set_instances(instances);
+        self.sync_drawn_model(node);
+        Ok(())
+    }
+
+    // ---> The drawn model is a copy of the one of its scene node:
+    fn sync_drawn_model(&mut self, node: NodeHandle) {
+        if self.model_node != Some(node) {
+            return;
+        }
+        let Some(scene_node) = self.scene.get_node(node) else {
+            self.model_uniform_state.model = None;
+            return;
+        };
+
+        // ---> Several instances use the instanced draw path (INSTANCING pipelines):
+        let mut model = scene_node.model.clone();
+        if scene_node.instances.len() > 1 {
+            self.instance_manager.update_instances(node, &scene_node.instances, &self.gpu);
+            if let Some(model) = &mut model {
+                model.instances = self.instance_manager.get_buffer(node)
+                                                       .map(|buffer| (buffer.clone(), self.instance_manager.get_instance_count(node)));
+            }
+        } else {
+            self.instance_manager.remove_node(node);
+        }
+        self.model_uniform_state.model = model;
     }
 
     pub fn handle_input(&mut self, event: &WindowEvent) -> bool {
@@ -284,6 +453,9 @@ impl State {
         let dt                = now - self.last_update_time;
         self.last_update_time = now;
 
+        // ---> Recording simulates a fixed framerate instead of real time:
+        let dt = self.capture.fixed_timestep().unwrap_or(dt);
+
         // ---> Input processing:
         self.camera_controller.process_input(&self.input);
 
@@ -291,6 +463,61 @@ impl State {
         if self.input.is_key_pressed(KeyCode::F1) {
             println!("Camera Position: {:?}", self.camera_state.camera.eye);
             println!("Camera Target  : {:?}", self.camera_state.camera.target);
+            println!("Assets         : {}", self.assets.stats());
+            println!("Pipelines      : {} variants", self.pipelines.variant_count());
+            if let Some(streamer) = self.assets.streamer() {
+                println!("Streaming      : {}", streamer);
+            }
+        }
+
+        // ---> Switch between forward and deferred rendering:
+        if self.input.is_key_pressed(KeyCode::F2) {
+            self.render_path = self.render_path.toggled();
+            println!("Render path: {:?}", self.render_path);
+        }
+
+        // ---> Screenshot / frame sequence recording:
+        if self.input.is_key_pressed(KeyCode::F12) {
+            self.capture.request_screenshot();
+        }
+        if self.input.is_key_pressed(KeyCode::F11) {
+            self.capture.toggle_recording();
+        }
+
+        // ---> Release assets nothing refers to anymore:
+        if self.input.is_key_pressed(KeyCode::F5) {
+            println!("Evicted: {}", self.assets.evict_unused());
+        }
+
+        // ---> Cycle through the material variants of the model (KHR_materials_variants):
+        if self.input.is_key_pressed(KeyCode::F6) {
+            self.cycle_variant();
+        }
+
+        // ---> Highlight the first material of the model (pulsing emission on a per node copy):
+        if self.input.is_key_pressed(KeyCode::F7) {
+            self.toggle_highlight();
+        }
+        if let Some((_, copy, phase)) = &mut self.highlight {
+            // ---> Emission in 1/32 steps, the uniform is only written when the step changes:
+            let pulse    = |phase: f32| (8.0 + 8.0 * (phase * std::f32::consts::TAU).sin()).round() / 32.0;
+            let previous = pulse(*phase);
+            *phase      += dt.as_secs_f32();
+            let pulse    = pulse(*phase);
+            if pulse != previous {
+                copy.edit(&self.gpu.device, &self.gpu.queue, 
+                          &MaterialEdit { emissive: Some([pulse, 0.5 * pulse, 0.0]), ..Default::default() });
+            }
+        }
+
+        // ---> Draw the model three times with one instanced draw per mesh:
+        if self.input.is_key_pressed(KeyCode::F8) {
+            self.toggle_instances();
+        }
+
+        // ---> Dump the render graph (Graphviz) on the next frame:
+        if self.input.is_key_pressed(KeyCode::F3) {
+            self.dump_render_graph = true;
         }
 
         // ---> Update camera:
@@ -310,6 +537,12 @@ impl State {
         self.camera_state.camera_uniform.update_view_proj(&self.camera_state.camera);
         self.gpu.queue.write_buffer(&self.camera_state.camera_buffer, 0, 
                                     bytemuck::cast_slice(&[self.camera_state.camera_uniform]));
+
+        // ---> Stream textures in (the drawn model uses the identity model matrix):
+        if let Some(model) = &self.model_uniform_state.model {
+            self.assets.update_streaming(&self.gpu.device, &self.gpu.queue, &self.camera_state.camera,
+                                         self.size.height, &[(model, nalgebra_glm::Mat4::identity())]);
+        }
         
         // ---> Clear frame-specific input events:
         self.input.end_frame();
@@ -322,10 +555,14 @@ impl State {
             self.gpu.config.height = new_size.height;
 
             // ---> Reconfigure surface:
-            self.gpu.surface.configure(&self.gpu.device, &self.gpu.config);
+            if let Some(surface) = &self.gpu.surface {
+                surface.configure(&self.gpu.device, &self.gpu.config);
+            }
+            self.offscreen_target = None;
 
             // ---> Recreate depth texture:
             self.depth_texture = create_depth_texture(&self.gpu.device, &self.gpu.config);
+            self.transient_pool.clear();
 
             // ---> Update camera aspect ratio:
             let width  = self.gpu.config.width  as f32;
@@ -342,28 +579,152 @@ impl State {
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        // ---> Headless: render into the offscreen target instead:
+        let Some(surface) = &self.gpu.surface else {
+            if self.capture.wants_frame() {
+                let image = self.render_to_image();
+                if let Err(e) = image.and_then(|image| self.capture.save_frame(image)) {
+                    eprintln!("Frame capture failed: {}", e);
+                }
+            } else {
+                let view = self.offscreen_target().view.clone();
+                self.render_to_view(&view);
+            }
+            return Ok(());
+        };
+
         // ---> Get current image (FrameBuffer):
-        let output = match self.gpu.surface.get_current_texture() {
+        let output = match surface.get_current_texture() {
             Ok(frame) => frame,
             Err(_) => {
-                self.gpu.surface.configure(&self.gpu.device, &self.gpu.config);
-                self.gpu.surface.get_current_texture()
-                                .expect("Failed to acquire next swap chain texture!")
+                surface.configure(&self.gpu.device, &self.gpu.config);
+                surface.get_current_texture()
+                       .expect("Failed to acquire next swap chain texture!")
             }
         };
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
 
+        self.render_to_view(&view);
+
+        // ---> Screenshot / recording capture:
+        if self.capture.wants_frame() {
+            let image = if output.texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+                self.gpu.read_texture(&output.texture)
+            } else {
+                self.render_to_image()
+            };
+
+            if let Err(e) = image.and_then(|image| self.capture.save_frame(image)) {
+                eprintln!("Frame capture failed: {}", e);
+            }
+        }
+
+        output.present();
+
+        Ok(())
+    }
+
+    /// Renders one frame into the offscreen target and reads the pixels back.
+    pub fn render_to_image(&mut self) -> anyhow::Result<image::RgbaImage> {
+        let view = self.offscreen_target().view.clone();
+        self.render_to_view(&view);
+
+        let target = self.offscreen_target.as_ref().unwrap();
+        self.gpu.read_texture(&target.texture)
+    }
+
+    fn offscreen_target(&mut self) -> &Texture {
+        self.offscreen_target.get_or_insert_with(|| {
+            create_render_target(&self.gpu.device, 
+                                 self.gpu.config.width, 
+                                 self.gpu.config.height, 
+                                 self.gpu.config.format, 
+                                 Some("Offscreen Target"))
+        })
+    }
+
+    pub fn set_camera(&mut self, eye: nalgebra_glm::Vec3, target: nalgebra_glm::Vec3) {
+        self.camera_state.camera.eye    = eye;
+        self.camera_state.camera.target = target;
+        self.camera_state.camera_uniform.update_view_proj(&self.camera_state.camera);
+        self.gpu.queue.write_buffer(&self.camera_state.camera_buffer, 0, 
+                                    bytemuck::cast_slice(&[self.camera_state.camera_uniform]));
+    }
+
+    fn render_to_view(&mut self, view: &wgpu::TextureView) {
         // ---> Command encoder for GPU commands:
         let mut encoder = self.gpu.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor { label: None }
         );
 
-        // ---> Starting render pass:
+        // ---> Declare this frame's passes and let the graph record them:
+        let mut transient_pool = std::mem::take(&mut self.transient_pool);
         {
+            let mut graph = RenderGraph::new();
+            let frame = graph.import_texture("Frame", view);
+            let depth = graph.import_texture("Depth", &self.depth_texture.view);
+
+            match self.render_path {
+                RenderPath::Forward  => self.add_forward_pass(&mut graph, frame, depth),
+                RenderPath::Deferred => self.deferred.add_passes(
+                    &mut graph,
+                    &self.gpu.device,
+                    frame,
+                    depth,
+                    (self.gpu.config.width, self.gpu.config.height),
+                    &self.camera_state.camera_bind_group,
+                    &self.model_uniform_state.model_bind_group,
+                    &self.lighting.bind_group,
+                    &self.scene_color,
+                    &self.assets.loader.mipmaps,
+                    &self.pipelines,
+                    self.camera_state.camera.eye,
+                    self.model_uniform_state.model.as_ref(),
+                    self.sky.as_ref(),
+                ),
+            }
+
+            // ---> Graphviz dump for debugging:
+            if self.dump_render_graph {
+                match std::fs::write("render_graph.dot", graph.to_dot()) {
+                    Ok(_)  => println!("Render graph written to render_graph.dot"),
+                    Err(e) => eprintln!("Failed to write render graph: {}", e),
+                }
+            }
+
+            if let Err(e) = graph.execute(&self.gpu.device, &mut encoder, &mut transient_pool) {
+                eprintln!("Render graph error: {}", e);
+            }
+        }
+        self.transient_pool    = transient_pool;
+        self.dump_render_graph = false;
+
+        // ---> Send to GPU to render the image:
+        self.gpu.queue.submit(Some(encoder.finish()));
+    }
+
+    fn add_forward_pass<'a>(&'a self, 
+                            graph: &mut RenderGraph<'a>, 
+                            frame: ResourceHandle, 
+                            depth: ResourceHandle) {
+        let model = self.model_uniform_state.model.as_ref();
+
+        // ---> Transmission: opaque meshes into the scene colour, the rest in a second pass:
+        let transmission = model.is_some_and(|model| model.has_transmission());
+        let target       = if transmission {
+            self.scene_color.declare(graph, self.gpu.config.width, self.gpu.config.height)
+        } else {
+            frame
+        };
+
+        graph.add_pass("Forward Pass", |builder| {
+            builder.write(target);
+            builder.write(depth);
+        }, move |encoder, resources| {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor { 
                 label: Some("Render Pass"), 
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment { 
-                    view: &view, 
+                    view: resources.texture_view(target), 
                     resolve_target: None, 
                     ops: wgpu::Operations { 
                         // ---> Background color:
@@ -372,7 +733,7 @@ impl State {
                     },
                 })], 
                 depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment { 
-                    view: &self.depth_texture.view, 
+                    view: resources.texture_view(depth), 
                     depth_ops: Some(wgpu::Operations { 
                         load: wgpu::LoadOp::Clear(1.0), 
                         store: wgpu::StoreOp::Store, 
@@ -383,41 +744,34 @@ impl State {
                 occlusion_query_set: None, 
             });
 
-            render_pass.set_pipeline(&self.render_pipeline);
+            // ---> Sky first, the scene covers it:
+            if let Some(sky) = &self.sky {
+                sky.draw(&mut render_pass, &self.camera_state.camera_bind_group);
+            }
 
             // ---> Set bind groups for camera and model:
             render_pass.set_bind_group(0, &self.camera_state.camera_bind_group, &[]);
             render_pass.set_bind_group(1, &self.model_uniform_state.model_bind_group, &[]);
 
-            // ---> Set bind group for lighting:
-            render_pass.set_bind_group(3, &self.lighting.bind_group, &[]);
-
-            // ---> Render model (if exists...):
-            if let Some(model) = &self.model_uniform_state.model {
-                for mesh in &model.meshes {
-                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), 
-                                                 wgpu::IndexFormat::Uint32);
-                    
-                    // ---> Set material bind group (if implemented):
-                    if mesh.material_index < model.materials.len() {
-                        render_pass.set_bind_group(
-                            2, &model.materials[mesh.material_index].bind_group, &[],
-                        );
-                    }
-                    
-                    // ===>>> DRAW !!!
-                    render_pass.draw_indexed(0..mesh.num_indices, 0, 0..1);
+            // ---> Set bind group for lighting (+ fallback scene colour):
+            render_pass.set_bind_group(3, &self.scene_color.default_bind_group, &[]);
+
+            // ---> Render model (if exists...), transparent meshes last:
+            if let Some(model) = model {
+                model.draw_opaque(&mut render_pass, &self.pipelines, PassKind::Forward);
+                if !transmission {
+                    model.draw_transparent(&mut render_pass, &self.pipelines, &self.camera_state.camera.eye);
                 }
             }
+        });
+
+        if transmission {
+            self.scene_color.add_transparent_passes(graph, &self.gpu.device, &self.assets.loader.mipmaps, 
+                                                    Some(target), frame, depth, 
+                                                    &self.camera_state.camera_bind_group,
+                                                    &self.model_uniform_state.model_bind_group,
+                                                    &self.pipelines, self.camera_state.camera.eye, model);
         }
-        // ---> End of render pass...
-
-        // ---> Send to GPU to render the image:
-        self.gpu.queue.submit(Some(encoder.finish()));
-        output.present();
-
-        Ok(())
     }
 }
 ///// STATE STRUCTURE //////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,483 @@
+/*
+
+    Texture streaming.
+
+    Streamed images are decoded on a background thread into a complete CPU mip chain, so loading
+    a scene does not wait for image decoding. The GPU only holds the levels a texture currently
+    needs: a role coloured 1x1 placeholder until decoding is done, then a small tail of the chain
+    (`placeholder_size`), and higher levels once the texture covers enough pixels on screen.
+
+    Changing the resident levels creates a new texture; the asset cache then points the materials
+    using the old one to it (see `AssetCache::update_streaming`). Resident levels stay within a
+    VRAM budget and uploads within a per-frame budget. Textures that are more detailed than they
+    need to be give up levels when memory is short.
+
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::mpsc;
+
+use crate::assets::TextureAssetKey;
+use crate::ktx::Ktx2Image;
+use crate::ktx::is_ktx2;
+use crate::texture::SamplerKey;
+use crate::texture::Texture;
+use crate::texture::TextureLoader;
+use crate::texture::TextureRole;
+use crate::texture::cpu_mip_chain;
+use crate::texture::create_texture_from_levels;
+use crate::texture::prepare_image;
+use crate::texture::supports_cpu_mipmaps;
+
+
+///// STREAMING SETTINGS STRUCTURE /////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamingSettings {
+    pub vram_budget     : u64,  // Bytes of resident streamed texture levels
+    pub upload_budget   : u64,  // Bytes uploaded per frame (at least one texture is always uploaded)
+    pub placeholder_size: u32,  // Largest dimension of the level shown right after decoding
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            vram_budget     : 512 * 1024 * 1024,
+            upload_budget   : 8 * 1024 * 1024,
+            placeholder_size: 64,
+        }
+    }
+}
+///// STREAMING SETTINGS STRUCTURE /////////////////////////////////////////////////////////////////
+
+
+///// MIP CHAIN STRUCTURE //////////////////////////////////////////////////////////////////////////
+/// Every level of a texture in CPU memory, level 0 (largest) first.
+#[derive(Debug, Clone)]
+pub struct MipChain {
+    pub format: wgpu::TextureFormat,
+    pub width : u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl MipChain {
+    /// Decodes an image file (anything `image` reads, or KTX2) and completes its mip chain.
+    pub fn from_encoded(bytes   : &[u8],
+                        srgb    : bool,
+                        features: wgpu::Features,
+                        label   : &str) -> anyhow::Result<Self> {
+        if is_ktx2(bytes) {
+            return Self::from_ktx2(Ktx2Image::parse(bytes, features)?.with_srgb(srgb), features);
+        }
+
+        let image          = image::load_from_memory(bytes)?;
+        let (data, format) = prepare_image(&image, srgb, features, Some(label));
+
+        Ok(Self {
+            format,
+            width : image.width(),
+            height: image.height(),
+            levels: cpu_mip_chain(&data, image.width(), image.height(), format),
+        })
+    }
+
+    /// Pre-built KTX2 levels are kept; a single uncompressed level gets its chain built.
+    pub fn from_ktx2(image: Ktx2Image, features: wgpu::Features) -> anyhow::Result<Self> {
+        let required = image.format.required_features();
+        if !features.contains(required) {
+            anyhow::bail!("{:?} needs {:?}, which the adapter does not support", image.format, required);
+        }
+
+        let levels = if image.levels.len() == 1 && supports_cpu_mipmaps(image.format) {
+            cpu_mip_chain(&image.levels[0], image.width, image.height, image.format)
+        } else {
+            image.levels
+        };
+
+        Ok(Self { format: image.format, width: image.width, height: image.height, levels })
+    }
+
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// Smallest level that can be the base of a texture (block compressed textures need block
+    /// aligned sizes).
+    pub fn lowest_level(&self) -> u32 {
+        let (block_width, block_height) = self.format.block_dimensions();
+        (0..self.levels.len() as u32).rev()
+                                     .find(|level| {
+                                         let (width, height) = self.level_size(*level);
+                                         width.is_multiple_of(block_width) && height.is_multiple_of(block_height)
+                                     })
+                                     .unwrap_or(0)
+    }
+
+    /// First level not larger than `size` in either dimension.
+    pub fn placeholder_level(&self, size: u32) -> u32 {
+        (0..self.levels.len() as u32).find(|level| {
+                                         let (width, height) = self.level_size(*level);
+                                         width.max(height) <= size
+                                     })
+                                     .unwrap_or(u32::MAX)
+                                     .min(self.lowest_level())
+    }
+
+    /// Level whose size matches `pixels` texels on screen (0 if it covers more than level 0).
+    pub fn wanted_level(&self, pixels: f32) -> u32 {
+        let size   = self.width.max(self.height) as f32;
+        let level  = (size / pixels.max(1.0)).log2().floor().max(0.0) as u32;
+        level.min(self.lowest_level())
+    }
+
+    /// Bytes of the levels from `first_level` down to the smallest.
+    pub fn memory(&self, first_level: u32) -> u64 {
+        self.levels.iter().skip(first_level as usize).map(|level| level.len() as u64).sum()
+    }
+
+    pub fn upload(&self,
+                  first_level: u32,
+                  device     : &wgpu::Device,
+                  queue      : &wgpu::Queue,
+                  loader     : &TextureLoader,
+                  sampler    : &SamplerKey,
+                  label      : &str) -> anyhow::Result<Texture> {
+        let (width, height) = self.level_size(first_level);
+        create_texture_from_levels(device, queue, loader, sampler, &self.levels[first_level as usize..],
+                                   width, height, self.format, Some(label))
+    }
+}
+
+/// 1x1 texture shown until the image is decoded, in the neutral value of its role.
+pub fn create_placeholder_texture(device : &wgpu::Device,
+                                  queue  : &wgpu::Queue,
+                                  loader : &TextureLoader,
+                                  role   : TextureRole,
+                                  sampler: &SamplerKey,
+                                  label  : &str) -> anyhow::Result<Texture> {
+    let (texel, format) = match role {
+        TextureRole::Normal | 
+        TextureRole::ClearcoatNormal => ([128, 128, 255, 255], wgpu::TextureFormat::Rgba8Unorm),
+        _ if role.is_srgb()          => ([255, 255, 255, 255], wgpu::TextureFormat::Rgba8UnormSrgb),
+        _                            => ([255, 255, 255, 255], wgpu::TextureFormat::Rgba8Unorm),
+    };
+
+    create_texture_from_levels(device, queue, loader, sampler, &[texel.to_vec()], 1, 1, format, Some(label))
+}
+///// MIP CHAIN STRUCTURE //////////////////////////////////////////////////////////////////////////
+
+
+///// TEXTURE STREAMER STRUCTURE ///////////////////////////////////////////////////////////////////
+pub enum StreamSource {
+    Encoded(Vec<u8>),  // Decoded on the background thread
+    Chain(MipChain),   // Ready to upload
+}
+
+struct DecodeRequest {
+    key    : TextureAssetKey,
+    bytes  : Vec<u8>,
+    srgb   : bool,
+    sampler: SamplerKey,
+    label  : String,
+}
+
+struct Decoded {
+    key    : TextureAssetKey,
+    chain  : anyhow::Result<MipChain>,
+    sampler: SamplerKey,
+    label  : String,
+}
+
+pub struct StreamedTexture {
+    pub chain         : MipChain,
+    pub sampler       : SamplerKey,
+    pub label         : String,
+    pub resident_level: u32,
+    pub wanted_level  : u32,
+}
+
+pub struct TextureStreamer {
+    pub settings: StreamingSettings,
+    requests    : mpsc::Sender<DecodeRequest>,
+    decoded     : mpsc::Receiver<Decoded>,
+    pending     : usize,
+    textures    : HashMap<TextureAssetKey, StreamedTexture>,
+}
+
+impl TextureStreamer {
+    pub fn new(settings: StreamingSettings, features: wgpu::Features) -> Self {
+        let (requests, request_receiver) = mpsc::channel::<DecodeRequest>();
+        let (decoded_sender, decoded)    = mpsc::channel();
+
+        // ---> Decoder thread, ends when the streamer (the request sender) is dropped:
+        std::thread::spawn(move || {
+            for request in request_receiver {
+                let chain = MipChain::from_encoded(&request.bytes, request.srgb, features, &request.label);
+                let reply = Decoded { key: request.key, chain, sampler: request.sampler, label: request.label };
+                if decoded_sender.send(reply).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { settings, requests, decoded, pending: 0, textures: HashMap::new() }
+    }
+
+    pub fn request(&mut self,
+                   key    : TextureAssetKey,
+                   source : StreamSource,
+                   sampler: SamplerKey,
+                   label  : String) {
+        match source {
+            StreamSource::Chain(chain) => {
+                self.insert(key, chain, sampler, label);
+            },
+            StreamSource::Encoded(bytes) => {
+                let srgb = key.role.is_srgb();
+                if self.requests.send(DecodeRequest { key, bytes, srgb, sampler, label }).is_ok() {
+                    self.pending += 1;
+                }
+            },
+        }
+    }
+
+    fn insert(&mut self, key: TextureAssetKey, chain: MipChain, sampler: SamplerKey, label: String) {
+        let level = chain.lowest_level() + 1;  // Nothing resident yet, the placeholder is shown
+        self.textures.insert(key, StreamedTexture { chain, sampler, label, resident_level: level, wanted_level: level });
+    }
+
+    /// Decodes still running on the background thread.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    pub fn forget(&mut self, key: &TextureAssetKey) {
+        self.textures.remove(key);
+    }
+
+    pub fn resident_memory(&self) -> u64 {
+        self.textures.values().map(|texture| texture.chain.memory(texture.resident_level)).sum()
+    }
+
+    /// Takes finished decodes and selects the resident levels for the next frame. `coverage` is
+    /// the on-screen size (pixels) of every visible streamed texture. Returns the textures that
+    /// replace the current ones.
+    pub fn update(&mut self,
+                  device  : &wgpu::Device,
+                  queue   : &wgpu::Queue,
+                  loader  : &TextureLoader,
+                  coverage: &HashMap<TextureAssetKey, f32>) -> Vec<(TextureAssetKey, Texture)> {
+        while let Ok(decoded) = self.decoded.try_recv() {
+            self.pending -= 1;
+            match decoded.chain {
+                Ok(chain) => self.insert(decoded.key, chain, decoded.sampler, decoded.label),
+                Err(e)    => eprintln!("Failed to decode {}: {}", decoded.label, e),
+            }
+        }
+
+        // ---> Invisible textures only need their placeholder levels:
+        let placeholder_size = self.settings.placeholder_size;
+        let mut keys = Vec::with_capacity(self.textures.len());
+        let mut plan = Vec::with_capacity(self.textures.len());
+        for (key, texture) in &mut self.textures {
+            let placeholder = texture.chain.placeholder_level(placeholder_size);
+            texture.wanted_level = match coverage.get(key) {
+                Some(pixels) => texture.chain.wanted_level(*pixels).min(placeholder),
+                None         => placeholder,
+            };
+            keys.push(key.clone());
+            plan.push(PlanEntry {
+                resident   : texture.resident_level,
+                wanted     : texture.wanted_level,
+                placeholder,
+                lowest     : texture.chain.lowest_level(),
+            });
+        }
+
+        let memory = |index: usize, level: u32| self.textures[&keys[index]].chain.memory(level);
+        let changes = plan_levels(&plan, memory, self.settings.vram_budget, self.settings.upload_budget);
+
+        let mut uploads = Vec::with_capacity(changes.len());
+        for (index, level) in changes {
+            let texture = self.textures.get_mut(&keys[index]).unwrap();
+            match texture.chain.upload(level, device, queue, loader, &texture.sampler, &texture.label) {
+                Ok(uploaded) => {
+                    texture.resident_level = level;
+                    uploads.push((keys[index].clone(), uploaded));
+                },
+                Err(e) => eprintln!("Failed to upload {}: {}", texture.label, e),
+            }
+        }
+
+        uploads
+    }
+}
+
+impl fmt::Display for TextureStreamer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let streaming = self.textures.values().filter(|texture| texture.resident_level > texture.wanted_level).count();
+        write!(f, "{} textures ({} decoding, {} streaming in), {:.2} of {:.2} MiB resident",
+               self.textures.len(), self.pending(), streaming,
+               self.resident_memory()    as f64 / (1024.0 * 1024.0),
+               self.settings.vram_budget as f64 / (1024.0 * 1024.0))
+    }
+}
+///// TEXTURE STREAMER STRUCTURE ///////////////////////////////////////////////////////////////////
+
+
+///// LEVEL PLANNING PROCEDURE /////////////////////////////////////////////////////////////////////
+/// Resident state of one streamed texture. Levels count down towards more detail, `lowest + 1`
+/// means nothing is resident yet. `wanted` is never below `placeholder`.
+#[derive(Debug, Clone, Copy)]
+struct PlanEntry {
+    resident   : u32,
+    wanted     : u32,
+    placeholder: u32,
+    lowest     : u32,
+}
+
+/// Decides the new resident level of textures (returns `(index, level)` for changed ones).
+///  * Textures without resident levels get their placeholder level first.
+///  * Textures needing more detail get one more level per frame, the largest deficit first,
+///    as long as the upload budget allows.
+///  * If that exceeds the VRAM budget, textures with more detail than they want give up levels,
+///    and if there are none left the upgrade waits.
+fn plan_levels(entries      : &[PlanEntry],
+               memory       : impl Fn(usize, u32) -> u64,
+               vram_budget  : u64,
+               upload_budget: u64) -> Vec<(usize, u32)> {
+    let resident_memory = |index: usize, level: u32| {
+        if level > entries[index].lowest { 0 } else { memory(index, level) }
+    };
+
+    let mut levels   = entries.iter().map(|entry| entry.resident).collect::<Vec<_>>();
+    let mut total    = (0..entries.len()).map(|index| resident_memory(index, levels[index])).sum::<u64>();
+    let mut uploaded = 0;
+
+    // ---> Frees memory by dropping a level of the most over-detailed texture (except `keep`):
+    let drop_surplus = |levels: &mut Vec<u32>, total: &mut u64, keep: usize| -> bool {
+        let surplus = (0..entries.len()).filter(|index| *index != keep && levels[*index] < entries[*index].wanted)
+                                        .max_by_key(|index| entries[*index].wanted - levels[*index]);
+        let Some(index) = surplus else {
+            return false;
+        };
+        *total -= resident_memory(index, levels[index]) - resident_memory(index, levels[index] + 1);
+        levels[index] += 1;
+        true
+    };
+
+    // ---> New textures first (their placeholder levels are small), then by deficit:
+    let mut candidates = (0..entries.len()).filter(|index| entries[*index].wanted < levels[*index])
+                                           .collect::<Vec<_>>();
+    candidates.sort_by_key(|index| {
+        let entry = &entries[*index];
+        (entry.resident <= entry.lowest, std::cmp::Reverse(entry.resident - entry.wanted))
+    });
+
+    for index in candidates {
+        let entry = &entries[index];
+        let level = if entry.resident > entry.lowest { entry.placeholder } else { entry.resident - 1 };
+        let size  = memory(index, level);
+        if uploaded > 0 && uploaded + size > upload_budget {
+            break;
+        }
+
+        let cost = size - resident_memory(index, levels[index]);
+        while total + cost > vram_budget {
+            if !drop_surplus(&mut levels, &mut total, index) {
+                break;
+            }
+        }
+        if total + cost > vram_budget {
+            continue;
+        }
+
+        total       += cost;
+        uploaded    += size;
+        levels[index] = level;
+    }
+
+    // ---> Still over budget (e.g. after lowering it): give up surplus detail:
+    while total > vram_budget && drop_surplus(&mut levels, &mut total, usize::MAX) {}
+
+    levels.iter().enumerate()
+                 .filter(|(index, level)| **level != entries[*index].resident)
+                 .map(|(index, level)| (index, *level))
+                 .collect()
+}
+///// LEVEL PLANNING PROCEDURE /////////////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 1024x1024 RGBA8: level `l` is 4 MiB / 4^l.
+    fn memory(_: usize, level: u32) -> u64 {
+        (level..=10).map(|level| 4 * (1024u64 >> level).pow(2)).sum()
+    }
+
+    const MIB: u64 = 1024 * 1024;
+
+    #[test]
+    fn mip_chain_levels_follow_screen_size() {
+        let chain = MipChain {
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            width : 256,
+            height: 128,
+            levels: cpu_mip_chain(&vec![0; 256 * 128 * 4], 256, 128, wgpu::TextureFormat::Rgba8Unorm),
+        };
+        assert_eq!(chain.levels.len(), 9);
+        assert_eq!(chain.lowest_level(), 8);
+        assert_eq!(chain.placeholder_level(64), 2);
+
+        assert_eq!(chain.wanted_level(1000.0), 0);
+        assert_eq!(chain.wanted_level(256.0),  0);
+        assert_eq!(chain.wanted_level(100.0),  1);
+        assert_eq!(chain.wanted_level(0.0),    8);
+        assert_eq!(chain.memory(7), (2 + 1) * 4);
+    }
+
+    #[test]
+    fn new_textures_get_placeholders_before_upgrades() {
+        let entries = [
+            PlanEntry { resident: 4,  wanted: 0, placeholder: 4, lowest: 10 },  // Streaming in
+            PlanEntry { resident: 11, wanted: 0, placeholder: 4, lowest: 10 },  // Just decoded
+        ];
+        let changes = plan_levels(&entries, memory, 512 * MIB, 64 * 1024);
+        assert_eq!(changes, [(1, 4)]);
+    }
+
+    #[test]
+    fn upgrades_are_one_level_per_frame_within_upload_budget() {
+        let entries = [
+            PlanEntry { resident: 4, wanted: 0, placeholder: 4, lowest: 10 },
+            PlanEntry { resident: 6, wanted: 0, placeholder: 4, lowest: 10 },
+            PlanEntry { resident: 5, wanted: 5, placeholder: 4, lowest: 10 },
+        ];
+
+        // ---> Largest deficit first; one texture is always uploaded, even over budget:
+        assert_eq!(plan_levels(&entries, memory, 512 * MIB, 0),       [(1, 5)]);
+        assert_eq!(plan_levels(&entries, memory, 512 * MIB, 64 * MIB), [(0, 3), (1, 5)]);
+    }
+
+    #[test]
+    fn vram_budget_takes_detail_from_textures_that_do_not_need_it() {
+        let entries = [
+            PlanEntry { resident: 2, wanted: 1, placeholder: 4, lowest: 10 },  // Needs more detail
+            PlanEntry { resident: 0, wanted: 3, placeholder: 4, lowest: 10 },  // Far away now
+        ];
+
+        // ---> Level 1 (1.33 MiB) fits after the second texture drops to level 1 or lower:
+        let budget  = memory(0, 1) + memory(1, 1);
+        let changes = plan_levels(&entries, memory, budget, 64 * MIB);
+        assert_eq!(changes, [(0, 1), (1, 1)]);
+
+        // ---> Nothing to give up, the upgrade has to wait:
+        let entries = [entries[0], PlanEntry { resident: 0, wanted: 0, placeholder: 4, lowest: 10 }];
+        assert!(plan_levels(&entries, memory, memory(0, 2) + memory(1, 0), 64 * MIB).is_empty());
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -1,5 +1,17 @@
 
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use wgpu::util::DeviceExt;
+
+use crate::ktx::Ktx2Image;
+use crate::streaming::StreamingSettings;
+use crate::ktx::is_ktx2;
+use crate::shader_library::ShaderDefines;
+use crate::shader_library::ShaderLibrary;
+
 ///// TEXTURE STRUCTURE ////////////////////////////////////////////////////////////////////////////
 #[derive(Debug)]
 pub struct Texture {
@@ -19,71 +31,1115 @@ impl Clone for Texture {
 }
 ///// TEXTURE STRUCTURE ////////////////////////////////////////////////////////////////////////////
 
+///// TEXTURE ROLE ENUM ////////////////////////////////////////////////////////////////////////////
+/// What a material texture is used for; decides how its texels are decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureRole {
+    BaseColor,
+    Emissive,
+    Normal,
+    Occlusion,
+    MetallicRoughness,
+    Clearcoat,         // KHR_materials_clearcoat factor (R) and roughness (G)
+    ClearcoatNormal,
+    SheenColor,        // KHR_materials_sheen
+    SheenRoughness,
+    Transmission,      // KHR_materials_transmission
+    Specular,          // KHR_materials_specular strength (A)
+    SpecularColor,
+}
+
+impl TextureRole {
+    /// Colour textures are sRGB encoded, everything else is linear data (glTF 2.0, 3.9.x).
+    pub fn is_srgb(self) -> bool {
+        matches!(self, TextureRole::BaseColor | TextureRole::Emissive | TextureRole::SheenColor | TextureRole::SpecularColor)
+    }
+}
+///// TEXTURE ROLE ENUM ////////////////////////////////////////////////////////////////////////////
+
+///// TEXTURE SETTINGS STRUCTURE ///////////////////////////////////////////////////////////////////
+#[derive(Debug, Clone, Copy)]
+pub struct TextureSettings {
+    pub generate_mipmaps: bool,
+    pub anisotropy      : u16,  // 1 = off, up to 16
+    pub streaming       : Option<StreamingSettings>,  // None = glTF images load synchronously
+}
+
+impl Default for TextureSettings {
+    fn default() -> Self {
+        Self { generate_mipmaps: true, anisotropy: 16, streaming: None }
+    }
+}
+
+///// TEXTURE SETTINGS STRUCTURE ///////////////////////////////////////////////////////////////////
+
+///// TEXTURE OPTIONS STRUCTURE ////////////////////////////////////////////////////////////////////
+/// How a standalone image file (not referenced by a glTF material) is uploaded.
+#[derive(Debug, Clone, Copy)]
+pub struct TextureOptions {
+    pub srgb            : bool,  // Colour data (true) or linear data like normals and masks
+    pub generate_mipmaps: bool,
+    pub sampler         : SamplerKey,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self { srgb: true, generate_mipmaps: true, sampler: SamplerKey::default() }
+    }
+}
+
+///// TEXTURE OPTIONS STRUCTURE ////////////////////////////////////////////////////////////////////
+
+///// SAMPLER CACHE STRUCTURE //////////////////////////////////////////////////////////////////////
+/// Everything that distinguishes two texture samplers (anisotropy is a global setting).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerKey {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,  // 3D textures only
+    pub mag_filter    : wgpu::FilterMode,
+    pub min_filter    : wgpu::FilterMode,
+    pub mipmap_filter : wgpu::FilterMode,
+    pub mipmapped     : bool,  // false = only the base level is sampled
+}
+
+impl Default for SamplerKey {
+    /// Trilinear sampling with repeat wrapping.
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter    : wgpu::FilterMode::Linear,
+            min_filter    : wgpu::FilterMode::Linear,
+            mipmap_filter : wgpu::FilterMode::Linear,
+            mipmapped     : true,
+        }
+    }
+}
+
+impl SamplerKey {
+    /// Trilinear sampling clamped at the edges (cube maps, lookup tables).
+    pub fn clamped() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        }
+    }
+
+    /// Maps a glTF sampler; unspecified filters fall back to trilinear.
+    pub fn from_gltf(sampler: &gltf::texture::Sampler) -> Self {
+        use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+        use wgpu::FilterMode::{Linear, Nearest};
+
+        let address_mode = |mode: WrappingMode| match mode {
+            WrappingMode::ClampToEdge    => wgpu::AddressMode::ClampToEdge,
+            WrappingMode::MirroredRepeat => wgpu::AddressMode::MirrorRepeat,
+            WrappingMode::Repeat         => wgpu::AddressMode::Repeat,
+        };
+
+        let mag_filter = match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => Nearest,
+            Some(MagFilter::Linear) | None => Linear,
+        };
+
+        let (min_filter, mipmap_filter, mipmapped) = match sampler.min_filter() {
+            Some(MinFilter::Nearest)              => (Nearest, Nearest, false),
+            Some(MinFilter::Linear)               => (Linear,  Nearest, false),
+            Some(MinFilter::NearestMipmapNearest) => (Nearest, Nearest, true),
+            Some(MinFilter::LinearMipmapNearest)  => (Linear,  Nearest, true),
+            Some(MinFilter::NearestMipmapLinear)  => (Nearest, Linear,  true),
+            Some(MinFilter::LinearMipmapLinear) | None => (Linear, Linear, true),
+        };
+
+        Self {
+            address_mode_u: address_mode(sampler.wrap_s()),
+            address_mode_v: address_mode(sampler.wrap_t()),
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            mipmapped,
+        }
+    }
+}
+
+/// Shares one `wgpu::Sampler` between all textures with identical sampler settings.
+pub struct SamplerCache {
+    anisotropy: u16,
+    samplers  : RefCell<HashMap<SamplerKey, wgpu::Sampler>>,
+}
+
+impl SamplerCache {
+    pub fn new(anisotropy: u16) -> Self {
+        Self { anisotropy: anisotropy.clamp(1, 16), samplers: RefCell::new(HashMap::new()) }
+    }
+
+    pub fn get(&self, device: &wgpu::Device, key: &SamplerKey) -> wgpu::Sampler {
+        self.samplers.borrow_mut().entry(*key).or_insert_with(|| {
+            // ---> wgpu only allows anisotropic filtering if every filter is linear:
+            let all_linear = key.mag_filter    == wgpu::FilterMode::Linear &&
+                             key.min_filter    == wgpu::FilterMode::Linear &&
+                             key.mipmap_filter == wgpu::FilterMode::Linear;
+
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label           : Some("Texture Sampler"),
+                address_mode_u  : key.address_mode_u,
+                address_mode_v  : key.address_mode_v,
+                address_mode_w  : key.address_mode_w,
+                mag_filter      : key.mag_filter,
+                min_filter      : key.min_filter,
+                mipmap_filter   : key.mipmap_filter,
+                lod_max_clamp   : if key.mipmapped { 32.0 } else { 0.0 },
+                anisotropy_clamp: if all_linear { self.anisotropy } else { 1 },
+                ..Default::default()
+            })
+        }).clone()
+    }
+}
+///// SAMPLER CACHE STRUCTURE //////////////////////////////////////////////////////////////////////
+
+///// TEXTURE LOADER STRUCTURE /////////////////////////////////////////////////////////////////////
+/// Shared state for uploading many textures (one per scene/model load).
+pub struct TextureLoader {
+    pub settings: TextureSettings,
+    pub mipmaps : MipmapGenerator,
+    pub samplers: SamplerCache,
+}
+
+impl TextureLoader {
+    pub fn new(device: &wgpu::Device, shaders: &ShaderLibrary, settings: TextureSettings) -> Self {
+        Self {
+            settings,
+            mipmaps : MipmapGenerator::new(device, shaders),
+            samplers: SamplerCache::new(settings.anisotropy),
+        }
+    }
+}
+///// TEXTURE LOADER STRUCTURE /////////////////////////////////////////////////////////////////////
+
+///// MIPMAP GENERATOR STRUCTURE ///////////////////////////////////////////////////////////////////
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Builds mip chains on the GPU by repeatedly blitting level N-1 into level N.
+/// The same blit projects equirectangular panoramas onto cube faces.
+pub struct MipmapGenerator {
+    shader          : wgpu::ShaderModule,
+    layouts         : HashMap<wgpu::TextureViewDimension, wgpu::BindGroupLayout>,  // D2, D2Array, Cube
+    sampler         : wgpu::Sampler,
+    equirect_sampler: wgpu::Sampler,  // Longitude wraps around
+    pipelines       : RefCell<HashMap<(wgpu::TextureFormat, &'static str), wgpu::RenderPipeline>>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &wgpu::Device, shaders: &ShaderLibrary) -> Self {
+        let shader = shaders.load(device, "mipmap.wgsl", &ShaderDefines::new()).unwrap_or_else(|error| panic!("{:#}", error));
+
+        // ---> One layout per source dimension (bindings 0, 2 and 3 of the shader):
+        let layouts = [
+            (wgpu::TextureViewDimension::D2,      0),
+            (wgpu::TextureViewDimension::D2Array, 2),
+            (wgpu::TextureViewDimension::Cube,    3),
+        ].into_iter().map(|(dimension, binding)| {
+            let entries = [
+                wgpu::BindGroupLayoutEntry {
+                    binding,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty        : wgpu::BindingType::Texture {
+                        sample_type   : wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: dimension,
+                        multisampled  : false,
+                    },
+                    count     : None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding   : 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count     : None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding   : 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty        : wgpu::BindingType::Buffer {
+                        ty                : wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size  : None,
+                    },
+                    count     : None,
+                },
+            ];
+
+            let layout = device.create_bind_group_layout(
+                &wgpu::BindGroupLayoutDescriptor {
+                    label  : Some("Mipmap Bind Group Layout"),
+                    entries: &entries,
+                },
+            );
+            (dimension, layout)
+        }).collect();
+
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label         : Some("Mipmap Sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter    : wgpu::FilterMode::Linear,
+                min_filter    : wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        );
+        let equirect_sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label         : Some("Equirect Sampler"),
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter    : wgpu::FilterMode::Linear,
+                min_filter    : wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        );
+
+        Self { shader, layouts, sampler, equirect_sampler, pipelines: RefCell::new(HashMap::new()) }
+    }
+
+    /// Can this format be rendered to and linearly filtered (needed for the blit)?
+    pub fn supports_format(&self, device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+        let features = format.guaranteed_format_features(device.features());
+        features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT) &&
+        features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+    }
+
+    fn pipeline(&self, 
+                device     : &wgpu::Device, 
+                format     : wgpu::TextureFormat, 
+                dimension  : wgpu::TextureViewDimension,
+                entry_point: &'static str) -> wgpu::RenderPipeline {
+        self.pipelines.borrow_mut().entry((format, entry_point)).or_insert_with(|| {
+            let layout = device.create_pipeline_layout(
+                &wgpu::PipelineLayoutDescriptor {
+                    label               : Some("Mipmap Pipeline Layout"),
+                    bind_group_layouts  : &[&self.layouts[&dimension]],
+                    push_constant_ranges: &[],
+                },
+            );
+
+            device.create_render_pipeline(
+                &wgpu::RenderPipelineDescriptor {
+                    label        : Some("Mipmap Pipeline"),
+                    layout       : Some(&layout),
+                    vertex       : wgpu::VertexState {
+                        module             : &self.shader,
+                        entry_point        : Some("vs_main"),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        buffers            : &[],
+                    },
+                    primitive    : wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample  : wgpu::MultisampleState::default(),
+                    fragment     : Some(wgpu::FragmentState {
+                        module             : &self.shader,
+                        entry_point        : Some(entry_point),
+                        compilation_options: wgpu::PipelineCompilationOptions::default(),
+                        targets            : &[Some(format.into())],
+                    }),
+                    multiview    : None,
+                    cache        : None,
+                },
+            )
+        }).clone()
+    }
+
+    /// Records a filtered copy of `source` into `target` (a render target of `target_format`).
+    pub fn blit(&self, 
+                device       : &wgpu::Device, 
+                encoder      : &mut wgpu::CommandEncoder, 
+                source       : &wgpu::TextureView, 
+                target       : &wgpu::TextureView, 
+                target_format: wgpu::TextureFormat) {
+        self.blit_layer(device, encoder, source, wgpu::TextureViewDimension::D2, 0, target, target_format);
+    }
+
+    /// Like `blit`, but reads `layer` of a `D2Array` or `Cube` source view.
+    #[allow(clippy::too_many_arguments)]
+    pub fn blit_layer(&self, 
+                      device       : &wgpu::Device, 
+                      encoder      : &mut wgpu::CommandEncoder, 
+                      source       : &wgpu::TextureView, 
+                      dimension    : wgpu::TextureViewDimension,
+                      layer        : u32,
+                      target       : &wgpu::TextureView, 
+                      target_format: wgpu::TextureFormat) {
+        let entry_point = match dimension {
+            wgpu::TextureViewDimension::D2Array => "fs_array",
+            wgpu::TextureViewDimension::Cube    => "fs_cube",
+            _                                   => "fs_main",
+        };
+        self.draw(device, encoder, source, dimension, entry_point, &self.sampler, layer, target, target_format);
+    }
+
+    /// Renders cube face `face` (+X, -X, +Y, -Y, +Z, -Z) from an equirectangular `source` view.
+    pub fn project_equirect(&self, 
+                            device       : &wgpu::Device, 
+                            encoder      : &mut wgpu::CommandEncoder, 
+                            source       : &wgpu::TextureView, 
+                            face         : u32,
+                            target       : &wgpu::TextureView, 
+                            target_format: wgpu::TextureFormat) {
+        self.draw(device, encoder, source, wgpu::TextureViewDimension::D2, "fs_equirect", &self.equirect_sampler, 
+                  face, target, target_format);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn draw(&self, 
+            device       : &wgpu::Device, 
+            encoder      : &mut wgpu::CommandEncoder, 
+            source       : &wgpu::TextureView, 
+            dimension    : wgpu::TextureViewDimension,
+            entry_point  : &'static str,
+            sampler      : &wgpu::Sampler,
+            layer        : u32,
+            target       : &wgpu::TextureView, 
+            target_format: wgpu::TextureFormat) {
+        let pipeline = self.pipeline(device, target_format, dimension, entry_point);
+
+        let binding = match dimension {
+            wgpu::TextureViewDimension::D2Array => 2,
+            wgpu::TextureViewDimension::Cube    => 3,
+            _                                   => 0,
+        };
+        let layer_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label   : Some("Mipmap Layer Buffer"),
+                contents: bytemuck::cast_slice(&[layer, 0, 0, 0]),
+                usage   : wgpu::BufferUsages::UNIFORM,
+            },
+        );
+
+        let entries = [
+            wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding : 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding : 4,
+                resource: layer_buffer.as_entire_binding(),
+            },
+        ];
+
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label  : Some("Mipmap Bind Group"),
+                layout : &self.layouts[&dimension],
+                entries: &entries,
+            },
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label                   : Some("Mipmap Pass"),
+            color_attachments       : &[Some(wgpu::RenderPassColorAttachment {
+                view          : target,
+                resolve_target: None,
+                ops           : wgpu::Operations {
+                    load : wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes        : None,
+            occlusion_query_set     : None,
+        });
+
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Fills mip levels 1.. of `texture` from level 0 (texture needs RENDER_ATTACHMENT usage).
+    /// Array layers (and cube faces) get their own chain; 3D textures are not supported.
+    pub fn generate(&self, device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        let dimension = source_view_dimension(texture);
+
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Mipmap Encoder") }
+        );
+
+        for level in 1..texture.mip_level_count() {
+            let source = texture.create_view(&wgpu::TextureViewDescriptor {
+                label          : Some("Mip Source View"),
+                dimension      : Some(dimension),
+                base_mip_level : level - 1,
+                mip_level_count: Some(1),
+                ..Default::default()
+            });
+
+            for layer in 0..texture.depth_or_array_layers() {
+                let target = texture.create_view(&wgpu::TextureViewDescriptor {
+                    label            : Some("Mip Target View"),
+                    dimension        : Some(wgpu::TextureViewDimension::D2),
+                    base_mip_level   : level,
+                    mip_level_count  : Some(1),
+                    base_array_layer : layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                });
+                self.blit_layer(device, &mut encoder, &source, dimension, layer, &target, texture.format());
+            }
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// View dimension a layered texture has to be sampled through. The GL backend fixes it when the
+/// texture is created: six square layers are a cube map, any other layer count an array.
+pub fn source_view_dimension(texture: &wgpu::Texture) -> wgpu::TextureViewDimension {
+    match texture.depth_or_array_layers() {
+        1                                        => wgpu::TextureViewDimension::D2,
+        6 if texture.width() == texture.height() => wgpu::TextureViewDimension::Cube,
+        _                                        => wgpu::TextureViewDimension::D2Array,
+    }
+}
+
+//===== CPU FALLBACK ===============================================================================
+pub fn supports_cpu_mipmaps(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Rgba8Unorm  | wgpu::TextureFormat::Rgba8UnormSrgb |
+                     wgpu::TextureFormat::Rgba16Unorm | wgpu::TextureFormat::Rgba16Float    |
+                     wgpu::TextureFormat::Rgba32Float)
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 }
+}
+
+/// Texel data of one of the `supports_cpu_mipmaps` formats as linear floats (RGBA).
+fn decode_texels(data: &[u8], format: wgpu::TextureFormat) -> Vec<f32> {
+    match format {
+        wgpu::TextureFormat::Rgba16Unorm => {
+            data.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]]) as f32 / 65535.0).collect()
+        },
+        wgpu::TextureFormat::Rgba16Float => {
+            data.chunks_exact(2).map(|c| half::f16::from_le_bytes([c[0], c[1]]).to_f32()).collect()
+        },
+        wgpu::TextureFormat::Rgba32Float => {
+            data.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()
+        },
+        _ => {
+            let srgb = format.is_srgb();
+            data.iter().enumerate().map(|(i, v)| {
+                let value = *v as f32 / 255.0;
+                if srgb && i % 4 < 3 { srgb_to_linear(value) } else { value }
+            }).collect()
+        },
+    }
+}
+
+fn encode_texels(values: &[f32], format: wgpu::TextureFormat) -> Vec<u8> {
+    match format {
+        wgpu::TextureFormat::Rgba16Unorm => {
+            values.iter().flat_map(|v| ((v.clamp(0.0, 1.0) * 65535.0 + 0.5) as u16).to_le_bytes()).collect()
+        },
+        wgpu::TextureFormat::Rgba16Float => {
+            values.iter().flat_map(|v| half::f16::from_f32(*v).to_le_bytes()).collect()
+        },
+        wgpu::TextureFormat::Rgba32Float => {
+            values.iter().flat_map(|v| v.to_le_bytes()).collect()
+        },
+        _ => {
+            let srgb = format.is_srgb();
+            values.iter().enumerate().map(|(i, v)| {
+                let value = if srgb && i % 4 < 3 { linear_to_srgb(*v) } else { *v };
+                (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+            }).collect()
+        },
+    }
+}
+
+/// Source texels (and weights) of texel `index` of the next smaller level along one axis. 
+/// Odd sizes use a 3 texel footprint, so the last row/column is not dropped.
+fn downsample_footprint(index: u32, size: u32) -> Vec<(u32, f32)> {
+    let new_size = (size / 2).max(1);
+
+    if size == 1 {
+        vec![(0, 1.0)]
+    } else if size.is_multiple_of(2) {
+        vec![(2 * index, 0.5), (2 * index + 1, 0.5)]
+    } else {
+        let total = (2 * new_size + 1) as f32;
+        vec![(2 * index,     (new_size - index) as f32 / total),
+             (2 * index + 1, new_size as f32 / total),
+             (2 * index + 2, (index + 1) as f32 / total)]
+    }
+}
+
+/// Box filter (2x2, 3x3 along odd axes); sRGB data is averaged in linear space.
+fn downsample_cpu(data  : &[u8], 
+                  width : u32, 
+                  height: u32, 
+                  format: wgpu::TextureFormat) -> (Vec<u8>, u32, u32) {
+    let texels = decode_texels(data, format);
+    let (new_width, new_height) = ((width / 2).max(1), (height / 2).max(1));
+
+    let mut result = Vec::with_capacity((new_width * new_height * 4) as usize);
+    for y in 0..new_height {
+        let rows = downsample_footprint(y, height);
+        for x in 0..new_width {
+            let columns = downsample_footprint(x, width);
+            for channel in 0..4 {
+                let mut sum = 0.0;
+                for &(sy, wy) in &rows {
+                    for &(sx, wx) in &columns {
+                        sum += wx * wy * texels[((sy * width + sx) * 4) as usize + channel];
+                    }
+                }
+                result.push(sum);
+            }
+        }
+    }
+
+    (encode_texels(&result, format), new_width, new_height)
+}
+
+/// Complete mip chain of level 0 `data` (level 0 included), one of the `supports_cpu_mipmaps` formats.
+pub fn cpu_mip_chain(data: &[u8], width: u32, height: u32, format: wgpu::TextureFormat) -> Vec<Vec<u8>> {
+    let mut levels = vec![data.to_vec()];
+    let (mut level_width, mut level_height) = (width, height);
+
+    for _ in 1..mip_level_count(width, height) {
+        let (level_data, new_width, new_height) = downsample_cpu(levels.last().unwrap(), level_width, 
+                                                                 level_height, format);
+        levels.push(level_data);
+        (level_width, level_height) = (new_width, new_height);
+    }
+
+    levels
+}
+//===== CPU FALLBACK ===============================================================================
+///// MIPMAP GENERATOR STRUCTURE ///////////////////////////////////////////////////////////////////
+
 ///// TEXTURE LOADING PROCEDURE ////////////////////////////////////////////////////////////////////
-pub fn load_texture_from_image(image: &gltf::image::Data, 
-                               device: &wgpu::Device, 
-                               queue: &wgpu::Queue, 
-                               label: Option<&str>) -> anyhow::Result<Texture> {
-    // ---> Convert image to RGBA:
+pub fn load_texture_from_image(image  : &gltf::image::Data, 
+                               role   : TextureRole,
+                               sampler: &SamplerKey,
+                               device : &wgpu::Device, 
+                               queue  : &wgpu::Queue, 
+                               loader : &TextureLoader,
+                               label  : Option<&str>) -> anyhow::Result<Texture> {
+    let (width, height) = (image.width, image.height);
+    let error = || anyhow::anyhow!("Image data does not match its size ({}x{})!", width, height);
+
+    let bytes  = || image.pixels.clone();
+    let words  = || bytemuck::pod_collect_to_vec::<u8, u16>(&image.pixels);
+    let floats = || bytemuck::pod_collect_to_vec::<u8, f32>(&image.pixels);
+
+    // ---> Wrap the decoded pixels, keeping the precision of 16 bit and float images:
+    use image::DynamicImage as Image;
+    use image::ImageBuffer as Buffer;
     let dynamic_image = match image.format {
-        gltf::image::Format::R8G8B8 => {
-            let rgb_image = image::RgbImage::from_raw(image.width, image.height, 
-                                                      image.pixels.clone());
-            let rgb_image = rgb_image.ok_or_else(|| {
-                anyhow::anyhow!("Failed to create RGB image!")
-            })?;
-            image::DynamicImage::ImageRgb8(rgb_image)
-        },
-        gltf::image::Format::R8G8B8A8 => {
-            let rgba_image = image::RgbaImage::from_raw(image.width, image.height, 
-                                                        image.pixels.clone());
-            let rgba_image = rgba_image.ok_or_else(|| {
-                anyhow::anyhow!("Failed to create RGBA image!")
-            })?;
-            image::DynamicImage::ImageRgba8(rgba_image)
-        },
-        gltf::image::Format::R8 => {
-            let gray_image = image::GrayImage::from_raw(image.width, image.height, 
-                                                        image.pixels.clone());
-            let gray_image = gray_image.ok_or_else(|| {
-                anyhow::anyhow!("Failed to create gray image!")
-            })?;
-            image::DynamicImage::ImageLuma8(gray_image)
-        }
-        gltf::image::Format::R8G8 => {
-            let gray_alpha_image = image::GrayAlphaImage::from_raw(image.width, image.height, 
-                                                                   image.pixels.clone());
-            let gray_alpha_image = gray_alpha_image.ok_or_else(|| {
-                anyhow::anyhow!("Failed to create gray alpha image!")
-            })?;
-            image::DynamicImage::ImageLumaA8(gray_alpha_image)
-        },
-
-        // Let's assume those never happen :D
-        gltf::image::Format::R16               => todo!(),
-        gltf::image::Format::R16G16            => todo!(),
-        gltf::image::Format::R16G16B16         => todo!(),
-        gltf::image::Format::R16G16B16A16      => todo!(),
-        gltf::image::Format::R32G32B32FLOAT    => todo!(),
-        gltf::image::Format::R32G32B32A32FLOAT => todo!(),
+        gltf::image::Format::R8                => Image::ImageLuma8(Buffer::from_raw(width, height, bytes()).ok_or_else(error)?),
+        gltf::image::Format::R8G8              => Image::ImageLumaA8(Buffer::from_raw(width, height, bytes()).ok_or_else(error)?),
+        gltf::image::Format::R8G8B8            => Image::ImageRgb8(Buffer::from_raw(width, height, bytes()).ok_or_else(error)?),
+        gltf::image::Format::R8G8B8A8          => Image::ImageRgba8(Buffer::from_raw(width, height, bytes()).ok_or_else(error)?),
+        gltf::image::Format::R16               => Image::ImageLuma16(Buffer::from_raw(width, height, words()).ok_or_else(error)?),
+        gltf::image::Format::R16G16            => Image::ImageLumaA16(Buffer::from_raw(width, height, words()).ok_or_else(error)?),
+        gltf::image::Format::R16G16B16         => Image::ImageRgb16(Buffer::from_raw(width, height, words()).ok_or_else(error)?),
+        gltf::image::Format::R16G16B16A16      => Image::ImageRgba16(Buffer::from_raw(width, height, words()).ok_or_else(error)?),
+        gltf::image::Format::R32G32B32FLOAT    => Image::ImageRgb32F(Buffer::from_raw(width, height, floats()).ok_or_else(error)?),
+        gltf::image::Format::R32G32B32A32FLOAT => Image::ImageRgba32F(Buffer::from_raw(width, height, floats()).ok_or_else(error)?),
+    };
+
+    create_texture_from_image(&dynamic_image, role.is_srgb(), sampler, device, queue, loader, label)
+}
+
+/// Uploads a decoded image (see `prepare_image` for the texture format).
+pub fn create_texture_from_image(image  : &image::DynamicImage,
+                                 srgb   : bool,
+                                 sampler: &SamplerKey,
+                                 device : &wgpu::Device, 
+                                 queue  : &wgpu::Queue, 
+                                 loader : &TextureLoader,
+                                 label  : Option<&str>) -> anyhow::Result<Texture> {
+    let (data, format) = prepare_image(image, srgb, device.features(), label);
+
+    Ok(create_texture_with_mipmaps(device, queue, loader, sampler, &data, 
+                                   image.width(), image.height(), format, label))
+}
+
+/// Converts a decoded image into level 0 texel data (CPU only, safe to call on any thread).
+/// 8 bit images become `Rgba8UnormSrgb` (`srgb`) or `Rgba8Unorm`. 16 bit images become 
+/// `Rgba16Unorm`, float images `Rgba32Float`. Where the device lacks the features for those 
+/// (TEXTURE_FORMAT_16BIT_NORM / FLOAT32_FILTERABLE) the closest lossless filterable format is 
+/// used, and `Rgba16Float` only as the last resort.
+/// There are no sRGB variants of the high precision formats, so colour data is linearized.
+pub fn prepare_image(image   : &image::DynamicImage,
+                     srgb    : bool,
+                     features: wgpu::Features,
+                     label   : Option<&str>) -> (Vec<u8>, wgpu::TextureFormat) {
+    let unorm16 = features.contains(wgpu::Features::TEXTURE_FORMAT_16BIT_NORM);
+    let float32 = features.contains(wgpu::Features::FLOAT32_FILTERABLE);
+
+    let (texels, format) = match image {
+        image::DynamicImage::ImageLuma8(_)  |
+        image::DynamicImage::ImageLumaA8(_) |
+        image::DynamicImage::ImageRgb8(_)   |
+        image::DynamicImage::ImageRgba8(_)  => {
+            let format = if srgb { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+            return (image.to_rgba8().into_raw(), format);
+        },
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_) => {
+            // ---> Float images (HDR/EXR) are linear already. Rgba16Float keeps the HDR range 
+            //      (unlike any unorm format), but only has an 11 bit mantissa:
+            let format = if float32 { wgpu::TextureFormat::Rgba32Float } else { wgpu::TextureFormat::Rgba16Float };
+
+            (image.to_rgba32f().into_raw(), format)
+        },
+        _ => {
+            let mut texels = image.to_rgba16().as_raw()
+                                  .iter()
+                                  .map(|v| *v as f32 / 65535.0)
+                                  .collect::<Vec<_>>();
+
+            // ---> Integer images store colours sRGB encoded, like their 8 bit counterparts:
+            if srgb {
+                for (i, value) in texels.iter_mut().enumerate() {
+                    if i % 4 < 3 {
+                        *value = srgb_to_linear(*value);
+                    }
+                }
+            }
+
+            let format = if unorm16 {
+                wgpu::TextureFormat::Rgba16Unorm
+            } else if float32 {
+                wgpu::TextureFormat::Rgba32Float
+            } else {
+                eprintln!("16 bit texture {:?} stored as Rgba16Float (device lacks 16 bit norm formats)",
+                          label.unwrap_or("unnamed"));
+                wgpu::TextureFormat::Rgba16Float
+            };
+
+            (texels, format)
+        },
     };
-    
-    let rgba = dynamic_image.to_rgba8();
-    
+
+    (encode_texels(&texels, format), format)
+}
+
+/// Creates a sampled 2D texture from level 0 data and fills the remaining mip chain
+/// (GPU blit where the format is renderable, CPU box filter otherwise).
+#[allow(clippy::too_many_arguments)]
+pub fn create_texture_with_mipmaps(device : &wgpu::Device,
+                                   queue  : &wgpu::Queue,
+                                   loader : &TextureLoader,
+                                   sampler: &SamplerKey,
+                                   data   : &[u8],
+                                   width  : u32,
+                                   height : u32,
+                                   format : wgpu::TextureFormat,
+                                   label  : Option<&str>) -> Texture {
+    create_layered_texture(device, queue, loader, sampler, &[data], width, height, format, 
+                           wgpu::TextureViewDimension::D2, label)
+}
+
+/// Creates a sampled 2D texture from a pre-built mip chain (level 0 first). Block compressed
+/// levels are stored with their physical (block aligned) size.
+#[allow(clippy::too_many_arguments)]
+pub fn create_texture_from_levels(device : &wgpu::Device,
+                                  queue  : &wgpu::Queue,
+                                  loader : &TextureLoader,
+                                  sampler: &SamplerKey,
+                                  levels : &[Vec<u8>],
+                                  width  : u32,
+                                  height : u32,
+                                  format : wgpu::TextureFormat,
+                                  label  : Option<&str>) -> anyhow::Result<Texture> {
+    let (block_width, block_height) = format.block_dimensions();
+    if !width.is_multiple_of(block_width) || !height.is_multiple_of(block_height) {
+        anyhow::bail!("{}x{} is not a multiple of the {}x{} block size of {:?}",
+                      width, height, block_width, block_height, format);
+    }
+    let block_size = format.block_copy_size(None).unwrap_or(16);
+
+    let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+    let texture = device.create_texture(
+        &wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: levels.len() as u32,
+            sample_count   : 1,
+            dimension      : wgpu::TextureDimension::D2,
+            format,
+            usage          : wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats   : &[],
+        },
+    );
+
+    // ---> Uploaded level by level:
+    for (level, data) in levels.iter().enumerate() {
+        let level_size = size.mip_level_size(level as u32, wgpu::TextureDimension::D2)
+                             .physical_size(format);
+        let rows       = level_size.height / block_height;
+        let row_bytes  = level_size.width / block_width * block_size;
+
+        if data.len() < (row_bytes * rows) as usize {
+            anyhow::bail!("Mip level {} is truncated ({} of {} bytes)", level, data.len(), row_bytes * rows);
+        }
+
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                aspect   : wgpu::TextureAspect::All,
+                texture  : &texture,
+                mip_level: level as u32,
+                origin   : wgpu::Origin3d::ZERO,
+            },
+            data,
+            wgpu::TexelCopyBufferLayout {
+                offset        : 0,
+                bytes_per_row : Some(row_bytes),
+                rows_per_image: Some(rows),
+            },
+            level_size,
+        );
+    }
+
+    let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = loader.samplers.get(device, sampler);
+
+    Ok(Texture { texture, view, sampler })
+}
+
+/// Shared by 2D textures, texture arrays and cube maps: one level 0 image per layer, each layer
+/// gets its own mip chain. `dimension` decides how the default view sees the layers.
+#[allow(clippy::too_many_arguments)]
+fn create_layered_texture(device   : &wgpu::Device,
+                          queue    : &wgpu::Queue,
+                          loader   : &TextureLoader,
+                          sampler  : &SamplerKey,
+                          layers   : &[&[u8]],
+                          width    : u32,
+                          height   : u32,
+                          format   : wgpu::TextureFormat,
+                          dimension: wgpu::TextureViewDimension,
+                          label    : Option<&str>) -> Texture {
     let size = wgpu::Extent3d {
-        width                : rgba.width(),
-        height               : rgba.height(),
-        depth_or_array_layers: 1,
+        width,
+        height,
+        depth_or_array_layers: layers.len() as u32,
     };
 
+    // ---> Decide how (and whether) the mip chain can be built:
+    let (mipmaps, settings) = (&loader.mipmaps, &loader.settings);
+    let wants_mipmaps  = settings.generate_mipmaps && sampler.mipmapped;
+    let full_mip_count = if wants_mipmaps { mip_level_count(width, height) } else { 1 };
+    let gpu_mipmaps    = full_mip_count > 1 && mipmaps.supports_format(device, format);
+    let cpu_mipmaps    = full_mip_count > 1 && !gpu_mipmaps && supports_cpu_mipmaps(format);
+    let mip_count      = if gpu_mipmaps || cpu_mipmaps { full_mip_count } else { 1 };
+
+    let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+    if gpu_mipmaps {
+        usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
+
     let texture = device.create_texture(
         &wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count: mip_count,
             sample_count   : 1,
             dimension      : wgpu::TextureDimension::D2,
-            format         : wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
+            usage,
+            view_formats   : &[],
+        },
+    );
+
+    for (layer, data) in layers.iter().enumerate() {
+        write_mip_level(queue, &texture, 0, layer as u32, data, width, height);
+
+        if cpu_mipmaps {
+            for (level, level_data) in cpu_mip_chain(data, width, height, format).iter().enumerate().skip(1) {
+                let level_size = size.mip_level_size(level as u32, wgpu::TextureDimension::D2);
+                write_mip_level(queue, &texture, level as u32, layer as u32, level_data, 
+                                level_size.width, level_size.height);
+            }
+        }
+    }
+
+    if gpu_mipmaps {
+        mipmaps.generate(device, queue, &texture);
+    }
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(dimension),
+        ..Default::default()
+    });
+    let sampler = loader.samplers.get(device, sampler);
+
+    Texture { texture, view, sampler }
+}
+
+fn write_mip_level(queue    : &wgpu::Queue, 
+                   texture  : &wgpu::Texture, 
+                   mip_level: u32, 
+                   layer    : u32,
+                   data     : &[u8], 
+                   width    : u32, 
+                   height   : u32) {
+    let bytes_per_pixel = texture.format().block_copy_size(None).unwrap_or(4);
+
+    queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            aspect   : wgpu::TextureAspect::All,
+            texture,
+            mip_level,
+            origin   : wgpu::Origin3d { x: 0, y: 0, z: layer },
+        },
+        data,
+        wgpu::TexelCopyBufferLayout {
+            offset        : 0,
+            bytes_per_row : Some(bytes_per_pixel * width),
+            rows_per_image: Some(height),
+        },
+        wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+    );
+}
+///// TEXTURE LOADING PROCEDURE ////////////////////////////////////////////////////////////////////
+
+///// IMAGE FILE LOADING PROCEDURES ////////////////////////////////////////////////////////////////
+impl Texture {
+    /// Loads a PNG, JPEG, TGA, HDR, EXR or KTX2 file (format detected from the content).
+    pub fn from_path(path   : impl AsRef<Path>,
+                     device : &wgpu::Device,
+                     queue  : &wgpu::Queue,
+                     loader : &TextureLoader,
+                     options: &TextureOptions) -> anyhow::Result<Texture> {
+        let path  = path.as_ref();
+        let bytes = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read texture {}: {}", path.display(), e))?;
+
+        let format = image::ImageFormat::from_path(path).ok();
+        Self::from_encoded(&bytes, format, device, queue, loader, options, Some(&path.to_string_lossy()))
+            .map_err(|e| anyhow::anyhow!("Failed to load texture {}: {}", path.display(), e))
+    }
+
+    /// Decodes an encoded image file held in memory. HDR and EXR data is linear float data,
+    /// `options.srgb` only applies to integer images.
+    pub fn from_bytes(bytes  : &[u8],
+                      device : &wgpu::Device,
+                      queue  : &wgpu::Queue,
+                      loader : &TextureLoader,
+                      options: &TextureOptions,
+                      label  : Option<&str>) -> anyhow::Result<Texture> {
+        Self::from_encoded(bytes, None, device, queue, loader, options, label)
+    }
+
+    /// `format` comes from the file extension; without it the format is guessed from the
+    /// content, and data without any known signature is tried as TGA (which has none).
+    #[allow(clippy::too_many_arguments)]
+    fn from_encoded(bytes  : &[u8],
+                    format : Option<image::ImageFormat>,
+                    device : &wgpu::Device,
+                    queue  : &wgpu::Queue,
+                    loader : &TextureLoader,
+                    options: &TextureOptions,
+                    label  : Option<&str>) -> anyhow::Result<Texture> {
+        let sampler = SamplerKey {
+            mipmapped: options.generate_mipmaps && options.sampler.mipmapped,
+            ..options.sampler
+        };
+
+        if is_ktx2(bytes) {
+            return Ktx2Image::parse(bytes, device.features())?.with_srgb(options.srgb).upload(device, queue, loader, &sampler, label);
+        }
+
+        let format = match format {
+            Some(format) => format,
+            None         => image::guess_format(bytes).unwrap_or(image::ImageFormat::Tga),
+        };
+        let image = image::load_from_memory_with_format(bytes, format)?;
+        create_texture_from_image(&image, options.srgb, &sampler, device, queue, loader, label)
+    }
+}
+///// IMAGE FILE LOADING PROCEDURES ////////////////////////////////////////////////////////////////
+
+
+///// LAYERED TEXTURE CREATION PROCEDURES //////////////////////////////////////////////////////////
+/// Bytes per texel; layered textures are built from uncompressed images only.
+fn texel_size(format: wgpu::TextureFormat) -> anyhow::Result<u32> {
+    format.block_copy_size(None)
+          .filter(|_| format.block_dimensions() == (1, 1))
+          .ok_or_else(|| anyhow::anyhow!("Layered textures need an uncompressed format, got {:?}!", format))
+}
+
+/// Checks that every layer holds `width` x `height` texels of `format`.
+fn validate_layers(layers: &[&[u8]], width: u32, height: u32, format: wgpu::TextureFormat) -> anyhow::Result<()> {
+    if width == 0 || height == 0 {
+        anyhow::bail!("Texture size must not be zero ({}x{})!", width, height);
+    }
+
+    let expected = (width * height * texel_size(format)?) as usize;
+    for (layer, data) in layers.iter().enumerate() {
+        if data.len() != expected {
+            anyhow::bail!("Layer {} has {} bytes, expected {} ({}x{} {:?})!", 
+                          layer, data.len(), expected, width, height, format);
+        }
+    }
+
+    Ok(())
+}
+
+/// 2D texture array (terrain splat layers, sprite sheets...), viewed as `D2Array`.
+/// Six square layers are a cube map on GL (see `source_view_dimension`), avoid that count there.
+#[allow(clippy::too_many_arguments)]
+pub fn create_texture_array(device : &wgpu::Device,
+                            queue  : &wgpu::Queue,
+                            loader : &TextureLoader,
+                            sampler: &SamplerKey,
+                            layers : &[&[u8]],
+                            width  : u32,
+                            height : u32,
+                            format : wgpu::TextureFormat,
+                            label  : Option<&str>) -> anyhow::Result<Texture> {
+    if layers.is_empty() {
+        anyhow::bail!("A texture array needs at least one layer!");
+    }
+    validate_layers(layers, width, height, format)?;
+
+    Ok(create_layered_texture(device, queue, loader, sampler, layers, width, height, format, 
+                              wgpu::TextureViewDimension::D2Array, label))
+}
+
+/// Cube map from six square faces in +X, -X, +Y, -Y, +Z, -Z order, viewed as `Cube`.
+#[allow(clippy::too_many_arguments)]
+pub fn create_cube_texture(device : &wgpu::Device,
+                           queue  : &wgpu::Queue,
+                           loader : &TextureLoader,
+                           sampler: &SamplerKey,
+                           faces  : [&[u8]; 6],
+                           size   : u32,
+                           format : wgpu::TextureFormat,
+                           label  : Option<&str>) -> anyhow::Result<Texture> {
+    validate_layers(&faces, size, size, format)?;
+
+    Ok(create_layered_texture(device, queue, loader, sampler, &faces, size, size, format, 
+                              wgpu::TextureViewDimension::Cube, label))
+}
+
+/// Cube map with `face_size` faces projected from an equirectangular (latitude/longitude)
+/// `panorama` on the GPU. Faces are Rgba16Float so HDR panoramas keep their range.
+pub fn create_cube_texture_from_equirect(device   : &wgpu::Device,
+                                         queue    : &wgpu::Queue,
+                                         loader   : &TextureLoader,
+                                         sampler  : &SamplerKey,
+                                         panorama : &Texture,
+                                         face_size: u32,
+                                         label    : Option<&str>) -> anyhow::Result<Texture> {
+    if source_view_dimension(&panorama.texture) != wgpu::TextureViewDimension::D2 {
+        anyhow::bail!("An equirectangular panorama has to be a plain 2D texture!");
+    }
+    if face_size == 0 {
+        anyhow::bail!("Cube face size must not be zero!");
+    }
+
+    let format    = wgpu::TextureFormat::Rgba16Float;
+    let mip_count = if loader.settings.generate_mipmaps && sampler.mipmapped { mip_level_count(face_size, face_size) } else { 1 };
+
+    let texture = device.create_texture(
+        &wgpu::TextureDescriptor {
+            label,
+            size           : wgpu::Extent3d { width: face_size, height: face_size, depth_or_array_layers: 6 },
+            mip_level_count: mip_count,
+            sample_count   : 1,
+            dimension      : wgpu::TextureDimension::D2,
+            format,
+            usage          : wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats   : &[],
+        },
+    );
+
+    // ---> Only the base level of the panorama is read (whatever its own mip chain):
+    let source = panorama.texture.create_view(&wgpu::TextureViewDescriptor {
+        label          : Some("Equirect Source View"),
+        dimension      : Some(wgpu::TextureViewDimension::D2),
+        mip_level_count: Some(1),
+        ..Default::default()
+    });
+
+    let mut encoder = device.create_command_encoder(
+        &wgpu::CommandEncoderDescriptor { label: Some("Equirect Encoder") }
+    );
+    for face in 0..6 {
+        let target = texture.create_view(&wgpu::TextureViewDescriptor {
+            label            : Some("Cube Face View"),
+            dimension        : Some(wgpu::TextureViewDimension::D2),
+            mip_level_count  : Some(1),
+            base_array_layer : face,
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+        loader.mipmaps.project_equirect(device, &mut encoder, &source, face, &target, format);
+    }
+    queue.submit(Some(encoder.finish()));
+
+    if mip_count > 1 {
+        loader.mipmaps.generate(device, queue, &texture);
+    }
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    });
+    let sampler = loader.samplers.get(device, sampler);
+
+    Ok(Texture { texture, view, sampler })
+}
+
+/// 3D texture (volumes, colour grading LUTs), `depth` slices of `width` x `height` texels.
+/// Mip chains are not generated: the blit only downsamples in two dimensions.
+#[allow(clippy::too_many_arguments)]
+pub fn create_texture_3d(device : &wgpu::Device,
+                         queue  : &wgpu::Queue,
+                         loader : &TextureLoader,
+                         sampler: &SamplerKey,
+                         data   : &[u8],
+                         width  : u32,
+                         height : u32,
+                         depth  : u32,
+                         format : wgpu::TextureFormat,
+                         label  : Option<&str>) -> anyhow::Result<Texture> {
+    let texel_size = texel_size(format)?;
+    if depth == 0 || data.len() != (width * height * depth * texel_size) as usize {
+        anyhow::bail!("3D texture data has {} bytes, expected {}x{}x{} {:?} texels!", 
+                      data.len(), width, height, depth, format);
+    }
+    validate_layers(&[], width, height, format)?;
+
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: depth,
+    };
+
+    let texture = device.create_texture(
+        &wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count   : 1,
+            dimension      : wgpu::TextureDimension::D3,
+            format,
             usage          : wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats   : &[],
         },
@@ -96,30 +1152,22 @@ pub fn load_texture_from_image(image: &gltf::image::Data,
             mip_level: 0,
             origin   : wgpu::Origin3d::ZERO,
         },
-        &rgba,
+        data,
         wgpu::TexelCopyBufferLayout {
             offset        : 0,
-            bytes_per_row : Some(4 * rgba.width()),
-            rows_per_image: Some(rgba.height()),
+            bytes_per_row : Some(texel_size * width),
+            rows_per_image: Some(height),
         },
         size,
     );
 
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = device.create_sampler(
-        &wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter    : wgpu::FilterMode::Linear,
-            min_filter    : wgpu::FilterMode::Nearest,
-            ..Default::default()
-        },
-    );
+    let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = loader.samplers.get(device, &SamplerKey { mipmapped: false, ..*sampler });
 
     Ok(Texture { texture, view, sampler })
 }
-///// TEXTURE LOADING PROCEDURE ////////////////////////////////////////////////////////////////////
+///// LAYERED TEXTURE CREATION PROCEDURES //////////////////////////////////////////////////////////
+
 
 ///// DEPTH BUFFER CREATION PROCEDURE //////////////////////////////////////////////////////////////
 pub fn create_depth_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Texture {
@@ -209,3 +1257,460 @@ pub fn create_default_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> any
     Ok(Texture { texture, view, sampler })
 }
 ///// DEFAULT WHITE TEXTURE PROCEDURE //////////////////////////////////////////////////////////////
+
+
+///// RENDER TARGET CREATION PROCEDURE /////////////////////////////////////////////////////////////
+pub fn create_render_target(device: &wgpu::Device, 
+                            width : u32, 
+                            height: u32, 
+                            format: wgpu::TextureFormat, 
+                            label : Option<&str>) -> Texture {
+    let size = wgpu::Extent3d {
+        width                : width.max(1),
+        height               : height.max(1),
+        depth_or_array_layers: 1,
+    };
+
+    let texture = device.create_texture(
+        &wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count: 1,
+            sample_count   : 1,
+            dimension      : wgpu::TextureDimension::D2,
+            format,
+            usage          : wgpu::TextureUsages::RENDER_ATTACHMENT | 
+                             wgpu::TextureUsages::TEXTURE_BINDING   |
+                             wgpu::TextureUsages::COPY_SRC,  // For readback...
+            view_formats   : &[],
+        },
+    );
+
+    let view    = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(
+        &wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter    : wgpu::FilterMode::Nearest,
+            min_filter    : wgpu::FilterMode::Nearest,
+            ..Default::default()
+        },
+    );
+
+    Texture { texture, view, sampler }
+}
+///// RENDER TARGET CREATION PROCEDURE /////////////////////////////////////////////////////////////
+
+
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu::GPU;
+
+    /// Uploads a 1x1 image for `role` and returns the texel as the shader sees it (sampled and 
+    /// written to a linear target).
+    fn sample_on_gpu(format: gltf::image::Format, pixels: Vec<u8>, role: TextureRole) -> [u8; 4] {
+        let gpu      = GPU::for_tests(1, 1);
+        let loader   = TextureLoader::new(&gpu.device, &gpu.shaders, TextureSettings { generate_mipmaps: false, anisotropy: 1, streaming: None });
+        let image    = gltf::image::Data { format, width: 1, height: 1, pixels };
+
+        let texture = load_texture_from_image(&image, role, &SamplerKey::default(), &gpu.device, 
+                                              &gpu.queue, &loader, Some("Test")).unwrap();
+
+        read_back(&gpu, &loader, &texture)
+    }
+
+    /// Level 0 texel at the centre as the shader sees it (written to a linear 1x1 target).
+    fn read_back(gpu: &GPU, loader: &TextureLoader, texture: &Texture) -> [u8; 4] {
+        let target = create_render_target(&gpu.device, 1, 1, wgpu::TextureFormat::Rgba8Unorm, 
+                                          Some("Test Target"));
+
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        loader.mipmaps.blit(&gpu.device, &mut encoder, &texture.view, &target.view, target.texture.format());
+        gpu.queue.submit(Some(encoder.finish()));
+
+        gpu.read_texture(&target.texture).unwrap().get_pixel(0, 0).0
+    }
+
+    fn assert_close(actual: [u8; 4], expected: [u8; 4]) {
+        let close = actual.iter().zip(expected).all(|(a, e)| (*a as i32 - e as i32).abs() <= 1);
+        assert!(close, "decoded {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn colour_roles_are_srgb() {
+        assert!( TextureRole::BaseColor.is_srgb());
+        assert!( TextureRole::Emissive.is_srgb());
+        assert!(!TextureRole::Normal.is_srgb());
+        assert!(!TextureRole::Occlusion.is_srgb());
+        assert!(!TextureRole::MetallicRoughness.is_srgb());
+        assert!( TextureRole::SheenColor.is_srgb());
+        assert!( TextureRole::SpecularColor.is_srgb());
+        assert!(!TextureRole::Clearcoat.is_srgb());
+        assert!(!TextureRole::ClearcoatNormal.is_srgb());
+        assert!(!TextureRole::Transmission.is_srgb());
+    }
+
+    #[test]
+    fn gltf_samplers_are_mapped() {
+        let document = gltf::Gltf::from_slice(br#"{
+            "asset"   : { "version": "2.0" },
+            "samplers": [
+                { "magFilter": 9728, "minFilter": 9729, "wrapS": 33071, "wrapT": 33648 },
+                { "minFilter": 9986 },
+                {}
+            ]
+        }"#).unwrap();
+        let keys = document.samplers().map(|sampler| SamplerKey::from_gltf(&sampler)).collect::<Vec<_>>();
+
+        assert_eq!(keys[0].address_mode_u, wgpu::AddressMode::ClampToEdge);
+        assert_eq!(keys[0].address_mode_v, wgpu::AddressMode::MirrorRepeat);
+        assert_eq!(keys[0].mag_filter,     wgpu::FilterMode::Nearest);
+        assert_eq!(keys[0].min_filter,     wgpu::FilterMode::Linear);
+        assert!(!keys[0].mipmapped);
+
+        assert_eq!(keys[1].min_filter,    wgpu::FilterMode::Nearest);
+        assert_eq!(keys[1].mipmap_filter, wgpu::FilterMode::Linear);
+        assert_eq!(keys[1].address_mode_u, wgpu::AddressMode::Repeat);
+
+        assert_eq!(keys[2], SamplerKey::default());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn colour_textures_are_decoded_to_linear() {
+        // ---> sRGB 128 is linear 0.216 (55), alpha is always linear:
+        for role in [TextureRole::BaseColor, TextureRole::Emissive] {
+            let texel = sample_on_gpu(gltf::image::Format::R8G8B8A8, vec![128, 128, 128, 128], role);
+            assert_close(texel, [55, 55, 55, 128]);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn data_textures_are_not_decoded() {
+        for role in [TextureRole::Normal, TextureRole::Occlusion, TextureRole::MetallicRoughness] {
+            let texel = sample_on_gpu(gltf::image::Format::R8G8B8A8, vec![128, 128, 255, 255], role);
+            assert_close(texel, [128, 128, 255, 255]);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn high_precision_colour_textures_are_linearized() {
+        let pixels: Vec<u8> = bytemuck::cast_slice(&[0x8080u16, 0x8080, 0x8080, 0xFFFF]).to_vec();
+
+        let texel = sample_on_gpu(gltf::image::Format::R16G16B16A16, pixels.clone(), TextureRole::BaseColor);
+        assert_close(texel, [55, 55, 55, 255]);
+
+        let texel = sample_on_gpu(gltf::image::Format::R16G16B16A16, pixels, TextureRole::Normal);
+        assert_close(texel, [128, 128, 128, 255]);
+    }
+
+    fn float_texels(values: &[f32]) -> Vec<u8> {
+        bytemuck::cast_slice(values).to_vec()
+    }
+
+    fn mean(texels: &[f32]) -> [f32; 4] {
+        let count = (texels.len() / 4) as f32;
+        [0, 1, 2, 3].map(|channel| texels.iter().skip(channel).step_by(4).sum::<f32>() / count)
+    }
+
+    #[test]
+    fn cpu_mip_chains_cover_odd_and_non_power_of_two_sizes() {
+        let format = wgpu::TextureFormat::Rgba32Float;
+        for (width, height, sizes) in [(5, 3, vec![(5, 3), (2, 1), (1, 1)]),
+                                       (6, 10, vec![(6, 10), (3, 5), (1, 2), (1, 1)]),
+                                       (1, 7, vec![(1, 7), (1, 3), (1, 1)])] {
+            let texels = (0..width * height * 4).map(|i| (i * 7 % 13) as f32).collect::<Vec<_>>();
+            let levels = cpu_mip_chain(&float_texels(&texels), width, height, format);
+            assert_eq!(levels.len(), sizes.len());
+
+            for (level, (level_width, level_height)) in levels.iter().zip(sizes) {
+                assert_eq!(level.len(), (level_width * level_height * 16) as usize);
+
+                // ---> Every source texel contributes (with equal weight), so the mean is kept:
+                let level_mean = mean(bytemuck::cast_slice(level));
+                for (actual, expected) in level_mean.iter().zip(mean(&texels)) {
+                    assert!((actual - expected).abs() < 1e-4, "{}x{}: {} vs {}", 
+                            level_width, level_height, actual, expected);
+                }
+            }
+        }
+
+        // ---> Last column of a 3x1 row is not dropped (1/3 each):
+        let (level, width, height) = downsample_cpu(&float_texels(&[0.0, 0.0, 0.0, 0.0,
+                                                                    0.0, 0.0, 0.0, 0.0,
+                                                                    3.0, 3.0, 3.0, 3.0]), 3, 1, format);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&level), [1.0; 4]);
+    }
+
+    #[test]
+    fn cpu_mipmaps_average_srgb_in_linear_space() {
+        let black_and_white = [0, 0, 0, 0, 255, 255, 255, 255];
+
+        // ---> Linear 0.5 is sRGB 188, alpha is averaged linearly:
+        let (srgb, ..) = downsample_cpu(&black_and_white, 2, 1, wgpu::TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(srgb, [188, 188, 188, 128]);
+
+        let (linear, ..) = downsample_cpu(&black_and_white, 2, 1, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!(linear, [128, 128, 128, 128]);
+
+        let words: Vec<u8> = bytemuck::cast_slice(&[0u16, 0, 0, 0, 65535, 65535, 65535, 65535]).to_vec();
+        let (unorm16, ..) = downsample_cpu(&words, 2, 1, wgpu::TextureFormat::Rgba16Unorm);
+        assert_eq!(bytemuck::pod_collect_to_vec::<u8, u16>(&unorm16), [32768; 4]);
+    }
+
+    #[test]
+    fn eight_bit_images_keep_their_bytes() {
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_raw(1, 1, vec![128, 64, 32]).unwrap());
+
+        assert_eq!(prepare_image(&image, true,  wgpu::Features::all(), None),
+                   (vec![128, 64, 32, 255], wgpu::TextureFormat::Rgba8UnormSrgb));
+        assert_eq!(prepare_image(&image, false, wgpu::Features::empty(), None),
+                   (vec![128, 64, 32, 255], wgpu::TextureFormat::Rgba8Unorm));
+    }
+
+    #[test]
+    fn sixteen_bit_images_map_to_unorm16_or_fall_back() {
+        let image = image::DynamicImage::ImageRgba16(
+            image::ImageBuffer::from_raw(1, 1, vec![0x8080u16, 0xFFFF, 0, 0x8080]).unwrap()
+        );
+        let unorm16 = wgpu::Features::TEXTURE_FORMAT_16BIT_NORM;
+        let float32 = wgpu::Features::FLOAT32_FILTERABLE;
+
+        // ---> Data textures keep their values exactly:
+        let (data, format) = prepare_image(&image, false, unorm16 | float32, None);
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Unorm);
+        assert_eq!(bytemuck::pod_collect_to_vec::<u8, u16>(&data), [0x8080, 0xFFFF, 0, 0x8080]);
+
+        // ---> Colour textures are linearized (there is no sRGB 16 bit format), alpha is not:
+        let (data, format) = prepare_image(&image, true, unorm16, None);
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Unorm);
+        let texels = bytemuck::pod_collect_to_vec::<u8, u16>(&data);
+        assert!((texels[0] as f32 / 65535.0 - srgb_to_linear(0x8080 as f32 / 65535.0)).abs() < 1e-4);
+        assert_eq!(&texels[1..], [0xFFFF, 0, 0x8080]);
+
+        // ---> Without 16 bit norm formats: lossless Rgba32Float, Rgba16Float as the last resort:
+        let (data, format) = prepare_image(&image, false, float32, None);
+        assert_eq!(format, wgpu::TextureFormat::Rgba32Float);
+        assert_eq!(bytemuck::pod_collect_to_vec::<u8, f32>(&data)[3], 0x8080 as f32 / 65535.0);
+
+        let (data, format) = prepare_image(&image, false, wgpu::Features::empty(), None);
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Float);
+        assert_eq!(data.len(), 8);
+        assert_eq!(half::f16::from_le_bytes([data[2], data[3]]).to_f32(), 1.0);
+    }
+
+    #[test]
+    fn float_images_map_to_float32_or_fall_back() {
+        let image = image::DynamicImage::ImageRgb32F(
+            image::ImageBuffer::from_raw(1, 1, vec![4.5f32, 0.25, 0.0]).unwrap()
+        );
+
+        // ---> Linear HDR data, `srgb` does not change it:
+        let (data, format) = prepare_image(&image, true, wgpu::Features::FLOAT32_FILTERABLE, None);
+        assert_eq!(format, wgpu::TextureFormat::Rgba32Float);
+        assert_eq!(bytemuck::pod_collect_to_vec::<u8, f32>(&data), [4.5, 0.25, 0.0, 1.0]);
+
+        // ---> Rgba16Float keeps the HDR range where Rgba32Float is not filterable:
+        let (data, format) = prepare_image(&image, false, wgpu::Features::TEXTURE_FORMAT_16BIT_NORM, None);
+        assert_eq!(format, wgpu::TextureFormat::Rgba16Float);
+        let halves = data.chunks_exact(2).map(|c| half::f16::from_le_bytes([c[0], c[1]]).to_f32()).collect::<Vec<_>>();
+        assert_eq!(halves, [4.5, 0.25, 0.0, 1.0]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn equirect_is_projected_onto_cube_faces() {
+        let gpu    = GPU::for_tests(1, 1);
+        let loader = TextureLoader::new(&gpu.device, &gpu.shaders, TextureSettings::default());
+
+        // ---> Red is the longitude (u), green the latitude (v) of every texel:
+        let (width, height) = (64, 32);
+        let panorama = image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([((x as f32 + 0.5) / width as f32 * 255.0) as u8, ((y as f32 + 0.5) / height as f32 * 255.0) as u8, 0, 255])
+        });
+        let options  = TextureOptions { srgb: false, generate_mipmaps: false, sampler: SamplerKey::clamped() };
+        let panorama = create_texture_from_image(&image::DynamicImage::ImageRgba8(panorama), options.srgb, &options.sampler,
+                                                 &gpu.device, &gpu.queue, &loader, None).unwrap();
+        let cube     = create_cube_texture_from_equirect(&gpu.device, &gpu.queue, &loader, &SamplerKey::clamped(), 
+                                                         &panorama, 8, Some("Sky")).unwrap();
+        assert_eq!(cube.texture.depth_or_array_layers(), 6);
+        assert_eq!(cube.texture.mip_level_count(), 4);
+
+        // ---> Longitude and latitude seen through the centre of every face:
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+        let target = create_render_target(&gpu.device, 1, 1, format, Some("Test Target"));
+        let source = cube.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension      : Some(wgpu::TextureViewDimension::Cube),
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        let centre = |face: u32| {
+            let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            loader.mipmaps.blit_layer(&gpu.device, &mut encoder, &source, wgpu::TextureViewDimension::Cube, face, 
+                                      &target.view, format);
+            gpu.queue.submit(Some(encoder.finish()));
+            let pixel = gpu.read_texture(&target.texture).unwrap().get_pixel(0, 0).0;
+            [pixel[0] as f32 / 255.0, pixel[1] as f32 / 255.0]
+        };
+        let close = |actual: f32, expected: f32| (actual - expected).abs() < 0.05;
+
+        let [u, v] = centre(0); assert!(close(u, 0.75) && close(v, 0.5), "+X: {u} {v}");
+        let [u, v] = centre(1); assert!(close(u, 0.25) && close(v, 0.5), "-X: {u} {v}");
+        let [u, v] = centre(5); assert!(close(u, 0.5)  && close(v, 0.5), "-Z: {u} {v}");
+        assert!(centre(2)[1] < 0.1 && centre(3)[1] > 0.9);  // +Y, -Y
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn layered_textures_keep_their_layers() {
+        let gpu = GPU::for_tests(1, 1);
+        let loader = TextureLoader::new(&gpu.device, &gpu.shaders, TextureSettings::default());
+        let format = wgpu::TextureFormat::Rgba8Unorm;
+
+        let colours = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], 
+                       [255, 255, 0, 255], [0, 255, 255, 255], [255, 0, 255, 255]];
+        let faces   = colours.map(|colour: [u8; 4]| colour.repeat(4 * 4));
+        let cube    = create_cube_texture(&gpu.device, &gpu.queue, &loader, &SamplerKey::clamped(), 
+                                          [0, 1, 2, 3, 4, 5].map(|face| faces[face].as_slice()), 
+                                          4, format, Some("Cube")).unwrap();
+        assert_eq!(cube.texture.depth_or_array_layers(), 6);
+        assert_eq!(cube.texture.mip_level_count(), 3);
+
+        // ---> Every face got its own mip chain:
+        let target = create_render_target(&gpu.device, 1, 1, format, Some("Test Target"));
+        let source = cube.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension      : Some(wgpu::TextureViewDimension::Cube),
+            base_mip_level : 2,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        for (face, colour) in colours.iter().enumerate() {
+            let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            loader.mipmaps.blit_layer(&gpu.device, &mut encoder, &source, wgpu::TextureViewDimension::Cube, 
+                                      face as u32, &target.view, format);
+            gpu.queue.submit(Some(encoder.finish()));
+
+            assert_close(gpu.read_texture(&target.texture).unwrap().get_pixel(0, 0).0, *colour);
+        }
+        assert!(binds_as(&gpu, &cube.view, wgpu::TextureViewDimension::Cube));
+
+        let layers = [faces[0].as_slice(), faces[1].as_slice(), faces[2].as_slice()];
+        let array  = create_texture_array(&gpu.device, &gpu.queue, &loader, &SamplerKey::default(), 
+                                          &layers, 4, 4, format, Some("Array")).unwrap();
+        assert_eq!(array.texture.depth_or_array_layers(), 3);
+        assert!(binds_as(&gpu, &array.view, wgpu::TextureViewDimension::D2Array));
+        assert!(!binds_as(&gpu, &array.view, wgpu::TextureViewDimension::D2));
+
+        let source = array.texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension      : Some(wgpu::TextureViewDimension::D2Array),
+            base_mip_level : 1,
+            mip_level_count: Some(1),
+            ..Default::default()
+        });
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        loader.mipmaps.blit_layer(&gpu.device, &mut encoder, &source, wgpu::TextureViewDimension::D2Array, 
+                                  2, &target.view, format);
+        gpu.queue.submit(Some(encoder.finish()));
+        assert_close(gpu.read_texture(&target.texture).unwrap().get_pixel(0, 0).0, colours[2]);
+        assert!(create_texture_array(&gpu.device, &gpu.queue, &loader, &SamplerKey::default(), 
+                                     &layers, 4, 2, format, Some("Array")).is_err());
+
+        let volume = create_texture_3d(&gpu.device, &gpu.queue, &loader, &SamplerKey::clamped(), 
+                                       &faces.concat()[..4 * 4 * 4 * 4], 4, 4, 4, format, 
+                                       Some("Volume")).unwrap();
+        assert_eq!(volume.texture.dimension(), wgpu::TextureDimension::D3);
+        assert_eq!(volume.texture.depth_or_array_layers(), 4);
+        assert!(binds_as(&gpu, &volume.view, wgpu::TextureViewDimension::D3));
+        assert!(create_texture_3d(&gpu.device, &gpu.queue, &loader, &SamplerKey::clamped(), 
+                                  &faces[0], 4, 4, 4, format, Some("Volume")).is_err());
+    }
+
+    /// Can `view` be bound where a shader expects a texture of `dimension`?
+    fn binds_as(gpu: &GPU, view: &wgpu::TextureView, dimension: wgpu::TextureViewDimension) -> bool {
+        let layout = gpu.device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label  : Some("Test Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding   : 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty        : wgpu::BindingType::Texture {
+                    sample_type   : wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: dimension,
+                    multisampled  : false,
+                },
+                count     : None,
+            }],
+        });
+
+        gpu.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let _bind_group = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label  : Some("Test Bind Group"),
+            layout : &layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(view) }],
+        });
+        pollster::block_on(gpu.device.pop_error_scope()).is_none()
+    }
+
+    fn encode(image: image::DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format).unwrap();
+        bytes.into_inner()
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn image_files_use_the_requested_colour_space() {
+        let gpu = GPU::for_tests(1, 1);
+        let loader = TextureLoader::new(&gpu.device, &gpu.shaders, TextureSettings::default());
+        let pixel  = image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 128, 255]));
+
+        for format in [image::ImageFormat::Png, image::ImageFormat::Tga] {
+            let bytes = encode(pixel.clone().into(), format);
+
+            let srgb = Texture::from_bytes(&bytes, &gpu.device, &gpu.queue, &loader, 
+                                           &TextureOptions::default(), None).unwrap();
+            assert_eq!(srgb.texture.format(), wgpu::TextureFormat::Rgba8UnormSrgb);
+            assert_close(read_back(&gpu, &loader, &srgb), [55, 55, 55, 255]);
+
+            let linear = Texture::from_bytes(&bytes, &gpu.device, &gpu.queue, &loader, 
+                                             &TextureOptions { srgb: false, ..Default::default() }, None).unwrap();
+            assert_eq!(linear.texture.format(), wgpu::TextureFormat::Rgba8Unorm);
+            assert_close(read_back(&gpu, &loader, &linear), [128, 128, 128, 255]);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn float_image_files_keep_their_range() {
+        let gpu = GPU::for_tests(1, 1);
+        let loader = TextureLoader::new(&gpu.device, &gpu.shaders, TextureSettings::default());
+        let pixel  = image::Rgb32FImage::from_pixel(4, 4, image::Rgb([4.0, 0.5, 0.25]));
+
+        for format in [image::ImageFormat::Hdr, image::ImageFormat::OpenExr] {
+            let bytes   = encode(pixel.clone().into(), format);
+            let options = TextureOptions { generate_mipmaps: false, ..Default::default() };
+            let texture = Texture::from_bytes(&bytes, &gpu.device, &gpu.queue, &loader, &options, None).unwrap();
+
+            assert!(matches!(texture.texture.format(), wgpu::TextureFormat::Rgba32Float | 
+                                                       wgpu::TextureFormat::Rgba16Float));
+            assert_eq!(texture.texture.mip_level_count(), 1);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+    fn missing_image_files_name_the_path() {
+        let gpu = GPU::for_tests(1, 1);
+        let loader = TextureLoader::new(&gpu.device, &gpu.shaders, TextureSettings::default());
+
+        let error = Texture::from_path("missing/albedo.png", &gpu.device, &gpu.queue, &loader, 
+                                       &TextureOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("missing/albedo.png"));
+    }
+}
+///// TESTS ////////////////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,205 @@
+/*
+
+    Scene colour for transmissive materials (KHR_materials_transmission).
+
+    Transmissive surfaces show the opaque scene behind them. When a model has any, the opaque
+    passes render into a transient "Scene Colour" target instead of the frame; it is copied into
+    the frame and the transparent pass draws on top, sampling the copy. Without transmissive
+    meshes everything renders straight into the frame and a 1x1 fallback is bound.
+
+    The forward pipelines get the light and the scene colour together as @group(3), wgpu only
+    guarantees four bind groups.
+
+*/
+
+use nalgebra_glm as glm;
+
+use crate::model::Model;
+use crate::pipeline::PipelineCache;
+use crate::render_graph::RenderGraph;
+use crate::render_graph::ResourceHandle;
+use crate::render_graph::TextureDesc;
+use crate::texture::MipmapGenerator;
+use crate::texture::Texture;
+use crate::texture::create_render_target;
+
+
+///// SCENE COLOUR STRUCTURE ///////////////////////////////////////////////////////////////////////
+pub struct SceneColor {
+    pub bind_group_layout : wgpu::BindGroupLayout,
+    pub default_bind_group: wgpu::BindGroup,  // Light + fallback texture
+    light_buffer          : wgpu::Buffer,
+    sampler               : wgpu::Sampler,
+    format                : wgpu::TextureFormat,
+    _fallback             : Texture,
+}
+
+impl SceneColor {
+    pub fn new(device: &wgpu::Device, light_buffer: &wgpu::Buffer, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor {
+                label  : Some("Scene Colour Bind Group Layout"),
+                entries: &[
+                    // Light:
+                    wgpu::BindGroupLayoutEntry {
+                        binding   : 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty        : wgpu::BindingType::Buffer {
+                            ty                : wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size  : None,
+                        },
+                        count     : None,
+                    },
+                    // Opaque scene colour:
+                    wgpu::BindGroupLayoutEntry {
+                        binding   : 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty        : wgpu::BindingType::Texture {
+                            sample_type   : wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled  : false,
+                        },
+                        count     : None,
+                    },
+                    // Scene colour sampler:
+                    wgpu::BindGroupLayoutEntry {
+                        binding   : 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty        : wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count     : None,
+                    },
+                ],
+            },
+        );
+
+        let sampler = device.create_sampler(
+            &wgpu::SamplerDescriptor {
+                label         : Some("Scene Colour Sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter    : wgpu::FilterMode::Linear,
+                min_filter    : wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        );
+
+        let fallback           = create_render_target(device, 1, 1, format, Some("Scene Colour Fallback"));
+        let default_bind_group = Self::create_bind_group(device, &bind_group_layout, light_buffer,
+                                                         &fallback.view, &sampler);
+
+        Self {
+            bind_group_layout,
+            default_bind_group,
+            light_buffer: light_buffer.clone(),
+            sampler,
+            format,
+            _fallback   : fallback,
+        }
+    }
+
+    /// Transient target the opaque passes render into when the model has transmissive meshes.
+    pub fn declare(&self, graph: &mut RenderGraph, width: u32, height: u32) -> ResourceHandle {
+        graph.create_texture("Scene Colour", TextureDesc::render_target(width, height, self.format))
+    }
+
+    /// Copies `scene_color` (if any) into the frame, then draws the blended and transmissive
+    /// meshes of `model` over it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_transparent_passes<'a>(&'a self,
+                                      graph            : &mut RenderGraph<'a>,
+                                      device           : &'a wgpu::Device,
+                                      mipmaps          : &'a MipmapGenerator,
+                                      scene_color      : Option<ResourceHandle>,
+                                      frame            : ResourceHandle,
+                                      depth            : ResourceHandle,
+                                      camera_bind_group: &'a wgpu::BindGroup,
+                                      model_bind_group : &'a wgpu::BindGroup,
+                                      pipelines        : &'a PipelineCache,
+                                      eye              : glm::Vec3,
+                                      model            : Option<&'a Model>) {
+        // ---> Opaque result into the frame (the scene colour stays readable):
+        if let Some(scene_color) = scene_color {
+            let format = self.format;
+            graph.add_pass("Scene Colour Copy", |builder| {
+                builder.read(scene_color);
+                builder.write(frame);
+            }, move |encoder, resources| {
+                mipmaps.blit(device, encoder, resources.texture_view(scene_color),
+                             resources.texture_view(frame), format);
+            });
+        }
+
+        // ---> Transparent pass (forward shaded over the opaque frame):
+        graph.add_pass("Transparent Pass", |builder| {
+            if let Some(scene_color) = scene_color {
+                builder.read(scene_color);
+            }
+            builder.read(depth);
+            builder.write(frame);
+        }, move |encoder, resources| {
+            let bind_group = scene_color.map(|scene_color| {
+                Self::create_bind_group(device, &self.bind_group_layout, &self.light_buffer,
+                                        resources.texture_view(scene_color), &self.sampler)
+            });
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label                   : Some("Transparent Pass"),
+                color_attachments       : &[Some(wgpu::RenderPassColorAttachment {
+                    view          : resources.texture_view(frame),
+                    resolve_target: None,
+                    ops           : wgpu::Operations {
+                        load : wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view       : resources.texture_view(depth),
+                    depth_ops  : Some(wgpu::Operations {
+                        load : wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes        : None,
+                occlusion_query_set     : None,
+            });
+
+            render_pass.set_bind_group(0, camera_bind_group, &[]);
+            render_pass.set_bind_group(1, model_bind_group, &[]);
+            render_pass.set_bind_group(3, bind_group.as_ref().unwrap_or(&self.default_bind_group), &[]);
+
+            if let Some(model) = model {
+                model.draw_transparent(&mut render_pass, pipelines, &eye);
+            }
+        });
+    }
+
+    fn create_bind_group(device      : &wgpu::Device,
+                         layout      : &wgpu::BindGroupLayout,
+                         light_buffer: &wgpu::Buffer,
+                         view        : &wgpu::TextureView,
+                         sampler     : &wgpu::Sampler) -> wgpu::BindGroup {
+        device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                label  : Some("Scene Colour Bind Group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding : 0,
+                        resource: light_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding : 1,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding : 2,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                ],
+            },
+        )
+    }
+}
+///// SCENE COLOUR STRUCTURE ///////////////////////////////////////////////////////////////////////
@@ -4,11 +4,13 @@
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
-    pub position  : [f32; 3],  // @location(0)
-    pub normal    : [f32; 3],  // @location(1)
-    pub tex_coords: [f32; 2],  // @location(2)
-    pub tangent   : [f32; 3],  // @location(3)
-    pub bitangent : [f32; 3],  // @location(4)
+    pub position    : [f32; 3],  // @location(0)
+    pub normal      : [f32; 3],  // @location(1)
+    pub tex_coords  : [f32; 2],  // @location(2)
+    pub tangent     : [f32; 3],  // @location(3)
+    pub bitangent   : [f32; 3],  // @location(4)
+    pub tex_coords_1: [f32; 2],  // @location(5), second UV set (TEXCOORD_1, e.g. lightmaps)
+    pub color       : [f32; 4],  // @location(6), COLOR_0 (linear RGBA, white without it)
 }
 
 impl Vertex {
@@ -42,6 +44,16 @@ impl Vertex {
                     shader_location: 4,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute { // Second texture coordinates
+                    offset: 56,  // 44 + 4Bytes x 3
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute { // Vertex colour
+                    offset: 64,  // 56 + 4Bytes x 2
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ], 
         }
     }
@@ -0,0 +1,41 @@
+// Runs the golden-image harness (`SealEngine --golden tests/golden`), which renders on a headless
+// adapter and therefore only runs with `cargo test -- --ignored`.
+use std::process::Command;
+
+#[test]
+#[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+fn golden_images_match() {
+    let output = Command::new(env!("CARGO_BIN_EXE_SealEngine"))
+        .args(["--golden", "tests/golden"])
+        .output()
+        .expect("Failed to start SealEngine");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    print!("{}", stdout);
+
+    assert!(output.status.success(), "Golden image mismatch:\n{}{}", stdout, stderr);
+}
+
+#[test]
+#[ignore = "needs a GPU adapter, run with `cargo test -- --ignored`"]
+fn missing_reference_fails_without_update() {
+    let golden_dir = std::env::temp_dir().join(format!("seal_golden_{}", std::process::id()));
+    let scene      = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/scenes/cube.gltf");
+    std::fs::create_dir_all(&golden_dir).unwrap();
+    std::fs::write(golden_dir.join("manifest.txt"),
+                   format!("cube {} 32x32 3,2.5,5,0,0,0\n", scene.display())).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_SealEngine"))
+        .arg("--golden")
+        .arg(&golden_dir)
+        .output()
+        .expect("Failed to start SealEngine");
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    let reference_written = golden_dir.join("reference/cube.png").exists();
+    let _ = std::fs::remove_dir_all(&golden_dir);
+
+    assert!(!output.status.success(), "Missing reference passed:\n{}", stdout);
+    assert!(stdout.contains("FAILED (missing reference"), "{}", stdout);
+    assert!(!reference_written);
+}